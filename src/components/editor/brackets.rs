@@ -0,0 +1,171 @@
+//! Bracket matching
+//!
+//! Finds the partner of a bracket, across lines. For languages with known
+//! strings and comments, brackets inside them are skipped; plain text
+//! matches every bracket.
+
+use super::{buffer::TextBuffer, language::Language};
+
+/// The bracket pairs that are matched, as `(open, close)`.
+pub const BRACKET_PAIRS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
+
+/// How far from a bracket to look, in bytes.
+///
+/// Strings and comments are found by scanning from the start of the
+/// document, or from this far before the bracket in a very large one.
+const SCAN_LIMIT: usize = 256 * 1024;
+
+/// A bracket next to a cursor and the bracket it pairs with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BracketMatch {
+    /// Byte offset of the bracket next to the cursor
+    pub bracket: usize,
+    /// Byte offset of its partner
+    pub partner: usize,
+}
+
+/// Find the partner of the bracket at `offset`.
+///
+/// Returns `None` if there is no bracket at `offset`, it is inside a
+/// string or comment, or it is unmatched.
+#[must_use]
+pub fn find_partner(buffer: &TextBuffer, language: Language, offset: usize) -> Option<usize> {
+    let bracket = buffer.char_at(offset)?;
+    let &(open, close) = BRACKET_PAIRS
+        .iter()
+        .find(|&&(open, close)| bracket == open || bracket == close)?;
+    let start = buffer.line_start(buffer.line_of_offset(offset.saturating_sub(SCAN_LIMIT)))?;
+    let end = buffer.len().min(offset + SCAN_LIMIT);
+    let text = buffer.slice(start..end)?;
+
+    // Unmatched opening brackets before `offset`, then the nesting depth
+    // after an opening bracket at `offset`
+    let mut opens = Vec::new();
+    let mut depth: Option<usize> = None;
+    for (pos, c) in code_brackets(&text, language) {
+        let pos = start + pos;
+        match depth {
+            None if pos == offset && c == open => depth = Some(0),
+            None if pos == offset => return opens.pop(),
+            None if pos > offset => return None,
+            None if c == open => opens.push(pos),
+            None if c == close => {
+                opens.pop();
+            }
+            Some(d) if c == open => depth = Some(d + 1),
+            Some(0) if c == close => return Some(pos),
+            Some(d) if c == close => depth = Some(d - 1),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Find the bracket next to a cursor at `offset` and its partner.
+///
+/// The bracket after the cursor is preferred over the one before it.
+#[must_use]
+pub fn match_at(buffer: &TextBuffer, language: Language, offset: usize) -> Option<BracketMatch> {
+    let before = (offset > 0).then(|| buffer.prev_char_boundary(offset));
+    [Some(offset), before]
+        .into_iter()
+        .flatten()
+        .find_map(|bracket| {
+            let partner = find_partner(buffer, language, bracket)?;
+            Some(BracketMatch { bracket, partner })
+        })
+}
+
+/// Get the brackets in `text` that are outside strings and comments, with
+/// their byte offsets.
+fn code_brackets(text: &str, language: Language) -> Vec<(usize, char)> {
+    let line_comment = language.line_comment();
+    let block_comment = language.block_comment();
+    let quotes = language.string_quotes();
+
+    let mut brackets = Vec::new();
+    let mut i = 0;
+    while let Some(c) = text[i..].chars().next() {
+        let rest = &text[i..];
+        if line_comment.is_some_and(|token| rest.starts_with(token)) {
+            i += rest.find('\n').unwrap_or(rest.len());
+        } else if let Some((open, close)) = block_comment.filter(|(open, _)| rest.starts_with(open))
+        {
+            i += rest[open.len()..]
+                .find(close)
+                .map_or(rest.len(), |end| open.len() + end + close.len());
+        } else if quotes.contains(&c)
+            && !(c == '\'' && language == Language::Rust && is_lifetime(rest))
+        {
+            i += string_len(rest, c, language);
+        } else {
+            if BRACKET_PAIRS
+                .iter()
+                .any(|&(open, close)| c == open || c == close)
+            {
+                brackets.push((i, c));
+            }
+            i += c.len_utf8();
+        }
+    }
+    brackets
+}
+
+/// Get the length of the string literal at the start of `text`.
+///
+/// A string ends at its closing quote. Except for backtick strings and in
+/// Rust, it also ends at the end of the line, so a stray quote does not
+/// hide the rest of the document.
+fn string_len(text: &str, quote: char, language: Language) -> usize {
+    let multi_line = quote == '`' || language == Language::Rust;
+    let mut chars = text.char_indices().skip(1);
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '\n' if !multi_line => return i,
+            c if c == quote => return i + c.len_utf8(),
+            _ => {}
+        }
+    }
+    text.len()
+}
+
+/// Check if the `'` at the start of `text` starts a Rust lifetime or label
+/// rather than a char literal.
+fn is_lifetime(text: &str) -> bool {
+    let mut chars = text.chars().skip(1);
+    !matches!(
+        (chars.next(), chars.next()),
+        (Some('\\'), _) | (Some(_), Some('\''))
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_partner_across_lines() {
+        let buffer = TextBuffer::from("fn f() {\n    g([1], 2);\n}");
+        assert_eq!(find_partner(&buffer, Language::Rust, 7), Some(24));
+        assert_eq!(find_partner(&buffer, Language::Rust, 24), Some(7));
+        assert_eq!(find_partner(&buffer, Language::Rust, 15), Some(17));
+        assert_eq!(find_partner(&buffer, Language::Rust, 0), None);
+    }
+
+    #[test]
+    fn test_skips_strings_and_comments() {
+        let code = "f(\")\", ')', // )\n  /* ( */ x: &'a T)";
+        let buffer = TextBuffer::from(code);
+        let close = code.rfind(')').unwrap();
+        assert_eq!(find_partner(&buffer, Language::Rust, 1), Some(close));
+        assert_eq!(find_partner(&buffer, Language::Rust, close), Some(1));
+        // Without language rules every bracket counts
+        assert_eq!(find_partner(&buffer, Language::PlainText, 1), Some(3));
+
+        let m = match_at(&buffer, Language::Rust, close + 1).unwrap();
+        assert_eq!((m.bracket, m.partner), (close, 1));
+    }
+}
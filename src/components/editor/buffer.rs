@@ -0,0 +1,409 @@
+//! Text buffer storage
+//!
+//! Rope-backed document storage with logarithmic edits and cheap snapshots.
+
+use std::{borrow::Cow, fmt, ops::Range};
+
+use ropey::Rope;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use super::coords;
+
+/// The document text of an editor.
+///
+/// Backed by a rope, so inserts and deletes are O(log n) regardless of the
+/// document size. Cloning is O(1) because clones share their unchanged
+/// nodes, which makes buffers cheap to keep around as history snapshots.
+///
+/// The rope also maintains a line-start index as edits are applied, so line
+/// lookups and line/column conversions are O(log n) as well.
+///
+/// All offsets are byte offsets and must lie on UTF-8 character boundaries.
+/// Only `\n` is treated as a line break.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TextBuffer {
+    rope: Rope,
+}
+
+impl TextBuffer {
+    /// Create an empty buffer.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the length of the text in bytes.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.rope.len_bytes()
+    }
+
+    /// Check if the buffer is empty.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.rope.len_bytes() == 0
+    }
+
+    /// Get the length of the text in characters.
+    #[must_use]
+    pub fn len_chars(&self) -> usize {
+        self.rope.len_chars()
+    }
+
+    /// Insert text at a byte offset.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `offset` is past the end of the buffer.
+    pub fn insert(&mut self, offset: usize, text: &str) {
+        let char_idx = self.rope.byte_to_char(offset);
+        self.rope.insert(char_idx, text);
+    }
+
+    /// Remove a byte range.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is out of bounds.
+    pub fn remove(&mut self, range: Range<usize>) {
+        if range.is_empty() {
+            return;
+        }
+        let start = self.rope.byte_to_char(range.start);
+        let end = self.rope.byte_to_char(range.end);
+        self.rope.remove(start..end);
+    }
+
+    /// Replace a byte range with new text.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range is out of bounds.
+    pub fn replace(&mut self, range: Range<usize>, text: &str) {
+        let start = range.start;
+        self.remove(range);
+        if !text.is_empty() {
+            self.insert(start, text);
+        }
+    }
+
+    /// Copy a byte range out of the buffer.
+    ///
+    /// Returns `None` if the range is out of bounds.
+    #[must_use]
+    pub fn slice(&self, range: Range<usize>) -> Option<String> {
+        if range.start > range.end || range.end > self.len() {
+            return None;
+        }
+        let start = self.rope.byte_to_char(range.start);
+        let end = self.rope.byte_to_char(range.end);
+        Some(self.rope.slice(start..end).to_string())
+    }
+
+    /// Get the character starting at a byte offset.
+    #[must_use]
+    pub fn char_at(&self, offset: usize) -> Option<char> {
+        if offset >= self.len() {
+            return None;
+        }
+        self.rope.get_char(self.rope.byte_to_char(offset))
+    }
+
+    /// Get the byte offset of the character boundary before `offset`.
+    ///
+    /// Returns 0 at the start of the buffer.
+    #[must_use]
+    pub fn prev_char_boundary(&self, offset: usize) -> usize {
+        let char_idx = self.rope.byte_to_char(offset.min(self.len()));
+        self.rope.char_to_byte(char_idx.saturating_sub(1))
+    }
+
+    /// Get the byte offset of the character boundary after `offset`.
+    ///
+    /// Returns the buffer length at the end of the buffer.
+    #[must_use]
+    pub fn next_char_boundary(&self, offset: usize) -> usize {
+        let char_idx = self.rope.byte_to_char(offset.min(self.len()));
+        self.rope
+            .char_to_byte((char_idx + 1).min(self.rope.len_chars()))
+    }
+
+    /// Get the byte offset of the grapheme cluster boundary before `offset`.
+    ///
+    /// Returns 0 at the start of the buffer.
+    #[must_use]
+    pub fn prev_grapheme_boundary(&self, offset: usize) -> usize {
+        let offset = offset.min(self.len());
+        let line = self.line_of_offset(offset);
+        let line_start = self.rope.line_to_byte(line);
+        if offset == line_start {
+            return self.prev_char_boundary(offset);
+        }
+        // Clusters never span a line break, so the line is enough context
+        let text = self.line(line).unwrap_or_default();
+        line_start + coords::prev_grapheme_boundary(&text, offset - line_start)
+    }
+
+    /// Get the byte offset of the grapheme cluster boundary after `offset`.
+    ///
+    /// Returns the buffer length at the end of the buffer.
+    #[must_use]
+    pub fn next_grapheme_boundary(&self, offset: usize) -> usize {
+        let offset = offset.min(self.len());
+        let line = self.line_of_offset(offset);
+        let line_start = self.rope.line_to_byte(line);
+        let text = self.line(line).unwrap_or_default();
+        if offset >= line_start + text.len() {
+            return self.next_char_boundary(offset);
+        }
+        line_start + coords::next_grapheme_boundary(&text, offset - line_start)
+    }
+
+    /// Convert a byte offset to a UTF-16 code unit offset.
+    #[must_use]
+    pub fn offset_to_utf16(&self, offset: usize) -> usize {
+        let char_idx = self.rope.byte_to_char(offset.min(self.len()));
+        self.rope.char_to_utf16_cu(char_idx)
+    }
+
+    /// Convert a UTF-16 code unit offset (as reported by the DOM) to a byte
+    /// offset.
+    ///
+    /// Offsets inside a surrogate pair round down to the start of the
+    /// character; offsets past the end clamp to the buffer length.
+    #[must_use]
+    pub fn utf16_to_offset(&self, utf16: usize) -> usize {
+        let utf16 = utf16.min(self.rope.len_utf16_cu());
+        self.rope.char_to_byte(self.rope.utf16_cu_to_char(utf16))
+    }
+
+    /// Get the number of lines (at least 1, even for an empty buffer).
+    #[must_use]
+    pub fn line_count(&self) -> usize {
+        self.rope.len_lines()
+    }
+
+    /// Get the byte offset where a line starts.
+    #[must_use]
+    pub fn line_start(&self, line: usize) -> Option<usize> {
+        (line < self.line_count()).then(|| self.rope.line_to_byte(line))
+    }
+
+    /// Get the byte range of a line, excluding its line terminator.
+    #[must_use]
+    pub fn line_range(&self, line: usize) -> Option<Range<usize>> {
+        let start = self.line_start(line)?;
+        let end = self
+            .line_start(line + 1)
+            .map_or(self.len(), |next| next - 1);
+        Some(start..end)
+    }
+
+    /// Get the line containing a byte offset.
+    ///
+    /// Offsets past the end map to the last line.
+    #[must_use]
+    pub fn line_of_offset(&self, offset: usize) -> usize {
+        self.rope.byte_to_line(offset.min(self.len()))
+    }
+
+    /// Get the text of a line, excluding its line terminator.
+    ///
+    /// Borrows from the rope when the line is stored contiguously.
+    #[must_use]
+    pub fn line(&self, line: usize) -> Option<Cow<'_, str>> {
+        let slice = self.rope.get_line(line)?;
+        if let Some(text) = slice.as_str() {
+            return Some(Cow::Borrowed(text.strip_suffix('\n').unwrap_or(text)));
+        }
+        let mut text = slice.to_string();
+        if text.ends_with('\n') {
+            text.pop();
+        }
+        Some(Cow::Owned(text))
+    }
+
+    /// Get the length of a line in characters, excluding its line terminator.
+    #[must_use]
+    pub fn line_len_chars(&self, line: usize) -> Option<usize> {
+        let slice = self.rope.get_line(line)?;
+        let len = slice.len_chars();
+        Some(if slice.chars_at(len).prev() == Some('\n') {
+            len - 1
+        } else {
+            len
+        })
+    }
+
+    /// Convert a line and character column to a byte offset.
+    ///
+    /// Returns `None` if the line does not exist or the column is past the
+    /// end of the line.
+    #[must_use]
+    pub fn line_col_to_offset(&self, line: usize, column: usize) -> Option<usize> {
+        if column > self.line_len_chars(line)? {
+            return None;
+        }
+        let char_idx = self.rope.line_to_char(line) + column;
+        Some(self.rope.char_to_byte(char_idx))
+    }
+
+    /// Convert a byte offset to a line and character column.
+    ///
+    /// Returns `None` if the offset is past the end of the buffer.
+    #[must_use]
+    pub fn offset_to_line_col(&self, offset: usize) -> Option<(usize, usize)> {
+        if offset > self.len() {
+            return None;
+        }
+        let char_idx = self.rope.byte_to_char(offset);
+        let line = self.rope.char_to_line(char_idx);
+        Some((line, char_idx - self.rope.line_to_char(line)))
+    }
+
+    /// Iterate over all characters in the buffer.
+    pub fn chars(&self) -> impl Iterator<Item = char> + '_ {
+        self.rope.chars()
+    }
+
+    /// Iterate over the contiguous text chunks that make up the buffer.
+    pub fn chunks(&self) -> impl Iterator<Item = &str> + '_ {
+        self.rope.chunks()
+    }
+}
+
+impl fmt::Display for TextBuffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for chunk in self.rope.chunks() {
+            f.write_str(chunk)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<&str> for TextBuffer {
+    fn from(text: &str) -> Self {
+        Self {
+            rope: Rope::from_str(text),
+        }
+    }
+}
+
+impl From<String> for TextBuffer {
+    fn from(text: String) -> Self {
+        Self::from(text.as_str())
+    }
+}
+
+impl From<&String> for TextBuffer {
+    fn from(text: &String) -> Self {
+        Self::from(text.as_str())
+    }
+}
+
+impl PartialEq<str> for TextBuffer {
+    fn eq(&self, other: &str) -> bool {
+        self.rope == other
+    }
+}
+
+impl PartialEq<&str> for TextBuffer {
+    fn eq(&self, other: &&str) -> bool {
+        self.rope == *other
+    }
+}
+
+impl PartialEq<String> for TextBuffer {
+    fn eq(&self, other: &String) -> bool {
+        self.rope == other.as_str()
+    }
+}
+
+impl Serialize for TextBuffer {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for TextBuffer {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Self::from)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_and_remove() {
+        let mut buffer = TextBuffer::from("hello world");
+        buffer.insert(5, ",");
+        assert_eq!(buffer, "hello, world");
+
+        buffer.remove(5..6);
+        assert_eq!(buffer, "hello world");
+
+        buffer.replace(6..11, "rope");
+        assert_eq!(buffer.to_string(), "hello rope");
+    }
+
+    #[test]
+    fn test_multibyte_offsets() {
+        let mut buffer = TextBuffer::from("a日本b");
+        assert_eq!(buffer.len(), 8);
+        assert_eq!(buffer.len_chars(), 4);
+        assert_eq!(buffer.next_char_boundary(1), 4);
+        assert_eq!(buffer.prev_char_boundary(7), 4);
+        assert_eq!(buffer.char_at(4), Some('本'));
+
+        buffer.remove(1..4);
+        assert_eq!(buffer, "a本b");
+        assert_eq!(buffer.slice(1..4).as_deref(), Some("本"));
+        assert_eq!(buffer.slice(1..9), None);
+    }
+
+    #[test]
+    fn test_line_index() {
+        let mut buffer = TextBuffer::from("hello\nwörld\n");
+        assert_eq!(buffer.line_count(), 3);
+        assert_eq!(buffer.line_range(1), Some(6..12));
+        assert_eq!(buffer.line_range(2), Some(13..13));
+        assert_eq!(buffer.line_range(3), None);
+        assert_eq!(buffer.line(1).as_deref(), Some("wörld"));
+        assert_eq!(buffer.line_len_chars(1), Some(5));
+
+        assert_eq!(buffer.line_col_to_offset(1, 2), Some(9));
+        assert_eq!(buffer.line_col_to_offset(1, 6), None);
+        assert_eq!(buffer.offset_to_line_col(9), Some((1, 2)));
+        assert_eq!(buffer.line_of_offset(13), 2);
+
+        buffer.insert(0, "a\nb\n");
+        assert_eq!(buffer.line_count(), 5);
+        assert_eq!(buffer.line_start(3), Some(10));
+    }
+
+    #[test]
+    fn test_unicode_boundaries() {
+        let buffer = TextBuffer::from("a👍🏽b\n😀");
+        assert_eq!(buffer.next_grapheme_boundary(1), 9);
+        assert_eq!(buffer.prev_grapheme_boundary(9), 1);
+        assert_eq!(buffer.next_grapheme_boundary(10), 11);
+        assert_eq!(buffer.prev_grapheme_boundary(11), 10);
+
+        assert_eq!(buffer.offset_to_utf16(9), 5);
+        assert_eq!(buffer.utf16_to_offset(5), 9);
+        assert_eq!(buffer.utf16_to_offset(8), 11);
+        assert_eq!(buffer.utf16_to_offset(100), buffer.len());
+    }
+
+    #[test]
+    fn test_snapshots_are_independent() {
+        let mut buffer = TextBuffer::from("original");
+        let snapshot = buffer.clone();
+        buffer.insert(0, "not ");
+
+        assert_eq!(snapshot, "original");
+        assert_eq!(buffer, "not original");
+    }
+}
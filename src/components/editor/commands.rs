@@ -0,0 +1,251 @@
+//! Editor commands
+//!
+//! A registry of named commands that operate on an [`EditorState`]. Key
+//! bindings in a [`Keymap`](super::Keymap) refer to commands by name, so an
+//! application can rebind keys or register its own commands without
+//! touching the editor component.
+
+use std::{collections::HashMap, fmt, sync::Arc};
+
+use crate::helpers::LineEnding;
+
+use super::{
+    movement::Movement,
+    state::{EditorState, SortOrder},
+    transform::TextTransform,
+};
+
+/// A command function.
+pub type CommandFn = Arc<dyn Fn(&mut EditorState) + Send + Sync>;
+
+/// A set of named commands.
+///
+/// [`CommandRegistry::default`] contains the built-in commands; use
+/// [`CommandRegistry::new`] for an empty registry.
+#[derive(Clone)]
+pub struct CommandRegistry {
+    commands: HashMap<String, CommandFn>,
+}
+
+impl fmt::Debug for CommandRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names: Vec<_> = self.names().collect();
+        names.sort_unstable();
+        f.debug_struct("CommandRegistry")
+            .field("commands", &names)
+            .finish()
+    }
+}
+
+impl Default for CommandRegistry {
+    fn default() -> Self {
+        let mut registry = Self::new();
+        registry.register_builtins();
+        registry
+    }
+}
+
+impl CommandRegistry {
+    /// Create an empty registry.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            commands: HashMap::new(),
+        }
+    }
+
+    /// Register a command, replacing any existing command with that name.
+    pub fn register(
+        &mut self,
+        name: impl Into<String>,
+        command: impl Fn(&mut EditorState) + Send + Sync + 'static,
+    ) {
+        self.commands.insert(name.into(), Arc::new(command));
+    }
+
+    /// Remove a command.
+    ///
+    /// Returns `true` if a command with that name existed.
+    pub fn unregister(&mut self, name: &str) -> bool {
+        self.commands.remove(name).is_some()
+    }
+
+    /// Get a command by name.
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<CommandFn> {
+        self.commands.get(name).cloned()
+    }
+
+    /// Check if a command is registered.
+    #[must_use]
+    pub fn contains(&self, name: &str) -> bool {
+        self.commands.contains_key(name)
+    }
+
+    /// Run a command.
+    ///
+    /// Returns `false` if no command with that name is registered.
+    pub fn run(&self, name: &str, state: &mut EditorState) -> bool {
+        match self.commands.get(name) {
+            Some(command) => {
+                command(state);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Iterate over the registered command names.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.commands.keys().map(String::as_str)
+    }
+
+    /// Register the built-in commands.
+    #[allow(clippy::too_many_lines)]
+    fn register_builtins(&mut self) {
+        self.register("undo", |state| {
+            state.undo();
+        });
+        self.register("redo", |state| {
+            state.redo();
+        });
+        self.register("select_all", EditorState::select_all);
+        self.register("indent", EditorState::indent);
+        self.register("outdent", EditorState::outdent);
+        self.register("delete_backward", EditorState::delete_backward);
+        self.register("delete_forward", EditorState::delete_forward);
+        self.register("insert_newline", EditorState::insert_newline);
+        self.register("jump_to_bracket", EditorState::jump_to_bracket);
+        self.register("toggle_line_comment", EditorState::toggle_line_comment);
+        self.register("toggle_block_comment", EditorState::toggle_block_comment);
+
+        self.register("move_lines_up", EditorState::move_lines_up);
+        self.register("move_lines_down", EditorState::move_lines_down);
+        self.register("copy_lines_up", EditorState::copy_lines_up);
+        self.register("copy_lines_down", EditorState::copy_lines_down);
+        self.register("delete_lines", EditorState::delete_lines);
+        self.register("join_lines", EditorState::join_lines);
+        self.register("reflow_paragraph", EditorState::reflow_paragraph);
+        self.register("insert_line_above", EditorState::insert_line_above);
+        self.register("insert_line_below", EditorState::insert_line_below);
+        for (name, order) in [
+            ("ascending", SortOrder::Ascending),
+            ("descending", SortOrder::Descending),
+            ("case_insensitive", SortOrder::CaseInsensitive),
+            ("natural", SortOrder::Natural),
+        ] {
+            self.register(format!("sort_lines_{name}"), move |state| {
+                state.sort_lines(order);
+            });
+        }
+        self.register(
+            "remove_duplicate_lines",
+            EditorState::remove_duplicate_lines,
+        );
+        self.register("reverse_lines", EditorState::reverse_lines);
+
+        for transform in TextTransform::ALL {
+            self.register(transform.command_name(), move |state| {
+                state.transform_selections(transform);
+            });
+        }
+        self.register("transpose_chars", EditorState::transpose_chars);
+        self.register(
+            "trim_trailing_whitespace",
+            EditorState::trim_trailing_whitespace,
+        );
+        self.register("indentation_to_spaces", EditorState::indentation_to_spaces);
+        self.register("indentation_to_tabs", EditorState::indentation_to_tabs);
+
+        for (name, line_ending) in [("lf", LineEnding::Lf), ("crlf", LineEnding::CrLf)] {
+            self.register(format!("line_ending_{name}"), move |state| {
+                state.set_line_ending(line_ending);
+            });
+        }
+        self.register("toggle_line_ending", |state| {
+            let line_ending = if state.line_ending == LineEnding::Lf {
+                LineEnding::CrLf
+            } else {
+                LineEnding::Lf
+            };
+            state.set_line_ending(line_ending);
+        });
+
+        self.register("add_cursor_above", |state| {
+            state.add_cursor_above();
+        });
+        self.register("add_cursor_below", |state| {
+            state.add_cursor_below();
+        });
+        self.register(
+            "clear_secondary_cursors",
+            EditorState::clear_secondary_cursors,
+        );
+
+        for (name, movement) in [
+            ("left", Movement::CharLeft),
+            ("right", Movement::CharRight),
+            ("word_left", Movement::WordLeft),
+            ("word_right", Movement::WordRight),
+            ("up", Movement::LineUp),
+            ("down", Movement::LineDown),
+            ("line_start", Movement::LineStart),
+            ("line_end", Movement::LineEnd),
+            ("document_start", Movement::DocumentStart),
+            ("document_end", Movement::DocumentEnd),
+        ] {
+            self.register(format!("move_{name}"), move |state| {
+                state.move_cursors(movement, false);
+            });
+            self.register(format!("select_{name}"), move |state| {
+                state.move_cursors(movement, true);
+            });
+        }
+
+        for (name, movement) in [
+            ("left", Movement::CharLeft),
+            ("right", Movement::CharRight),
+            ("up", Movement::LineUp),
+            ("down", Movement::LineDown),
+        ] {
+            self.register(format!("block_select_{name}"), move |state| {
+                state.extend_block(movement);
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_builtin_commands() {
+        let registry = CommandRegistry::default();
+        let mut state = EditorState::new("hello world");
+
+        assert!(registry.run("move_document_end", &mut state));
+        assert!(registry.run("select_word_left", &mut state));
+        assert_eq!(state.selected_text().as_deref(), Some("world"));
+        assert!(registry.run("delete_backward", &mut state));
+        assert_eq!(state.content(), "hello ");
+        assert!(registry.run("undo", &mut state));
+        assert_eq!(state.content(), "hello world");
+        assert!(!registry.run("no_such_command", &mut state));
+    }
+
+    #[test]
+    fn test_register_custom_command() {
+        let mut registry = CommandRegistry::new();
+        registry.register("shout", |state| {
+            let text = state.content().to_uppercase();
+            state.set_content(text);
+        });
+
+        let mut state = EditorState::new("quiet");
+        assert!(registry.run("shout", &mut state));
+        assert_eq!(state.content(), "QUIET");
+        assert!(registry.unregister("shout"));
+        assert!(!registry.contains("shout"));
+    }
+}
@@ -0,0 +1,190 @@
+//! Text coordinate conversions
+//!
+//! The editor deals with four units of text length:
+//!
+//! - **Bytes** (UTF-8) - offsets into [`TextBuffer`](super::TextBuffer),
+//!   `FindResult` ranges and all document edits
+//! - **Chars** (Unicode scalar values) - the unit of
+//!   [`CursorPosition::column`](super::CursorPosition::column)
+//! - **UTF-16 code units** - what the DOM reports, e.g. a textarea's
+//!   `selectionStart`
+//! - **Grapheme clusters** - what the user perceives as one character, used
+//!   for caret movement and deletion
+//!
+//! Every conversion between them goes through this module so that emoji,
+//! CJK text and combining marks are handled the same way everywhere.
+//! Conversions from a byte offset that is not on a character boundary round
+//! down to the start of that character; offsets past the end clamp to the
+//! end of the text.
+
+use unicode_segmentation::{GraphemeCursor, UnicodeSegmentation};
+
+/// Round a byte offset down to the nearest char boundary, clamped to the text.
+#[must_use]
+pub fn floor_char_boundary(text: &str, byte: usize) -> usize {
+    if byte >= text.len() {
+        return text.len();
+    }
+    (0..=byte)
+        .rev()
+        .find(|&i| text.is_char_boundary(i))
+        .unwrap_or(0)
+}
+
+/// Convert a byte offset to a char offset.
+#[must_use]
+pub fn byte_to_char(text: &str, byte: usize) -> usize {
+    text[..floor_char_boundary(text, byte)].chars().count()
+}
+
+/// Convert a char offset to a byte offset.
+///
+/// Char offsets past the end clamp to the text length.
+#[must_use]
+pub fn char_to_byte(text: &str, char_offset: usize) -> usize {
+    text.char_indices()
+        .nth(char_offset)
+        .map_or(text.len(), |(i, _)| i)
+}
+
+/// Convert a byte offset to a UTF-16 code unit offset.
+#[must_use]
+pub fn byte_to_utf16(text: &str, byte: usize) -> usize {
+    text[..floor_char_boundary(text, byte)]
+        .chars()
+        .map(char::len_utf16)
+        .sum()
+}
+
+/// Convert a UTF-16 code unit offset to a byte offset.
+///
+/// An offset that falls between the two halves of a surrogate pair rounds
+/// down to the start of that character.
+#[must_use]
+pub fn utf16_to_byte(text: &str, utf16: usize) -> usize {
+    let mut units = 0;
+    for (i, ch) in text.char_indices() {
+        units += ch.len_utf16();
+        if units > utf16 {
+            return i;
+        }
+    }
+    text.len()
+}
+
+/// Convert a char offset to a UTF-16 code unit offset.
+#[must_use]
+pub fn char_to_utf16(text: &str, char_offset: usize) -> usize {
+    text.chars().take(char_offset).map(char::len_utf16).sum()
+}
+
+/// Convert a UTF-16 code unit offset to a char offset.
+#[must_use]
+pub fn utf16_to_char(text: &str, utf16: usize) -> usize {
+    byte_to_char(text, utf16_to_byte(text, utf16))
+}
+
+/// Count the grapheme clusters in a string.
+#[must_use]
+pub fn grapheme_count(text: &str) -> usize {
+    text.graphemes(true).count()
+}
+
+/// Convert a byte offset to a grapheme cluster offset.
+///
+/// A byte offset inside a cluster counts the cluster as not yet reached.
+#[must_use]
+pub fn byte_to_grapheme(text: &str, byte: usize) -> usize {
+    text.grapheme_indices(true)
+        .take_while(|&(i, g)| i + g.len() <= byte)
+        .count()
+}
+
+/// Convert a grapheme cluster offset to a byte offset.
+///
+/// Offsets past the end clamp to the text length.
+#[must_use]
+pub fn grapheme_to_byte(text: &str, grapheme: usize) -> usize {
+    text.grapheme_indices(true)
+        .nth(grapheme)
+        .map_or(text.len(), |(i, _)| i)
+}
+
+/// Get the byte offset of the grapheme boundary before `byte`.
+///
+/// Returns 0 at the start of the text.
+#[must_use]
+pub fn prev_grapheme_boundary(text: &str, byte: usize) -> usize {
+    let byte = floor_char_boundary(text, byte);
+    let mut cursor = GraphemeCursor::new(byte, text.len(), true);
+    cursor.prev_boundary(text, 0).ok().flatten().unwrap_or(0)
+}
+
+/// Get the byte offset of the grapheme boundary after `byte`.
+///
+/// Returns the text length at the end of the text.
+#[must_use]
+pub fn next_grapheme_boundary(text: &str, byte: usize) -> usize {
+    let byte = floor_char_boundary(text, byte);
+    let mut cursor = GraphemeCursor::new(byte, text.len(), true);
+    cursor
+        .next_boundary(text, 0)
+        .ok()
+        .flatten()
+        .unwrap_or(text.len())
+}
+
+/// Convert a byte offset to a (line, char column) pair, both 0-indexed.
+#[must_use]
+pub fn byte_to_line_col(text: &str, byte: usize) -> (usize, usize) {
+    let byte = floor_char_boundary(text, byte);
+    let before = &text[..byte];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (
+        before.matches('\n').count(),
+        before[line_start..].chars().count(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_char_and_utf16_conversions() {
+        // 'é' is 2 bytes, '日' is 3 bytes, '😀' is 4 bytes / 2 UTF-16 units
+        let text = "é日😀x";
+
+        assert_eq!(byte_to_char(text, 5), 2);
+        assert_eq!(char_to_byte(text, 3), 9);
+        assert_eq!(byte_to_utf16(text, 9), 4);
+        assert_eq!(utf16_to_byte(text, 4), 9);
+        // Between the surrogate halves of the emoji
+        assert_eq!(utf16_to_byte(text, 3), 5);
+        assert_eq!(char_to_utf16(text, 4), 5);
+        assert_eq!(utf16_to_char(text, 5), 4);
+        // Mid-character byte offsets round down
+        assert_eq!(byte_to_char(text, 3), 1);
+    }
+
+    #[test]
+    fn test_grapheme_conversions() {
+        // Thumbs up with a skin tone modifier is two chars but one grapheme
+        let text = "a👍🏽b";
+        assert_eq!(grapheme_count(text), 3);
+        assert_eq!(grapheme_to_byte(text, 2), 9);
+        assert_eq!(byte_to_grapheme(text, 9), 2);
+        assert_eq!(byte_to_grapheme(text, 5), 1);
+        assert_eq!(next_grapheme_boundary(text, 1), 9);
+        assert_eq!(prev_grapheme_boundary(text, 9), 1);
+    }
+
+    #[test]
+    fn test_byte_to_line_col() {
+        let text = "ab\n日本語\nx";
+        assert_eq!(byte_to_line_col(text, 0), (0, 0));
+        assert_eq!(byte_to_line_col(text, 6), (1, 1));
+        assert_eq!(byte_to_line_col(text, 13), (2, 0));
+        assert_eq!(byte_to_line_col(text, 100), (2, 1));
+    }
+}
@@ -2,9 +2,26 @@
 //!
 //! The main text editor component with full editing capabilities.
 
-use leptos::prelude::*;
-
-use super::state::{EditorConfig, EditorState};
+use leptos::{html, prelude::*};
+use web_sys::HtmlTextAreaElement;
+
+use crate::helpers::{LineEnding, count_lines};
+
+#[cfg(feature = "emacs")]
+use super::emacs::{Emacs, EmacsResponse};
+#[cfg(feature = "vim")]
+use super::vim::{Vim, VimKey, VimMode, VimResponse};
+use super::{
+    commands::CommandRegistry,
+    cursor::{CursorPosition, CursorSet},
+    handle::{EditorController, EditorHandle},
+    keymap::{KeyChord, KeyLookup, KeyProfile, Keymap},
+    movement::Movement,
+    overlay::{self, Decoration, WhitespaceCache},
+    selection::{Selection, SelectionMode},
+    state::{EditorConfig, EditorState, WhitespaceMode},
+    transaction::{ChangeEvent, ChangeOrigin},
+};
 
 /// A production-ready text editor component.
 ///
@@ -13,8 +30,11 @@ use super::state::{EditorConfig, EditorState};
 /// - Line numbers
 /// - Find and replace (with `find-replace` feature)
 /// - Undo/redo
-/// - Multiple cursors (planned)
+/// - Multiple cursors (Alt+Click, Ctrl+Alt+Up/Down)
+/// - Block selection (Alt+Shift+drag, Alt+Shift+Arrow)
 /// - Code folding (with `folding` feature)
+/// - Vim modal editing (with `vim` feature)
+/// - Emacs key bindings (with `emacs` feature)
 ///
 /// # Example
 ///
@@ -65,6 +85,16 @@ pub fn Editor(
     #[prop(into, optional)]
     on_change: Option<Callback<String>>,
 
+    /// Callback with the ranges changed by each edit, as an alternative to
+    /// receiving the whole document through `on_change`
+    #[prop(into, optional)]
+    on_transaction: Option<Callback<ChangeEvent>>,
+
+    /// Identifies the open document. Changing it loads the next `value` as
+    /// a new document with empty undo history.
+    #[prop(into, optional)]
+    document_key: Option<Signal<String>>,
+
     /// Placeholder text shown when editor is empty
     #[prop(into, optional)]
     placeholder: Option<String>,
@@ -136,36 +166,111 @@ pub fn Editor(
     /// Whether to highlight the current line
     #[prop(optional, default = true)]
     highlight_current_line: bool,
+
+    /// Which whitespace characters to draw (`true` and `false` mean
+    /// [`WhitespaceMode::All`] and [`WhitespaceMode::None`])
+    #[prop(into, optional)]
+    show_whitespace: WhitespaceMode,
+
+    /// Columns to draw vertical rulers at
+    #[prop(optional)]
+    rulers: Vec<usize>,
+
+    /// Maximum line width, drawn as a ruler and used to reflow paragraphs
+    /// (0 = no limit)
+    #[prop(optional)]
+    max_line_width: usize,
+
+    /// Key bindings (defaults to [`Keymap::default`] for the current platform)
+    #[prop(optional)]
+    keymap: Option<Keymap>,
+
+    /// Commands available to key bindings (defaults to the built-in commands)
+    #[prop(optional)]
+    commands: Option<CommandRegistry>,
+
+    /// How key presses are interpreted (standard editing, Vim or Emacs)
+    #[prop(optional)]
+    key_profile: KeyProfile,
+
+    /// Callback when the user saves (`:w` in Vim, `C-x C-s` in Emacs)
+    #[prop(into, optional)]
+    on_save: Option<Callback<String>>,
+
+    /// Callback to transform pasted text; returning `None` cancels the paste
+    #[prop(into, optional)]
+    on_paste: Option<Callback<String, Option<String>>>,
+
+    /// Handle for controlling the editor from outside (see [`EditorHandle`])
+    #[prop(optional)]
+    handle: Option<EditorHandle>,
 ) -> impl IntoView {
     // Internal state
     let (cursor_line, set_cursor_line) = signal(0usize);
     let (cursor_col, set_cursor_col) = signal(0usize);
     let (is_focused, set_is_focused) = signal(false);
+    let (decorations, set_decorations) = signal(Vec::<Decoration>::new());
+    let (scroll, set_scroll) = signal((0, 0));
+    let (line_ending, set_line_ending) = signal(LineEnding::default());
+    let last_selection = StoredValue::new(None::<String>);
+    // The textarea's selection and length before the edit being input
+    let input_window = StoredValue::new(None::<(u32, u32, u32)>);
+    let textarea_ref = NodeRef::<html::Textarea>::new();
+    let keymap = StoredValue::new(keymap.unwrap_or_default());
+    let commands = StoredValue::new(commands.unwrap_or_default());
+    let pending_keys = StoredValue::new(Vec::<KeyChord>::new());
+    let mode_status = RwSignal::new(String::new());
+    // Only the Vim and Emacs profiles differ from standard editing and save
+    #[cfg(not(any(feature = "vim", feature = "emacs")))]
+    let _ = (key_profile, on_save);
+
+    #[cfg(feature = "vim")]
+    let vim = StoredValue::new(Vim::new());
+    #[cfg(feature = "vim")]
+    let vim_mode = RwSignal::new(VimMode::Normal);
+    #[cfg(feature = "vim")]
+    if key_profile == KeyProfile::Vim {
+        mode_status.set(vim.with_value(Vim::status));
+    }
+    #[cfg(feature = "emacs")]
+    let emacs = StoredValue::new(Emacs::new());
+    // Whether the state, not the textarea, owns the cursor: in Vim's
+    // normal and visual modes keys never move the native caret
+    let modal_cursor = move || {
+        #[cfg(feature = "vim")]
+        if key_profile == KeyProfile::Vim {
+            return vim_mode.get_untracked() != VimMode::Insert;
+        }
+        false
+    };
 
     // Create editor state
-    let editor_state = StoredValue::new(EditorState::with_config(
-        value.get_untracked(),
-        EditorConfig {
-            tab_size,
-            word_wrap,
-            show_line_numbers,
-            highlight_current_line,
-            match_brackets,
-            font_size,
-            read_only,
-            ..Default::default()
-        },
-    ));
+    let editor_state = StoredValue::new(EditorState {
+        language: language.clone(),
+        ..EditorState::with_config(
+            value.get_untracked(),
+            EditorConfig {
+                tab_size,
+                word_wrap,
+                show_line_numbers,
+                highlight_current_line,
+                show_whitespace,
+                match_brackets,
+                font_size,
+                max_line_width,
+                rulers,
+                read_only,
+                ..Default::default()
+            },
+        )
+    });
+
+    let ruler_columns = editor_state.with_value(|state| state.config.ruler_columns());
 
     // Compute line count for line numbers
-    let line_count = Memo::new(move |_| {
-        let content = value.get();
-        if content.is_empty() {
-            1
-        } else {
-            content.chars().filter(|&c| c == '\n').count() + 1
-        }
-    });
+    // The value may use any line ending
+    let line_count =
+        Memo::new(move |_| value.with(|content| count_lines(&LineEnding::normalize(content))));
 
     // Generate line number elements
     let line_numbers_view = move || {
@@ -208,6 +313,10 @@ pub fn Editor(
         if show_line_numbers {
             classes.push("with-line-numbers");
         }
+        #[cfg(feature = "vim")]
+        if key_profile == KeyProfile::Vim && vim_mode.get() != VimMode::Insert {
+            classes.push("vim-normal");
+        }
 
         if let Some(ref custom) = class {
             classes.push(custom);
@@ -233,18 +342,222 @@ pub fn Editor(
         styles.join("; ")
     };
 
+    // Publish the primary cursor, the selection and the overlay decorations
+    // Bracket matching scans the text around each cursor, so it is only
+    // redone when the text or the cursors change, and whitespace is only
+    // looked at again on the lines an edit touched
+    let bracket_cache = StoredValue::new(None::<(u64, CursorSet, Vec<Decoration>)>);
+    let whitespace_cache = StoredValue::new(WhitespaceCache::new());
+    let refresh_view = move || {
+        let (head, selected, cursor_decorations, ending) = editor_state.with_value(|state| {
+            let mut decorations = whitespace_cache
+                .try_update_value(|cache| cache.decorations(state))
+                .unwrap_or_default();
+            decorations.extend(overlay::secondary_cursor_decorations(state));
+            let cached = bracket_cache.with_value(|cache| {
+                cache
+                    .as_ref()
+                    .filter(|(version, cursors, _)| {
+                        *version == state.version && *cursors == state.cursors
+                    })
+                    .map(|(_, _, brackets)| brackets.clone())
+            });
+            let brackets = cached.unwrap_or_else(|| {
+                let brackets = overlay::bracket_decorations(state);
+                bracket_cache.set_value(Some((
+                    state.version,
+                    state.cursors.clone(),
+                    brackets.clone(),
+                )));
+                brackets
+            });
+            decorations.extend(brackets);
+            (
+                state.cursor_position(),
+                state.selected_text(),
+                decorations,
+                state.line_ending,
+            )
+        });
+        if line_ending.get_untracked() != ending {
+            set_line_ending.set(ending);
+        }
+
+        if (head.line, head.column) != (cursor_line.get_untracked(), cursor_col.get_untracked()) {
+            set_cursor_line.set(head.line);
+            set_cursor_col.set(head.column);
+            if let Some(callback) = on_cursor_change.as_ref() {
+                callback.run((head.line + 1, head.column + 1)); // 1-indexed for display
+            }
+        }
+
+        if last_selection.with_value(|last| *last != selected) {
+            last_selection.set_value(selected.clone());
+            if let Some(callback) = on_selection_change.as_ref() {
+                callback.run(selected);
+            }
+        }
+
+        if decorations.with_untracked(|current| *current != cursor_decorations) {
+            set_decorations.set(cursor_decorations);
+        }
+    };
+
+    // Read the textarea's selection (and optionally its value) into the
+    // editor state. This replaces any secondary cursors.
+    let sync_from_textarea = move |textarea: &HtmlTextAreaElement, with_value: bool| {
+        let backward = textarea.selection_direction().ok().flatten().as_deref() == Some("backward");
+        let value = with_value.then(|| textarea.value());
+        // The text between the selections before and after the edit is all
+        // that can have changed, in UTF-16 units of the old and new value
+        let window = input_window
+            .try_update_value(Option::take)
+            .flatten()
+            .and_then(|(old_start, old_end, old_len)| {
+                let start = textarea.selection_start().ok().flatten()?;
+                let end = textarea.selection_end().ok().flatten()?;
+                let delta = i64::from(textarea.text_length()) - i64::from(old_len);
+                let new_end = i64::from(end).max(i64::from(old_end) + delta);
+                let old_end = usize::try_from(new_end - delta).ok()?;
+                let start = old_start.min(start) as usize;
+                Some((start..old_end, usize::try_from(new_end).ok()?))
+            });
+
+        editor_state.update_value(|state| {
+            match (value, window) {
+                (Some(value), Some((changed, new_end))) => {
+                    state.set_content_within(&value, changed, new_end);
+                }
+                (Some(value), None) => state.set_content(value),
+                _ => {}
+            }
+            let (start, end) = dom_selection(state, textarea);
+            let (head, anchor) = if backward { (start, end) } else { (end, start) };
+            state.clear_secondary_cursors();
+            state.set_cursor_with_selection(head, anchor);
+        });
+        refresh_view();
+    };
+
+    // Report the edits made since the last report to `on_transaction`
+    let report_change = move |origin: ChangeOrigin| {
+        let event = editor_state
+            .try_update_value(|state| state.take_change(origin))
+            .flatten();
+        if let (Some(event), Some(callback)) = (event, on_transaction.as_ref()) {
+            callback.run(event);
+        }
+    };
+
+    // Write the editor state back into the textarea after an edit made
+    // through the state rather than by the browser. `change` says what
+    // edited the content, if anything did.
+    let sync_to_textarea = move |change: Option<ChangeOrigin>| {
+        let Some(textarea) = textarea_ref.get_untracked() else {
+            return;
+        };
+        let content_changed = change.is_some();
+        let (content, start, end, backward) = editor_state.with_value(|state| {
+            let cursor = state.cursors.primary();
+            let utf16 = |position| {
+                state
+                    .position_to_offset(position)
+                    .map_or(0, |offset| state.buffer.offset_to_utf16(offset))
+            };
+            (
+                content_changed.then(|| (state.content(), state.line_ending)),
+                utf16(cursor.selection_start()),
+                utf16(cursor.selection_end()),
+                cursor.head < cursor.anchor,
+            )
+        });
+
+        if let Some((content, line_ending)) = content {
+            textarea.set_value(&content);
+            if let Some(callback) = on_change.as_ref() {
+                callback.run(line_ending.apply(&content).into_owned());
+            }
+        }
+        if let Some(origin) = change {
+            report_change(origin);
+        }
+        let direction = if backward { "backward" } else { "forward" };
+        let _ = textarea.set_selection_range_with_direction(
+            dom_offset(start),
+            dom_offset(end),
+            direction,
+        );
+        refresh_view();
+    };
+
+    // Follow changes the parent makes to `value`. Edits made here come back
+    // through `on_change` already matching the state; anything else is
+    // merged in as its own undo step, or starts a new document when
+    // `document_key` changed.
+    let last_document_key = StoredValue::new(document_key.map(|key| key.get_untracked()));
+    Effect::new(move |_| {
+        let content = value.get();
+        let key = document_key.map(|key| key.get());
+        let new_document = last_document_key.with_value(|last| *last != key);
+        last_document_key.set_value(key);
+        let changed = editor_state.try_update_value(|state| {
+            if new_document {
+                state.reset_content(content.as_str());
+                true
+            } else {
+                state.sync_content(&content)
+            }
+        });
+        if changed != Some(true) {
+            return;
+        }
+        let content = editor_state.with_value(EditorState::content);
+        if let Some(textarea) = textarea_ref.get_untracked()
+            && textarea.value() != content
+        {
+            textarea.set_value(&content);
+        }
+        sync_to_textarea(None);
+        report_change(ChangeOrigin::External);
+    });
+
+    let is_multi_cursor = move || editor_state.with_value(|state| state.cursors.is_multi());
+
     // Handle input changes
+    // Note where the textarea's selection was before an edit whose extent
+    // it bounds, so the input handler only compares that part of the text
+    let handle_beforeinput = move |ev: web_sys::InputEvent| {
+        let target = event_target::<HtmlTextAreaElement>(&ev);
+        let window = matches!(
+            ev.input_type().as_str(),
+            "insertText"
+                | "insertLineBreak"
+                | "deleteContentBackward"
+                | "deleteContentForward"
+                | "deleteWordBackward"
+                | "deleteWordForward"
+        )
+        .then(|| {
+            let start = target.selection_start().ok().flatten()?;
+            let end = target.selection_end().ok().flatten()?;
+            Some((start, end, target.text_length()))
+        })
+        .flatten();
+        input_window.set_value(window);
+    };
+
     let handle_input = move |ev: web_sys::Event| {
         if read_only {
             return;
         }
 
-        let target = event_target::<web_sys::HtmlTextAreaElement>(&ev);
-        let new_value = target.value();
+        let target = event_target::<HtmlTextAreaElement>(&ev);
+        sync_from_textarea(&target, true);
 
         if let Some(callback) = on_change.as_ref() {
-            callback.run(new_value);
+            callback.run(editor_state.with_value(EditorState::document_text));
         }
+        report_change(ChangeOrigin::Typing);
     };
 
     // Handle focus
@@ -263,109 +576,405 @@ pub fn Editor(
         }
     };
 
-    // Handle selection change and cursor position
+    // Track the native selection while there is a single cursor. With
+    // several cursors the state is the source of truth.
     let handle_select = move |ev: web_sys::Event| {
-        let target = event_target::<web_sys::HtmlTextAreaElement>(&ev);
+        if !is_multi_cursor() {
+            sync_from_textarea(&event_target::<HtmlTextAreaElement>(&ev), false);
+        }
+    };
+
+    let handle_keyup = move |ev: web_sys::KeyboardEvent| {
+        if !is_multi_cursor() && !modal_cursor() {
+            sync_from_textarea(&event_target::<HtmlTextAreaElement>(&ev), false);
+        }
+    };
+
+    // Double-click selects a word and triple-click a line; a single click
+    // goes back to character selection unless Shift extends the selection
+    let handle_mousedown = move |ev: web_sys::MouseEvent| {
+        // Alt+Shift+drag starts a block selection at the current cursor
+        if ev.alt_key() && ev.shift_key() {
+            editor_state.update_value(|state| {
+                state.selection_mode = SelectionMode::Block;
+                state.selection_origin = Some(Selection::empty(state.cursor_position()));
+            });
+            return;
+        }
+        if ev.alt_key() {
+            return;
+        }
+        let target = event_target::<HtmlTextAreaElement>(&ev);
+        editor_state.update_value(|state| match ev.detail() {
+            1 if !ev.shift_key() => {
+                state.selection_mode = SelectionMode::Character;
+                state.selection_origin = None;
+            }
+            // The caret is still where the first click of the double-click put it
+            2 => {
+                let (_, caret) = dom_selection(state, &target);
+                state.select_word_at(caret);
+            }
+            3 => {
+                state.select_line(state.cursor_position().line);
+            }
+            _ => {}
+        });
+    };
+
+    // Alt+Click adds a cursor where the browser placed the caret. Otherwise
+    // the native selection is snapped to the active selection mode.
+    let handle_mouseup = move |ev: web_sys::MouseEvent| {
+        let target = event_target::<HtmlTextAreaElement>(&ev);
+        if ev.alt_key() && !ev.shift_key() {
+            editor_state.update_value(|state| {
+                let (_, caret) = dom_selection(state, &target);
+                state.add_cursor(caret);
+            });
+            sync_to_textarea(None);
+        } else if editor_state.with_value(|state| state.selection_mode) != SelectionMode::Character
+        {
+            editor_state.update_value(|state| {
+                let (start, end) = dom_selection(state, &target);
+                let before_origin = state
+                    .selection_origin
+                    .is_some_and(|origin| start < origin.start);
+                state.extend_selection_to(if before_origin { start } else { end });
+            });
+            sync_to_textarea(None);
+        } else {
+            sync_from_textarea(&target, false);
+        }
+    };
 
-        // Get cursor position
-        if let (Ok(start), Ok(end)) = (target.selection_start(), target.selection_end()) {
-            let start = start.unwrap_or(0) as usize;
-            let end = end.unwrap_or(0) as usize;
+    // A block selection is copied as one line per row and pasted back as a
+    // block. Without a selection the whole lines under the cursors are
+    // copied and pasted back above the cursor lines.
+    let block_clipboard = StoredValue::new(None::<String>);
+    let line_clipboard = StoredValue::new(None::<String>);
+    let write_block = move |ev: &web_sys::ClipboardEvent, text: String| {
+        let Some(data) = ev.clipboard_data() else {
+            return;
+        };
+        if data.set_data("text/plain", &text).is_ok() {
+            ev.prevent_default();
+            block_clipboard.set_value(Some(text));
+        }
+    };
+    let write_clipboard = move |ev: &web_sys::ClipboardEvent, text: String, linewise: bool| {
+        let Some(data) = ev.clipboard_data() else {
+            return;
+        };
+        if data.set_data("text/plain", &text).is_ok() {
+            ev.prevent_default();
+            line_clipboard.set_value(linewise.then_some(text));
+        }
+    };
 
-            // Calculate line and column from offset
-            let content = value.get();
-            let (line, col) = offset_to_line_col(&content, start);
+    let handle_copy = move |ev: web_sys::ClipboardEvent| {
+        if let Some(text) = editor_state.with_value(EditorState::block_text) {
+            write_block(&ev, text);
+            return;
+        }
+        if !is_multi_cursor() && !modal_cursor() {
+            sync_from_textarea(&event_target::<HtmlTextAreaElement>(&ev), false);
+        }
+        let (text, linewise) = editor_state.with_value(EditorState::copy_text);
+        write_clipboard(&ev, text, linewise);
+    };
 
-            set_cursor_line.set(line);
-            set_cursor_col.set(col);
+    let handle_cut = move |ev: web_sys::ClipboardEvent| {
+        if read_only {
+            return;
+        }
+        // A block without columns falls through to the line-wise cut
+        if let Some(text) = editor_state
+            .try_update_value(EditorState::cut_block)
+            .flatten()
+        {
+            write_block(&ev, text);
+            sync_to_textarea(Some(ChangeOrigin::Cut));
+            return;
+        }
+        if !is_multi_cursor() && !modal_cursor() {
+            sync_from_textarea(&event_target::<HtmlTextAreaElement>(&ev), false);
+        }
+        let Some((text, linewise)) = editor_state.try_update_value(EditorState::cut) else {
+            return;
+        };
+        write_clipboard(&ev, text, linewise);
+        sync_to_textarea(Some(ChangeOrigin::Cut));
+    };
 
-            if let Some(callback) = on_cursor_change.as_ref() {
-                callback.run((line + 1, col + 1)); // 1-indexed for display
+    let handle_paste = move |ev: web_sys::ClipboardEvent| {
+        ev.prevent_default();
+        if read_only {
+            return;
+        }
+        let Some(mut text) = ev
+            .clipboard_data()
+            .and_then(|data| data.get_data("text/plain").ok())
+        else {
+            return;
+        };
+        let clipboard_text = text.clone();
+        if let Some(callback) = on_paste.as_ref() {
+            match callback.run(text) {
+                Some(transformed) => text = transformed,
+                None => return,
+            }
+        }
+        if !is_multi_cursor() && !modal_cursor() {
+            sync_from_textarea(&event_target::<HtmlTextAreaElement>(&ev), false);
+        }
+        let as_block = editor_state.with_value(|state| state.active_block().is_some())
+            || block_clipboard
+                .with_value(|block| block.as_deref() == Some(clipboard_text.as_str()));
+        let linewise =
+            line_clipboard.with_value(|line| line.as_deref() == Some(clipboard_text.as_str()));
+        let pasted = editor_state.try_update_value(|state| {
+            if as_block {
+                state.paste_block(&text)
+            } else {
+                state.paste(&text, linewise)
             }
+        });
+        if pasted == Some(true) {
+            sync_to_textarea(Some(ChangeOrigin::Paste));
+        }
+    };
 
-            // Selection changed
-            if let Some(callback) = on_selection_change.as_ref() {
-                let selected = if start != end && end <= content.len() {
-                    content.get(start..end).map(String::from)
-                } else {
-                    None
-                };
-                callback.run(selected);
+    let handle_scroll = move |ev: web_sys::Event| {
+        let target = event_target::<HtmlTextAreaElement>(&ev);
+        set_scroll.set((target.scroll_left(), target.scroll_top()));
+    };
+
+    // Run a named command and push the result out to the textarea
+    let run_command = move |name: &str| {
+        let Some(command) = commands.with_value(|registry| registry.get(name)) else {
+            return;
+        };
+        let version = editor_state.with_value(|state| state.version);
+        editor_state.update_value(|state| command(state));
+        let content_changed = editor_state.with_value(|state| state.version) != version;
+        let origin = match name {
+            "undo" => ChangeOrigin::Undo,
+            "redo" => ChangeOrigin::Redo,
+            _ => ChangeOrigin::Command,
+        };
+        sync_to_textarea(content_changed.then_some(origin));
+    };
+
+    if let Some(handle) = handle {
+        handle.attach(EditorController {
+            state: editor_state,
+            textarea: textarea_ref,
+            sync: Callback::new(sync_to_textarea),
+            run_command: Callback::new(move |name: String| {
+                let exists = commands.with_value(|registry| registry.contains(&name));
+                if exists {
+                    run_command(&name);
+                }
+                exists
+            }),
+            line_height: f64::from(font_size) * 1.5,
+        });
+        on_cleanup(move || handle.detach());
+    }
+
+    // Push a key handled by the Vim or Emacs profile out to the textarea,
+    // saving if the key asked for it
+    #[cfg(any(feature = "vim", feature = "emacs"))]
+    let finish_profile_key = move |version: u64, save: bool| {
+        let content_changed = editor_state.with_value(|state| state.version) != version;
+        sync_to_textarea(content_changed.then_some(ChangeOrigin::Command));
+        if save {
+            let content = editor_state.with_value(EditorState::document_text);
+            editor_state.update_value(EditorState::mark_saved);
+            if let Some(callback) = on_save.as_ref() {
+                callback.run(content);
             }
         }
     };
 
     // Handle keyboard shortcuts
     let handle_keydown = move |ev: web_sys::KeyboardEvent| {
-        let key = ev.key();
-        let ctrl_or_cmd = ev.ctrl_key() || ev.meta_key();
-        let shift = ev.shift_key();
-
-        // Tab handling
-        if key == "Tab" && !read_only {
-            ev.prevent_default();
+        if ev.is_composing() {
+            return;
+        }
 
-            // Get current textarea
-            let target = event_target::<web_sys::HtmlTextAreaElement>(&ev);
-            if let (Ok(Some(start)), Ok(Some(end))) =
-                (target.selection_start(), target.selection_end())
-            {
-                let start = start as usize;
-                let end = end as usize;
-                let content = value.get();
-
-                let indent = " ".repeat(tab_size);
-
-                if shift {
-                    // Shift+Tab: Unindent
-                    // TODO: Implement unindent
-                } else {
-                    // Tab: Indent
-                    let new_content = format!("{}{}{}", &content[..start], indent, &content[end..]);
-
-                    if let Some(callback) = on_change.as_ref() {
-                        callback.run(new_content);
-                    }
+        // Vim sees keys first; the ones it does not use go on to the key
+        // bindings
+        #[cfg(feature = "vim")]
+        if key_profile == KeyProfile::Vim
+            && let Some(chord) = KeyChord::from_event(&ev)
+        {
+            if !is_multi_cursor() && !modal_cursor() {
+                sync_from_textarea(&event_target::<HtmlTextAreaElement>(&ev), false);
+            }
+            let version = editor_state.with_value(|state| state.version);
+            let response = editor_state
+                .try_update_value(|state| {
+                    vim.try_update_value(|vim| {
+                        let response = vim.handle_key(VimKey::from_chord(&chord), state);
+                        vim_mode.set(vim.mode());
+                        mode_status.set(vim.status());
+                        response
+                    })
+                })
+                .flatten()
+                .unwrap_or(VimResponse::Ignored);
+
+            if response != VimResponse::Ignored {
+                ev.prevent_default();
+                finish_profile_key(version, response == VimResponse::Save);
+                return;
+            }
+        }
 
-                    // Restore cursor position
-                    #[allow(clippy::cast_possible_truncation)]
-                    let new_pos = (start + tab_size) as u32;
-                    let _ = target.set_selection_start(Some(new_pos));
-                    let _ = target.set_selection_end(Some(new_pos));
-                }
+        // Likewise for Emacs, which owns the Ctrl and Alt chords it binds
+        #[cfg(feature = "emacs")]
+        if key_profile == KeyProfile::Emacs
+            && let Some(chord) = KeyChord::from_event(&ev)
+        {
+            if !is_multi_cursor() {
+                sync_from_textarea(&event_target::<HtmlTextAreaElement>(&ev), false);
+            }
+            let version = editor_state.with_value(|state| state.version);
+            let response = editor_state
+                .try_update_value(|state| {
+                    emacs.try_update_value(|emacs| {
+                        let response = emacs.handle_key(&chord, state);
+                        mode_status.set(emacs.status());
+                        response
+                    })
+                })
+                .flatten()
+                .unwrap_or(EmacsResponse::Ignored);
+
+            if response != EmacsResponse::Ignored {
+                ev.prevent_default();
+                finish_profile_key(version, response == EmacsResponse::Save);
+                return;
             }
         }
 
-        // Undo: Ctrl+Z
-        if ctrl_or_cmd && key == "z" && !shift {
-            ev.prevent_default();
-            editor_state.update_value(|state| {
-                if state.undo()
-                    && let Some(callback) = on_change.as_ref()
-                {
-                    callback.run(state.content.clone());
+        // Key bindings, including multi-stroke sequences
+        if !read_only && let Some(chord) = KeyChord::from_event(&ev) {
+            let mut keys = pending_keys.get_value();
+            keys.push(chord);
+            match keymap.with_value(|keymap| keymap.lookup(&keys)) {
+                KeyLookup::Command(name) => {
+                    ev.prevent_default();
+                    pending_keys.set_value(Vec::new());
+                    run_command(&name);
+                    return;
                 }
-            });
+                KeyLookup::Prefix => {
+                    ev.prevent_default();
+                    pending_keys.set_value(keys);
+                    return;
+                }
+                // A key that breaks a pending sequence is swallowed
+                KeyLookup::NoMatch if keys.len() > 1 => {
+                    ev.prevent_default();
+                    pending_keys.set_value(Vec::new());
+                    return;
+                }
+                KeyLookup::NoMatch => {}
+            }
         }
 
-        // Redo: Ctrl+Shift+Z or Ctrl+Y
-        if ctrl_or_cmd && ((key == "z" && shift) || key == "y") {
-            ev.prevent_default();
-            editor_state.update_value(|state| {
-                if state.redo()
-                    && let Some(callback) = on_change.as_ref()
-                {
-                    callback.run(state.content.clone());
+        // Closing pairs are typed through the editor state, since the
+        // textarea only inserts the key itself
+        if !read_only && !is_multi_cursor() && !modal_cursor() {
+            let key = ev.key();
+            let plain = !ev.ctrl_key() && !ev.meta_key() && !ev.alt_key();
+            let mut chars = key.chars();
+            let pair_key = match (chars.next(), chars.next()) {
+                (Some(ch), None) => editor_state.with_value(|state| {
+                    state
+                        .language_kind()
+                        .auto_pairs()
+                        .iter()
+                        .any(|&(open, close)| ch == open || ch == close)
+                }),
+                _ => key == "Backspace",
+            };
+            if plain && pair_key {
+                sync_from_textarea(&event_target::<HtmlTextAreaElement>(&ev), false);
+                let handled = editor_state
+                    .try_update_value(|state| match key.chars().next() {
+                        _ if key == "Backspace" => state.delete_pair(),
+                        Some(ch) => state.auto_close(ch),
+                        None => false,
+                    })
+                    .unwrap_or(false);
+                if handled {
+                    ev.prevent_default();
+                    sync_to_textarea(Some(ChangeOrigin::Typing));
+                    return;
                 }
-            });
+            }
         }
 
-        // Select All: Ctrl+A
-        if ctrl_or_cmd && key == "a" {
-            // Let browser handle this
+        // With several cursors, typing goes through the editor state so that
+        // every cursor receives the edit
+        if is_multi_cursor() {
+            let key = ev.key();
+            let plain = !ev.ctrl_key() && !ev.meta_key() && !ev.alt_key();
+            let textarea = event_target::<HtmlTextAreaElement>(&ev);
+            let movement = key_movement(&ev, page_lines(&textarea, font_size));
+            let handled = editor_state
+                .try_update_value(|state| match key.as_str() {
+                    _ if movement.is_some() => {
+                        if let Some(movement) = movement {
+                            state.move_cursors(movement, ev.shift_key());
+                        }
+                        Some(false)
+                    }
+                    _ if read_only => None,
+                    "Backspace" if plain => {
+                        state.delete_backward();
+                        Some(true)
+                    }
+                    "Delete" if plain => {
+                        state.delete_forward();
+                        Some(true)
+                    }
+                    "Enter" if plain => {
+                        state.insert_newline();
+                        Some(true)
+                    }
+                    text if plain && text.chars().count() == 1 => {
+                        if !text.chars().next().is_some_and(|ch| state.auto_close(ch)) {
+                            state.insert(text);
+                        }
+                        Some(true)
+                    }
+                    _ => None,
+                })
+                .flatten();
+
+            if let Some(content_changed) = handled {
+                ev.prevent_default();
+                sync_to_textarea(content_changed.then_some(ChangeOrigin::Typing));
+            }
         }
     };
 
+    // Secondary cursors and other decorations, drawn behind the textarea
+    let overlay_view = move || {
+        decorations.with(|decorations| {
+            if decorations.is_empty() {
+                return None;
+            }
+            let segments = editor_state
+                .with_value(|state| overlay::document_segments(&state.buffer, decorations));
+            Some(overlay::render_segments(segments))
+        })
+    };
+
     view! {
       <div class=css_class style=inline_style>
         // Line numbers gutter
@@ -373,21 +982,54 @@ pub fn Editor(
 
         // Main editor area
         <div class="leptos-editor-content">
+          <div class="leptos-editor-overlay" aria-hidden="true">
+            {ruler_columns
+                .into_iter()
+                .map(|column| {
+                    view! {
+                      <div
+                        class="leptos-editor-ruler"
+                        style:left=format!("calc(12px + {column}ch)")
+                        style:transform=move || format!("translateX({}px)", -scroll.get().0)
+                      />
+                    }
+                })
+                .collect_view()}
+            <div
+              class="leptos-editor-overlay-content"
+              style:transform=move || {
+                  let (left, top) = scroll.get();
+                  format!("translate({}px, {}px)", -left, -top)
+              }
+            >
+              {overlay_view}
+            </div>
+          </div>
+
           <textarea
+            node_ref=textarea_ref
             id=id
             class="leptos-editor-textarea"
-            prop:value=move || value.get()
+            prop:value=editor_state.with_value(EditorState::content)
             placeholder=placeholder.clone().unwrap_or_default()
             readonly=read_only
             spellcheck="false"
             autocomplete="off"
             aria-label="Code editor"
             aria-multiline="true"
+            on:beforeinput=handle_beforeinput
             on:input=handle_input
             on:focus=handle_focus
             on:blur=handle_blur
             on:select=handle_select
             on:keydown=handle_keydown
+            on:keyup=handle_keyup
+            on:copy=handle_copy
+            on:cut=handle_cut
+            on:paste=handle_paste
+            on:mousedown=handle_mousedown
+            on:mouseup=handle_mouseup
+            on:scroll=handle_scroll
             autofocus=autofocus
           />
 
@@ -407,9 +1049,17 @@ pub fn Editor(
 
         // Status bar
         <div class="leptos-editor-status">
+          {move || {
+            let status = mode_status.get();
+            (!status.is_empty())
+              .then(|| view! { <span class="leptos-editor-status-mode">{status}</span> })
+          }}
           <span class="leptos-editor-status-position">
             "Ln " {move || cursor_line.get() + 1} ", Col " {move || cursor_col.get() + 1}
           </span>
+          <span class="leptos-editor-status-line-ending">
+            {move || line_ending.get().to_string()}
+          </span>
           {
             let language_for_status = language.clone();
             language_for_status
@@ -423,29 +1073,6 @@ pub fn Editor(
     }
 }
 
-/// Convert a byte offset to line and column (0-indexed).
-fn offset_to_line_col(text: &str, offset: usize) -> (usize, usize) {
-    let mut line = 0;
-    let mut col = 0;
-    let mut current_offset = 0;
-
-    for ch in text.chars() {
-        if current_offset >= offset {
-            break;
-        }
-        current_offset += ch.len_utf8();
-
-        if ch == '\n' {
-            line += 1;
-            col = 0;
-        } else {
-            col += 1;
-        }
-    }
-
-    (line, col)
-}
-
 /// Default CSS styles for the editor component.
 ///
 /// Include this in your application to get the default styling.
@@ -460,6 +1087,11 @@ pub const DEFAULT_STYLES: &str = r"
     --editor-gutter-bg: #1e1e1e;
     --editor-border: #3c3c3c;
     --editor-current-line-bg: rgba(255, 255, 255, 0.04);
+    --editor-bracket-match-bg: rgba(255, 255, 255, 0.1);
+    --editor-bracket-match-border: #888888;
+    --editor-whitespace-fg: rgba(255, 255, 255, 0.25);
+    --editor-trailing-whitespace-bg: rgba(255, 80, 80, 0.25);
+    --editor-ruler-color: rgba(255, 255, 255, 0.1);
     --editor-font-size: 14px;
     --editor-line-height: 1.5;
     --editor-tab-size: 4;
@@ -516,6 +1148,8 @@ pub const DEFAULT_STYLES: &str = r"
 }
 
 .leptos-editor-textarea {
+    position: relative;
+    z-index: 1;
     flex: 1;
     width: 100%;
     height: 100%;
@@ -532,6 +1166,7 @@ pub const DEFAULT_STYLES: &str = r"
     tab-size: var(--editor-tab-size);
     -moz-tab-size: var(--editor-tab-size);
     overflow: auto;
+    scrollbar-gutter: stable;
 }
 
 .leptos-editor-textarea::selection {
@@ -556,6 +1191,89 @@ pub const DEFAULT_STYLES: &str = r"
     background: #4f4f4f;
 }
 
+.leptos-editor-overlay {
+    position: absolute;
+    inset: 0;
+    padding: 8px 12px;
+    overflow: hidden;
+    pointer-events: none;
+    color: transparent;
+    white-space: pre-wrap;
+    overflow-wrap: break-word;
+    tab-size: var(--editor-tab-size);
+    -moz-tab-size: var(--editor-tab-size);
+    scrollbar-gutter: stable;
+}
+
+.leptos-editor-overlay .leptos-editor-cursor {
+    border-left: 2px solid var(--editor-cursor);
+    margin: 0 -1px;
+}
+
+.leptos-editor-overlay .leptos-editor-selection {
+    background: var(--editor-selection-bg);
+}
+
+.leptos-editor-overlay .leptos-editor-bracket-match {
+    background: var(--editor-bracket-match-bg);
+    outline: 1px solid var(--editor-bracket-match-border);
+}
+
+/* Whitespace glyphs are drawn over the invisible overlay text so the
+   layout matches the textarea. Runs of spaces and tabs are one span each;
+   a mask repeats the `·` or `→` glyph once per character or tab stop,
+   over a layer of the whitespace color */
+.leptos-editor-overlay .leptos-editor-ws-space,
+.leptos-editor-overlay .leptos-editor-ws-tab {
+    position: relative;
+}
+
+.leptos-editor-overlay .leptos-editor-ws-space::before,
+.leptos-editor-overlay .leptos-editor-ws-tab::before {
+    content: '';
+    position: absolute;
+    inset: 0;
+    background-color: var(--editor-whitespace-fg);
+    -webkit-mask-repeat: repeat-x;
+    mask-repeat: repeat-x;
+}
+
+.leptos-editor-overlay .leptos-editor-ws-space::before {
+    -webkit-mask-image: url('data:image/svg+xml,%3Csvg xmlns=%22http://www.w3.org/2000/svg%22 viewBox=%220 0 10 20%22%3E%3Ctext x=%225%22 text-anchor=%22middle%22 y=%2215%22 font-family=%22monospace%22 font-size=%2216%22%3E%C2%B7%3C/text%3E%3C/svg%3E');
+    mask-image: url('data:image/svg+xml,%3Csvg xmlns=%22http://www.w3.org/2000/svg%22 viewBox=%220 0 10 20%22%3E%3Ctext x=%225%22 text-anchor=%22middle%22 y=%2215%22 font-family=%22monospace%22 font-size=%2216%22%3E%C2%B7%3C/text%3E%3C/svg%3E');
+    -webkit-mask-size: 1ch 100%;
+    mask-size: 1ch 100%;
+}
+
+.leptos-editor-overlay .leptos-editor-ws-tab::before {
+    -webkit-mask-image: url('data:image/svg+xml,%3Csvg xmlns=%22http://www.w3.org/2000/svg%22 viewBox=%220 0 10 20%22 preserveAspectRatio=%22xMinYMid meet%22%3E%3Ctext x=%221%22 y=%2215%22 font-family=%22monospace%22 font-size=%2216%22%3E%E2%86%92%3C/text%3E%3C/svg%3E');
+    mask-image: url('data:image/svg+xml,%3Csvg xmlns=%22http://www.w3.org/2000/svg%22 viewBox=%220 0 10 20%22 preserveAspectRatio=%22xMinYMid meet%22%3E%3Ctext x=%221%22 y=%2215%22 font-family=%22monospace%22 font-size=%2216%22%3E%E2%86%92%3C/text%3E%3C/svg%3E');
+    -webkit-mask-size: calc(var(--editor-tab-size) * 1ch) 100%;
+    mask-size: calc(var(--editor-tab-size) * 1ch) 100%;
+}
+
+.leptos-editor-overlay .leptos-editor-ws-eol {
+    position: relative;
+}
+
+.leptos-editor-overlay .leptos-editor-ws-eol::before {
+    content: '\00AC';
+    position: absolute;
+    left: 0;
+    color: var(--editor-whitespace-fg);
+}
+
+.leptos-editor-overlay .leptos-editor-ws-trailing {
+    background-color: var(--editor-trailing-whitespace-bg);
+}
+
+.leptos-editor-ruler {
+    position: absolute;
+    top: 0;
+    bottom: 0;
+    border-left: 1px solid var(--editor-ruler-color);
+}
+
 .leptos-editor-placeholder {
     position: absolute;
     top: 8px;
@@ -588,6 +1306,15 @@ pub const DEFAULT_STYLES: &str = r"
     text-transform: capitalize;
 }
 
+.leptos-editor-status-mode {
+    font-weight: 600;
+}
+
+/* Vim normal and visual modes */
+.leptos-editor.vim-normal .leptos-editor-textarea {
+    caret-shape: block;
+}
+
 /* Light theme variant */
 .leptos-editor.light {
     --editor-bg: #ffffff;
@@ -607,6 +1334,10 @@ pub const DEFAULT_STYLES: &str = r"
     overflow-x: auto;
 }
 
+.leptos-editor:not(.word-wrap) .leptos-editor-overlay {
+    white-space: pre;
+}
+
 /* Accessibility: Respect reduced motion preference */
 @media (prefers-reduced-motion: reduce) {
     .leptos-editor,
@@ -615,3 +1346,60 @@ pub const DEFAULT_STYLES: &str = r"
     }
 }
 ";
+
+/// Convert an offset to the `u32` the DOM selection API expects.
+fn dom_offset(offset: usize) -> u32 {
+    u32::try_from(offset).unwrap_or(u32::MAX)
+}
+
+/// Read the textarea's selection as document positions.
+///
+/// The DOM reports UTF-16 code units.
+fn dom_selection(
+    state: &EditorState,
+    textarea: &HtmlTextAreaElement,
+) -> (CursorPosition, CursorPosition) {
+    let position = |utf16: Option<u32>| {
+        state
+            .offset_to_position(state.buffer.utf16_to_offset(utf16.unwrap_or(0) as usize))
+            .unwrap_or_default()
+    };
+    (
+        position(textarea.selection_start().ok().flatten()),
+        position(textarea.selection_end().ok().flatten()),
+    )
+}
+
+/// Map a navigation key to a cursor movement.
+///
+/// Ctrl or Alt moves by word; Cmd+Arrow moves to the line or document edge.
+fn key_movement(ev: &web_sys::KeyboardEvent, page_lines: usize) -> Option<Movement> {
+    let word = ev.ctrl_key() || ev.alt_key();
+    let edge = ev.ctrl_key() || ev.meta_key();
+    Some(match ev.key().as_str() {
+        "ArrowLeft" if ev.meta_key() => Movement::LineStart,
+        "ArrowRight" if ev.meta_key() => Movement::LineEnd,
+        "ArrowUp" if ev.meta_key() => Movement::DocumentStart,
+        "ArrowDown" if ev.meta_key() => Movement::DocumentEnd,
+        "ArrowLeft" if word => Movement::WordLeft,
+        "ArrowRight" if word => Movement::WordRight,
+        "ArrowLeft" => Movement::CharLeft,
+        "ArrowRight" => Movement::CharRight,
+        "ArrowUp" => Movement::LineUp,
+        "ArrowDown" => Movement::LineDown,
+        "Home" if edge => Movement::DocumentStart,
+        "End" if edge => Movement::DocumentEnd,
+        "Home" => Movement::LineStart,
+        "End" => Movement::LineEnd,
+        "PageUp" => Movement::PageUp(page_lines),
+        "PageDown" => Movement::PageDown(page_lines),
+        _ => return None,
+    })
+}
+
+/// Estimate how many lines fit in the textarea.
+#[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+fn page_lines(textarea: &HtmlTextAreaElement, font_size: f32) -> usize {
+    let line_height = f64::from(font_size) * 1.5;
+    (f64::from(textarea.client_height()) / line_height).max(1.0) as usize
+}
@@ -5,11 +5,15 @@
 use serde::{Deserialize, Serialize};
 
 /// A position in the document (line and column, both 0-indexed).
+///
+/// The column counts chars (Unicode scalar values) from the start of the
+/// line, not bytes, UTF-16 code units or grapheme clusters. Use the
+/// [`coords`](super::coords) module to convert between units.
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct CursorPosition {
     /// Line number (0-indexed)
     pub line: usize,
-    /// Column number (0-indexed, in characters)
+    /// Column number (0-indexed, in chars)
     pub column: usize,
 }
 
@@ -141,7 +145,7 @@ impl Cursor {
 }
 
 /// A set of cursors for multi-cursor support.
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct CursorSet {
     /// All active cursors (primary cursor is first)
     cursors: Vec<Cursor>,
@@ -206,6 +210,25 @@ impl CursorSet {
         }
     }
 
+    /// Move every cursor's head and anchor through a position mapping.
+    ///
+    /// Cursors that end up overlapping are merged.
+    pub fn map_positions(&mut self, mut f: impl FnMut(CursorPosition) -> CursorPosition) {
+        for cursor in &mut self.cursors {
+            cursor.head = f(cursor.head);
+            cursor.anchor = f(cursor.anchor);
+        }
+        self.merge_overlapping();
+    }
+
+    /// Update every cursor in place.
+    ///
+    /// Cursors that end up overlapping are merged.
+    pub fn map_cursors(&mut self, f: impl FnMut(&mut Cursor)) {
+        self.cursors.iter_mut().for_each(f);
+        self.merge_overlapping();
+    }
+
     /// Merge overlapping cursors/selections.
     fn merge_overlapping(&mut self) {
         if self.cursors.len() <= 1 {
@@ -0,0 +1,846 @@
+//! Emacs key bindings
+//!
+//! An opt-in Emacs profile. [`Emacs`] keeps the mark, the kill ring, the
+//! incremental search and the universal argument, and runs Emacs commands
+//! through the [`EditorState`] API.
+//!
+//! Keys that are not Emacs commands are left to the editor, so typing and
+//! the browser's own shortcuts (such as Cmd+C) keep working.
+
+use std::{collections::VecDeque, ops::Range};
+
+use super::{
+    cursor::{Cursor, CursorPosition, CursorSet},
+    find_replace::FindState,
+    keymap::KeyChord,
+    movement::Movement,
+    state::EditorState,
+    transaction::{ChangeSet, Transaction},
+};
+
+/// The most kills the kill ring keeps.
+const KILL_RING_MAX: usize = 120;
+
+/// What the editor should do after [`Emacs::handle_key`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmacsResponse {
+    /// Emacs used the key
+    Handled,
+    /// Emacs does not use the key; the editor should handle it as usual
+    Ignored,
+    /// Emacs used the key and the document should be saved (`C-x C-s`)
+    Save,
+}
+
+/// The Emacs state of one editor.
+#[derive(Debug, Clone, Default)]
+pub struct Emacs {
+    /// Killed text, most recent first
+    kill_ring: VecDeque<String>,
+    /// The mark, if one has been set
+    mark: Option<CursorPosition>,
+    /// Whether the region between the mark and the cursor is active
+    mark_active: bool,
+    /// The universal argument being typed (`C-u`)
+    argument: Option<Argument>,
+    /// Whether `C-x` was pressed
+    prefix: bool,
+    /// The incremental search in progress
+    search: Option<Search>,
+    /// The last search query, reused by `C-s C-s`
+    last_query: String,
+    /// The kind of the previous command, for appending kills and `M-y`
+    last: Last,
+    /// The kill ring entry and length of the last yank
+    yank: Option<(usize, usize)>,
+    /// A message for the status bar, cleared by the next key
+    message: Option<String>,
+}
+
+/// A universal argument: `C-u` multiplies it by four, and digits after
+/// `C-u` replace it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Argument {
+    value: usize,
+    digits: Option<usize>,
+}
+
+impl Argument {
+    fn count(self) -> usize {
+        self.digits.unwrap_or(self.value)
+    }
+}
+
+/// An incremental search.
+#[derive(Debug, Clone)]
+struct Search {
+    find: FindState,
+    forward: bool,
+    /// The cursor when the search started
+    origin: Cursor,
+    /// Whether the query has no match in the search direction
+    failing: bool,
+    /// Earlier (query, match index, forward, failing) states, restored one
+    /// at a time by DEL
+    steps: Vec<(String, usize, bool, bool)>,
+}
+
+impl Search {
+    /// Remember the current state for DEL.
+    fn save_step(&mut self) {
+        self.steps.push((
+            self.find.query.clone(),
+            self.find.current_index,
+            self.forward,
+            self.failing,
+        ));
+    }
+
+    /// Add text to the query and search again from the current match.
+    fn extend_query(&mut self, text: &str, state: &EditorState) {
+        self.save_step();
+        self.find.query.push_str(text);
+        research(self, state);
+    }
+}
+
+/// The kind of the previous command.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum Last {
+    #[default]
+    Other,
+    Kill,
+    Yank,
+}
+
+impl Emacs {
+    /// Create an Emacs state with an empty kill ring.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Iterate over the kill ring, most recent kill first.
+    pub fn kill_ring(&self) -> impl Iterator<Item = &str> {
+        self.kill_ring.iter().map(String::as_str)
+    }
+
+    /// Check if the region between the mark and the cursor is active.
+    #[must_use]
+    pub fn is_mark_active(&self) -> bool {
+        self.mark_active
+    }
+
+    /// Get the text for the status bar: the search, a pending prefix or
+    /// argument, or a message. Empty if there is nothing to show.
+    #[must_use]
+    pub fn status(&self) -> String {
+        if let Some(search) = &self.search {
+            return format!(
+                "{}I-search{}: {}",
+                if search.failing { "Failing " } else { "" },
+                if search.forward { "" } else { " backward" },
+                search.find.query
+            );
+        }
+        if let Some(message) = &self.message {
+            return message.clone();
+        }
+        if self.prefix {
+            return "C-x-".to_string();
+        }
+        self.argument
+            .map(|argument| format!("C-u {}-", argument.count()))
+            .unwrap_or_default()
+    }
+
+    /// Handle a key press.
+    pub fn handle_key(&mut self, chord: &KeyChord, state: &mut EditorState) -> EmacsResponse {
+        let Some(name) = key_name(chord) else {
+            return EmacsResponse::Ignored;
+        };
+        self.message = None;
+        let previous = std::mem::take(&mut self.last);
+
+        if self.search.is_some()
+            && let Some(response) = self.search_key(&name, state)
+        {
+            return response;
+        }
+        if std::mem::take(&mut self.prefix) {
+            self.argument = None;
+            return self.prefixed(&name, state);
+        }
+
+        if name == "C-u" {
+            let value = match self.argument {
+                Some(argument) if argument.digits.is_none() => argument.value.saturating_mul(4),
+                _ => 4,
+            };
+            self.argument = Some(Argument {
+                value,
+                digits: None,
+            });
+            self.last = previous;
+            return EmacsResponse::Handled;
+        }
+        if let Some(argument) = &mut self.argument
+            && let Some(digit) = name.chars().next().and_then(|c| c.to_digit(10))
+            && name.len() == 1
+        {
+            let digits = argument.digits.unwrap_or(0);
+            argument.digits = Some(digits.saturating_mul(10).saturating_add(digit as usize));
+            self.last = previous;
+            return EmacsResponse::Handled;
+        }
+
+        let count = self.argument.take().map(Argument::count);
+        self.command(&name, count, previous, state)
+    }
+
+    /// Run a command that is not part of a search or a `C-x` sequence.
+    #[allow(clippy::too_many_lines)]
+    fn command(
+        &mut self,
+        name: &str,
+        count: Option<usize>,
+        previous: Last,
+        state: &mut EditorState,
+    ) -> EmacsResponse {
+        let n = count.unwrap_or(1);
+        match name {
+            "C-f" => self.move_by(state, Movement::CharRight, n),
+            "C-b" => self.move_by(state, Movement::CharLeft, n),
+            "C-n" => self.move_by(state, Movement::LineDown, n),
+            "C-p" => self.move_by(state, Movement::LineUp, n),
+            "M-f" => self.move_by(state, Movement::WordRight, n),
+            "M-b" => self.move_by(state, Movement::WordLeft, n),
+            "C-e" => self.move_by(state, Movement::LineEnd, 1),
+            "M-<" => self.move_by(state, Movement::DocumentStart, 1),
+            "M->" => self.move_by(state, Movement::DocumentEnd, 1),
+            "C-a" => {
+                let extend = self.mark_active;
+                state.cursors.map_cursors(|cursor| {
+                    cursor.move_to(CursorPosition::new(cursor.head.line, 0), extend);
+                    cursor.clear_preferred_column();
+                });
+            }
+            "C-SPC" | "C-@" if count.is_some() => {
+                // C-u C-SPC jumps to the mark
+                if let Some(mark) = self.mark {
+                    let mark = state.clamp_position(mark);
+                    self.mark = Some(state.cursor_position());
+                    state.cursors = CursorSet::new(Cursor::new(mark));
+                }
+                self.mark_active = false;
+            }
+            "C-SPC" | "C-@" => {
+                state.cursors.map_cursors(Cursor::collapse);
+                self.mark = Some(state.cursor_position());
+                self.mark_active = true;
+                self.message = Some("Mark set".to_string());
+            }
+            "C-g" => {
+                self.deactivate_mark(state);
+                self.message = Some("Quit".to_string());
+            }
+            "C-d" => {
+                self.deactivate_mark(state);
+                state.history.checkpoint();
+                (0..n).for_each(|_| state.delete_forward());
+            }
+            "C-k" => {
+                let ranges =
+                    cursor_ranges(state, |state, cursor| kill_line_range(state, cursor, count));
+                self.kill(state, &ranges, false, previous);
+            }
+            "M-d" | "M-DEL" => {
+                let forward = name == "M-d";
+                let ranges = cursor_ranges(state, |state, cursor| {
+                    kill_word_range(state, cursor, forward, n)
+                });
+                self.kill(state, &ranges, !forward, previous);
+            }
+            "C-w" | "M-w" => {
+                let ranges = self.region_ranges(state);
+                if ranges.is_empty() {
+                    self.message =
+                        Some("The mark is not set now, so there is no region".to_string());
+                } else if name == "C-w" {
+                    self.kill(state, &ranges, false, previous);
+                } else {
+                    self.copy(state, &ranges, previous);
+                    self.deactivate_mark(state);
+                }
+            }
+            "M-q" => {
+                state.reflow_paragraph();
+                self.deactivate_mark(state);
+            }
+            "C-y" => self.yank(state, n),
+            "M-y" => self.yank_pop(state, n, previous),
+            "C-/" | "C-_" => undo(state, n),
+            "C-x" => self.prefix = true,
+            "C-s" | "C-r" => {
+                state.clear_secondary_cursors();
+                self.search = Some(Search {
+                    find: FindState::new(),
+                    forward: name == "C-s",
+                    origin: *state.cursors.primary(),
+                    failing: false,
+                    steps: Vec::new(),
+                });
+            }
+            _ => {
+                let text = match name {
+                    "SPC" => Some(" "),
+                    "RET" => Some("\n"),
+                    name if name.chars().count() == 1 => Some(name),
+                    _ => None,
+                };
+                match (text, count) {
+                    (Some(text), Some(n)) => {
+                        state.history.checkpoint();
+                        state.insert(&text.repeat(n));
+                    }
+                    (Some(_), None) => {
+                        self.mark_active = false;
+                        return EmacsResponse::Ignored;
+                    }
+                    (None, _) => return EmacsResponse::Ignored,
+                }
+            }
+        }
+        EmacsResponse::Handled
+    }
+
+    /// Run the command after `C-x`.
+    fn prefixed(&mut self, name: &str, state: &mut EditorState) -> EmacsResponse {
+        match name {
+            "C-s" => return EmacsResponse::Save,
+            "u" => undo(state, 1),
+            "h" => {
+                state.select_all();
+                self.mark = Some(CursorPosition::zero());
+                self.mark_active = true;
+            }
+            "C-x" => {
+                let Some(mark) = self.mark else {
+                    self.message = Some("No mark set in this buffer".to_string());
+                    return EmacsResponse::Handled;
+                };
+                let head = state.cursor_position();
+                let mark = state.clamp_position(mark);
+                state.cursors = CursorSet::new(Cursor::with_selection(mark, head));
+                self.mark = Some(head);
+                self.mark_active = true;
+            }
+            "C-g" => self.message = Some("Quit".to_string()),
+            name => self.message = Some(format!("C-x {name} is undefined")),
+        }
+        EmacsResponse::Handled
+    }
+
+    /// Handle a key during an incremental search.
+    ///
+    /// Returns `None` if the key ends the search and should then run as an
+    /// ordinary command.
+    fn search_key(&mut self, name: &str, state: &mut EditorState) -> Option<EmacsResponse> {
+        let search = self.search.as_mut()?;
+        match name {
+            "C-s" | "C-r" => {
+                let forward = name == "C-s";
+                search.save_step();
+                if search.find.query.is_empty() {
+                    search.find.query.clone_from(&self.last_query);
+                    search.forward = forward;
+                    research(search, state);
+                } else if search.forward != forward {
+                    search.forward = forward;
+                    search.failing = false;
+                } else {
+                    let found = if forward {
+                        search.find.next()
+                    } else {
+                        search.find.prev()
+                    };
+                    search.failing = found.is_none();
+                }
+                show_match(search, state);
+            }
+            "DEL" => {
+                if let Some((query, index, forward, failing)) = search.steps.pop() {
+                    if query != search.find.query {
+                        search.find.query = query;
+                        search.find.search(&state.content());
+                    }
+                    search.find.current_index = index;
+                    search.forward = forward;
+                    search.failing = failing;
+                }
+                show_match(search, state);
+            }
+            "C-g" => {
+                state.cursors = CursorSet::new(search.origin);
+                self.search = None;
+                self.message = Some("Quit".to_string());
+            }
+            "RET" | "ESC" => self.finish_search(state),
+            "SPC" => {
+                search.extend_query(" ", state);
+                show_match(search, state);
+            }
+            name if name.chars().count() == 1 => {
+                search.extend_query(name, state);
+                show_match(search, state);
+            }
+            _ => {
+                self.finish_search(state);
+                return None;
+            }
+        }
+        Some(EmacsResponse::Handled)
+    }
+
+    /// End the search at the current match, setting the mark where the
+    /// search started.
+    fn finish_search(&mut self, state: &mut EditorState) {
+        let Some(search) = self.search.take() else {
+            return;
+        };
+        if !search.find.query.is_empty() {
+            self.last_query = search.find.query;
+        }
+        state.cursors.map_cursors(Cursor::collapse);
+        self.mark = Some(search.origin.head);
+        self.mark_active = false;
+    }
+
+    /// Move every cursor, extending the region if the mark is active.
+    fn move_by(&self, state: &mut EditorState, movement: Movement, count: usize) {
+        for _ in 0..count {
+            state.move_cursors(movement, self.mark_active);
+        }
+    }
+
+    fn deactivate_mark(&mut self, state: &mut EditorState) {
+        self.mark_active = false;
+        state.cursors.map_cursors(Cursor::collapse);
+    }
+
+    /// Get the region at each cursor: its selection, or the text between
+    /// the mark and a single cursor.
+    fn region_ranges(&self, state: &EditorState) -> Vec<Range<usize>> {
+        let mark = self
+            .mark
+            .filter(|_| !state.cursors.is_multi())
+            .map(|mark| state.clamp_position(mark));
+        cursor_ranges(state, |state, cursor| {
+            let (start, end) = if cursor.has_selection() {
+                (cursor.selection_start(), cursor.selection_end())
+            } else {
+                let mark = mark?;
+                (cursor.head.min(mark), cursor.head.max(mark))
+            };
+            Some(state.position_to_offset(start)?..state.position_to_offset(end)?)
+        })
+    }
+
+    /// Delete the ranges and put their text on the kill ring.
+    fn kill(
+        &mut self,
+        state: &mut EditorState,
+        ranges: &[Range<usize>],
+        backward: bool,
+        previous: Last,
+    ) {
+        self.mark_active = false;
+        if !self.copy_to_ring(state, ranges, backward, previous) {
+            return;
+        }
+
+        let mut edits: Vec<_> = ranges.iter().map(|range| (range.clone(), "")).collect();
+        edits.sort_by_key(|(range, _)| (range.start, range.end));
+        let mut end = 0;
+        edits.retain(|(range, _)| {
+            let keep = range.start >= end;
+            end = end.max(range.end);
+            keep
+        });
+        state.history.checkpoint();
+        let changes = ChangeSet::from_edits(state.buffer.len(), edits);
+        state.apply(&Transaction::new(changes));
+        state.cursors.map_cursors(Cursor::collapse);
+    }
+
+    /// Put the text of the ranges on the kill ring without deleting it.
+    fn copy(&mut self, state: &EditorState, ranges: &[Range<usize>], previous: Last) {
+        self.copy_to_ring(state, ranges, false, previous);
+    }
+
+    /// Add the text of the ranges to the kill ring, joined by line breaks.
+    ///
+    /// Right after another kill, the text is added to the previous kill
+    /// instead: after it, or before it for a backward kill. Returns `false`
+    /// if there is no text.
+    fn copy_to_ring(
+        &mut self,
+        state: &EditorState,
+        ranges: &[Range<usize>],
+        backward: bool,
+        previous: Last,
+    ) -> bool {
+        let text = ranges
+            .iter()
+            .filter_map(|range| state.buffer.slice(range.clone()))
+            .collect::<Vec<_>>()
+            .join("\n");
+        if text.is_empty() {
+            return false;
+        }
+
+        self.last = Last::Kill;
+        match self.kill_ring.front_mut() {
+            Some(front) if previous == Last::Kill && backward => front.insert_str(0, &text),
+            Some(front) if previous == Last::Kill => front.push_str(&text),
+            _ => {
+                self.kill_ring.push_front(text);
+                self.kill_ring.truncate(KILL_RING_MAX);
+            }
+        }
+        true
+    }
+
+    /// Insert the `n`th most recent kill at every cursor (`C-y`).
+    ///
+    /// A count of 0 yanks the most recent kill, like 1.
+    fn yank(&mut self, state: &mut EditorState, n: usize) {
+        let index = n.saturating_sub(1);
+        let Some(text) = self.kill_ring.get(index).cloned() else {
+            self.message = Some("Kill ring is empty".to_string());
+            return;
+        };
+        self.mark = Some(state.cursor_position());
+        self.mark_active = false;
+        state.history.checkpoint();
+        state.insert(&text);
+        self.yank = Some((index, text.len()));
+        self.last = Last::Yank;
+    }
+
+    /// Replace the text just yanked with an older kill (`M-y`).
+    fn yank_pop(&mut self, state: &mut EditorState, n: usize, previous: Last) {
+        let Some((index, len)) = self.yank.filter(|_| previous == Last::Yank) else {
+            self.message = Some("Previous command was not a yank".to_string());
+            return;
+        };
+        let index = (index + n) % self.kill_ring.len();
+        let text = self.kill_ring[index].clone();
+
+        // Select the previous yank at each cursor, then type over it
+        let buffer = &state.buffer;
+        state.cursors.map_cursors(|cursor| {
+            let start = buffer
+                .line_col_to_offset(cursor.head.line, cursor.head.column)
+                .and_then(|end| end.checked_sub(len))
+                .and_then(|start| buffer.offset_to_line_col(start));
+            if let Some((line, column)) = start {
+                cursor.anchor = CursorPosition::new(line, column);
+            }
+        });
+        state.insert(&text);
+        self.yank = Some((index, text.len()));
+        self.last = Last::Yank;
+    }
+}
+
+/// Get the Emacs name of a chord, such as `C-x`, `M-<` or `C-SPC`.
+///
+/// Returns `None` for chords with Meta (Cmd on macOS), which belong to the
+/// browser; Alt is Emacs' Meta.
+fn key_name(chord: &KeyChord) -> Option<String> {
+    if chord.meta {
+        return None;
+    }
+    let key = match chord.key.as_str() {
+        " " => "SPC".to_string(),
+        "Backspace" => "DEL".to_string(),
+        "Enter" => "RET".to_string(),
+        "Escape" => "ESC".to_string(),
+        "Tab" => "TAB".to_string(),
+        key if chord.shift && key.chars().count() == 1 => key.to_uppercase(),
+        key => key.to_string(),
+    };
+    let ctrl = if chord.ctrl { "C-" } else { "" };
+    let alt = if chord.alt { "M-" } else { "" };
+    Some(format!("{ctrl}{alt}{key}"))
+}
+
+/// Get a byte range at each cursor.
+fn cursor_ranges(
+    state: &EditorState,
+    range: impl Fn(&EditorState, &Cursor) -> Option<Range<usize>>,
+) -> Vec<Range<usize>> {
+    state
+        .cursors
+        .all()
+        .iter()
+        .filter_map(|cursor| range(state, cursor))
+        .collect()
+}
+
+/// Get the range `C-k` kills from a cursor: the rest of the line, or the
+/// line break if only whitespace is left. With a count, kill that many
+/// lines including their line breaks.
+fn kill_line_range(
+    state: &EditorState,
+    cursor: &Cursor,
+    count: Option<usize>,
+) -> Option<Range<usize>> {
+    let buffer = &state.buffer;
+    let head = state.position_to_offset(cursor.head)?;
+    let line = cursor.head.line;
+    let end = if let Some(n) = count {
+        buffer.line_start(line + n).unwrap_or(buffer.len())
+    } else {
+        let end = buffer.line_range(line)?.end;
+        let rest = buffer.slice(head..end)?;
+        if rest.trim().is_empty() {
+            buffer.next_char_boundary(end)
+        } else {
+            end
+        }
+    };
+    Some(head..end)
+}
+
+/// Get the range `M-d` or `M-DEL` kills from a cursor: `n` words forward
+/// or backward.
+fn kill_word_range(
+    state: &EditorState,
+    cursor: &Cursor,
+    forward: bool,
+    n: usize,
+) -> Option<Range<usize>> {
+    let movement = if forward {
+        Movement::WordRight
+    } else {
+        Movement::WordLeft
+    };
+    let mut target = Cursor::new(cursor.head);
+    (0..n).for_each(|_| movement.apply(&state.buffer, &mut target, false));
+    let head = state.position_to_offset(cursor.head)?;
+    let target = state.position_to_offset(target.head)?;
+    Some(head.min(target)..head.max(target))
+}
+
+/// Search again after the query changed, starting at the current match
+/// or, if there is none yet, where the search started.
+fn research(search: &mut Search, state: &EditorState) {
+    let find = &mut search.find;
+    let current = find.current_match().filter(|_| !search.failing);
+    // Like Emacs, an all-lowercase query ignores case
+    find.options.case_sensitive = find.query.chars().any(char::is_uppercase);
+    find.search(&state.content());
+
+    let index = if let Some(current) = current {
+        if search.forward {
+            find.matches.iter().position(|m| m.start >= current.start)
+        } else {
+            find.matches.iter().rposition(|m| m.start <= current.start)
+        }
+    } else {
+        let origin = state.position_to_offset(search.origin.head).unwrap_or(0);
+        if search.forward {
+            find.matches.iter().position(|m| m.start >= origin)
+        } else {
+            find.matches.iter().rposition(|m| m.start < origin)
+        }
+    };
+    search.failing = index.is_none() && !find.query.is_empty();
+    if let Some(index) = index {
+        find.current_index = index;
+    }
+}
+
+/// Select the current match: the cursor ends after it when searching
+/// forward and before it when searching backward.
+fn show_match(search: &Search, state: &mut EditorState) {
+    if search.failing {
+        return;
+    }
+    let Some(found) = search.find.current_match() else {
+        state.cursors = CursorSet::new(search.origin);
+        return;
+    };
+    let (Some(start), Some(end)) = (
+        state.offset_to_position(found.start),
+        state.offset_to_position(found.end),
+    ) else {
+        return;
+    };
+    let cursor = if search.forward {
+        Cursor::with_selection(end, start)
+    } else {
+        Cursor::with_selection(start, end)
+    };
+    state.cursors = CursorSet::new(cursor);
+}
+
+fn undo(state: &mut EditorState, count: usize) {
+    for _ in 0..count {
+        if !state.undo() {
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parse keys in Emacs notation, e.g. `"C-a M-f C-SPC"`.
+    fn chords(keys: &str) -> Vec<KeyChord> {
+        keys.split(' ')
+            .map(|name| {
+                let mut chord = KeyChord::new("a");
+                let mut key = name;
+                loop {
+                    if let Some(rest) = key.strip_prefix("C-").filter(|rest| !rest.is_empty()) {
+                        chord.ctrl = true;
+                        key = rest;
+                    } else if let Some(rest) =
+                        key.strip_prefix("M-").filter(|rest| !rest.is_empty())
+                    {
+                        chord.alt = true;
+                        key = rest;
+                    } else {
+                        break;
+                    }
+                }
+                chord.shift = key.chars().count() == 1 && key.chars().all(char::is_uppercase);
+                chord.key = KeyChord::new(match key {
+                    "SPC" => " ",
+                    "DEL" => "Backspace",
+                    "RET" => "Enter",
+                    key => key,
+                })
+                .key;
+                chord
+            })
+            .collect()
+    }
+
+    /// Feed keys, typing plain characters that Emacs leaves to the editor.
+    fn feed(emacs: &mut Emacs, state: &mut EditorState, keys: &str) -> Vec<EmacsResponse> {
+        chords(keys)
+            .iter()
+            .map(|chord| {
+                let response = emacs.handle_key(chord, state);
+                if response == EmacsResponse::Ignored && !chord.ctrl && !chord.alt {
+                    let text = if chord.shift {
+                        chord.key.to_uppercase()
+                    } else {
+                        chord.key.clone()
+                    };
+                    state.insert(&text);
+                }
+                response
+            })
+            .collect()
+    }
+
+    fn head(state: &EditorState) -> (usize, usize) {
+        let head = state.cursor_position();
+        (head.line, head.column)
+    }
+
+    #[test]
+    fn test_motions_mark_and_region() {
+        let mut emacs = Emacs::new();
+        let mut state = EditorState::new("hello brave world\nsecond line");
+
+        feed(&mut emacs, &mut state, "C-e C-n C-a");
+        assert_eq!(head(&state), (1, 0));
+        feed(&mut emacs, &mut state, "M-< M-f C-f");
+        assert_eq!(head(&state), (0, 6));
+
+        feed(&mut emacs, &mut state, "C-SPC M-f");
+        assert!(emacs.is_mark_active());
+        assert_eq!(state.selected_text().as_deref(), Some("brave"));
+        feed(&mut emacs, &mut state, "C-w");
+        assert_eq!(state.content(), "hello  world\nsecond line");
+        assert!(!emacs.is_mark_active());
+
+        feed(&mut emacs, &mut state, "M-> C-y");
+        assert_eq!(state.content(), "hello  world\nsecond linebrave");
+        feed(&mut emacs, &mut state, "C-x C-x");
+        assert_eq!(state.selected_text().as_deref(), Some("brave"));
+        feed(&mut emacs, &mut state, "M-w C-g");
+        assert_eq!(emacs.kill_ring().collect::<Vec<_>>(), ["brave", "brave"]);
+    }
+
+    #[test]
+    fn test_kill_ring_and_universal_argument() {
+        let mut emacs = Emacs::new();
+        let mut state = EditorState::new("one\ntwo\nthree\nfour");
+
+        // Consecutive kills build a single kill ring entry
+        feed(&mut emacs, &mut state, "C-k C-k C-k C-k");
+        assert_eq!(state.content(), "three\nfour");
+        assert_eq!(emacs.kill_ring().next(), Some("one\ntwo\n"));
+
+        feed(&mut emacs, &mut state, "C-g C-u 2 C-k");
+        assert_eq!(state.content(), "");
+        assert_eq!(emacs.kill_ring().count(), 2);
+
+        feed(&mut emacs, &mut state, "C-y M-y");
+        assert_eq!(state.content(), "one\ntwo\n");
+        feed(&mut emacs, &mut state, "M-y");
+        assert_eq!(state.content(), "three\nfour");
+        feed(&mut emacs, &mut state, "C-f M-y");
+        assert_eq!(emacs.status(), "Previous command was not a yank");
+        feed(&mut emacs, &mut state, "C-u 0 C-y");
+        assert_eq!(state.content(), "three\nfourthree\nfour");
+        feed(&mut emacs, &mut state, "C-/");
+        assert_eq!(state.content(), "three\nfour");
+
+        feed(&mut emacs, &mut state, "M-< C-u 1 C-n");
+        assert_eq!(head(&state), (1, 0));
+        feed(&mut emacs, &mut state, "C-u 3 x");
+        assert_eq!(state.content(), "three\nxxxfour");
+        feed(&mut emacs, &mut state, "C-/");
+        assert_eq!(state.content(), "three\nfour");
+    }
+
+    #[test]
+    fn test_incremental_search() {
+        let mut emacs = Emacs::new();
+        let mut state = EditorState::new("foo bar\nFoo baz\nfoo");
+
+        feed(&mut emacs, &mut state, "C-s f o");
+        assert_eq!(emacs.status(), "I-search: fo");
+        assert_eq!(head(&state), (0, 2));
+        feed(&mut emacs, &mut state, "C-s");
+        assert_eq!(head(&state), (1, 2));
+        feed(&mut emacs, &mut state, "x");
+        assert_eq!(emacs.status(), "Failing I-search: fox");
+        feed(&mut emacs, &mut state, "DEL RET");
+        assert_eq!(emacs.status(), "");
+        assert_eq!(head(&state), (1, 2));
+        assert_eq!(state.selected_text(), None);
+
+        feed(&mut emacs, &mut state, "C-r C-r");
+        assert_eq!(emacs.status(), "I-search backward: fo");
+        assert_eq!(head(&state), (1, 0));
+        feed(&mut emacs, &mut state, "C-g");
+        assert_eq!(head(&state), (1, 2));
+
+        feed(&mut emacs, &mut state, "C-s b a C-e");
+        assert_eq!(head(&state), (1, 7));
+        assert_eq!(
+            feed(&mut emacs, &mut state, "C-x C-s"),
+            [EmacsResponse::Handled, EmacsResponse::Save]
+        );
+    }
+}
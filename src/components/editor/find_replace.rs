@@ -6,6 +6,8 @@
 use regex::Regex;
 use serde::{Deserialize, Serialize};
 
+use super::transaction::{Assoc, ChangeSet};
+
 /// Options for find operations.
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct FindOptions {
@@ -240,6 +242,29 @@ impl FindState {
         result
     }
 
+    /// Remap match offsets through an edit to the document.
+    ///
+    /// Matches touched by the edit are dropped, since their text may no
+    /// longer match the query. The current match is kept if it survives.
+    pub fn map_through(&mut self, changes: &ChangeSet) {
+        let mut current_index = 0;
+        let mut mapped = Vec::with_capacity(self.matches.len());
+
+        for (i, m) in self.matches.iter().enumerate() {
+            if i == self.current_index {
+                current_index = mapped.len();
+            }
+            if changes.touches(m.start..m.end) {
+                continue;
+            }
+            let start = changes.map_position(m.start, Assoc::After);
+            mapped.push(FindResult::new(start, start + m.len()));
+        }
+
+        self.matches = mapped;
+        self.current_index = current_index.min(self.matches.len().saturating_sub(1));
+    }
+
     /// Show the find panel.
     pub fn show(&mut self) {
         self.is_visible = true;
@@ -310,4 +335,22 @@ mod tests {
         let result = state.replace_all("old and old");
         assert_eq!(result, "new and new");
     }
+
+    #[test]
+    fn test_map_through_edit() {
+        let mut state = FindState::new();
+        state.query = "ab".to_string();
+        state.search("ab ab ab");
+        state.current_index = 2;
+
+        // Insert before the first match and edit inside the second one
+        let changes = ChangeSet::from_edits(8, [(0..0, "xx"), (4..4, "-")]);
+        state.map_through(&changes);
+
+        assert_eq!(
+            state.matches,
+            vec![FindResult::new(2, 4), FindResult::new(9, 11)]
+        );
+        assert_eq!(state.current_match(), Some(FindResult::new(9, 11)));
+    }
 }
@@ -6,6 +6,11 @@ use std::collections::HashMap;
 
 use serde::{Deserialize, Serialize};
 
+use super::{
+    buffer::TextBuffer,
+    transaction::{Assoc, ChangeSet},
+};
+
 /// Type of foldable region.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum FoldKind {
@@ -245,6 +250,24 @@ impl FoldState {
         self.is_dirty
     }
 
+    /// Remap region lines through an edit to the document.
+    ///
+    /// `before` and `after` are the document text before and after the
+    /// change. Regions that collapse to a single line are dropped.
+    pub fn map_through(&mut self, changes: &ChangeSet, before: &TextBuffer, after: &TextBuffer) {
+        self.regions.retain(|_, region| {
+            let (Some(start), Some(end)) = (
+                before.line_start(region.start_line),
+                before.line_range(region.end_line),
+            ) else {
+                return false;
+            };
+            region.start_line = after.line_of_offset(changes.map_position(start, Assoc::After));
+            region.end_line = after.line_of_offset(changes.map_position(end.end, Assoc::Before));
+            region.end_line > region.start_line
+        });
+    }
+
     /// Get the number of fold regions.
     #[must_use]
     pub fn region_count(&self) -> usize {
@@ -408,4 +431,22 @@ mod tests {
 
         assert!(state.region_count() > 0);
     }
+
+    #[test]
+    fn test_map_through_edit() {
+        let before = TextBuffer::from("# A\ntext\n# B\nmore\nend");
+        let mut state = FoldState::new();
+        let first = state.add_region(0, 1, FoldKind::Heading(1));
+        let second = state.add_region(2, 4, FoldKind::Heading(1));
+
+        // Insert two lines at the top and delete the body of the first region
+        let changes = ChangeSet::from_edits(before.len(), [(0..0, "x\ny\n"), (3..8, "")]);
+        let mut after = before.clone();
+        changes.apply(&mut after);
+        state.map_through(&changes, &before, &after);
+
+        assert!(state.get_region(first).is_none());
+        let region = state.get_region(second).expect("region survives");
+        assert_eq!((region.start_line, region.end_line), (3, 5));
+    }
 }
@@ -0,0 +1,228 @@
+//! Imperative editor handle
+//!
+//! An [`EditorHandle`] lets toolbars, menus and other components outside an
+//! [`Editor`](super::Editor) drive it directly instead of going through the
+//! `value` signal.
+
+use std::ops::Range;
+
+use leptos::{html, prelude::*};
+
+use super::{state::EditorState, transaction::ChangeOrigin};
+
+/// What a mounted editor hands to its [`EditorHandle`].
+#[derive(Clone, Copy)]
+pub(crate) struct EditorController {
+    /// The editor's state
+    pub state: StoredValue<EditorState>,
+    /// The editor's textarea
+    pub textarea: NodeRef<html::Textarea>,
+    /// Write the state out to the textarea, with what changed the content
+    /// if anything did
+    pub sync: Callback<Option<ChangeOrigin>>,
+    /// Run a registered command; returns `false` if there is none by that name
+    pub run_command: Callback<String, bool>,
+    /// Height of one line in pixels
+    pub line_height: f64,
+}
+
+/// A handle for controlling an [`Editor`](super::Editor) from outside.
+///
+/// Create one with [`EditorHandle::new`] and pass it to the editor's
+/// `handle` prop, much like a `NodeRef`. Until the editor is mounted every
+/// method does nothing and returns `false` or `None`.
+///
+/// ```rust,ignore
+/// let handle = EditorHandle::new();
+///
+/// view! {
+///     <button on:click=move |_| { handle.undo(); }>"Undo"</button>
+///     <Editor value=content handle=handle />
+/// }
+/// ```
+#[derive(Clone, Copy)]
+pub struct EditorHandle {
+    controller: RwSignal<Option<EditorController>>,
+}
+
+impl Default for EditorHandle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// The handle is `Copy` and edits through shared state, so `&self` methods
+// returning `bool` still have side effects
+#[allow(clippy::must_use_candidate)]
+impl EditorHandle {
+    /// Create a handle that is not attached to an editor yet.
+    #[must_use]
+    pub fn new() -> Self {
+        Self {
+            controller: RwSignal::new(None),
+        }
+    }
+
+    /// Attach the handle to a mounted editor.
+    pub(crate) fn attach(&self, controller: EditorController) {
+        self.controller.set(Some(controller));
+    }
+
+    /// Detach the handle when its editor is unmounted.
+    pub(crate) fn detach(&self) {
+        self.controller.try_set(None);
+    }
+
+    /// Check whether the handle is attached to an editor.
+    ///
+    /// This is reactive, so it can be used to enable controls once the
+    /// editor has mounted.
+    #[must_use]
+    pub fn is_attached(&self) -> bool {
+        self.controller.with(Option::is_some)
+    }
+
+    /// Update the state and write the result out to the textarea.
+    ///
+    /// `origin` is reported for any change to the content.
+    fn edit<R>(&self, origin: ChangeOrigin, edit: impl FnOnce(&mut EditorState) -> R) -> Option<R> {
+        let controller = self.controller.get_untracked()?;
+        let version = controller.state.with_value(|state| state.version);
+        let result = controller.state.try_update_value(edit)?;
+        let content_changed = controller.state.with_value(|state| state.version) != version;
+        controller.sync.run(content_changed.then_some(origin));
+        Some(result)
+    }
+
+    /// Move keyboard focus to the editor.
+    pub fn focus(&self) {
+        if let Some(textarea) = self
+            .controller
+            .get_untracked()
+            .and_then(|controller| controller.textarea.get_untracked())
+        {
+            let _ = textarea.focus();
+        }
+    }
+
+    /// Insert text at every cursor, replacing the selections.
+    ///
+    /// Returns `false` if nothing changed, for example because the editor
+    /// is read-only.
+    pub fn insert_at_cursor(&self, text: &str) -> bool {
+        self.edit(ChangeOrigin::Command, |state| {
+            let version = state.version;
+            state.insert(text);
+            state.version != version
+        })
+        .unwrap_or(false)
+    }
+
+    /// Select a byte range of the document with a single cursor.
+    ///
+    /// The caret goes to `range.end`. Returns `false` if the range is not
+    /// inside the document or does not start and end on character
+    /// boundaries.
+    pub fn set_selection(&self, range: Range<usize>) -> bool {
+        self.edit(ChangeOrigin::Command, |state| {
+            // Offsets inside a character would round down to its start
+            let position = |offset| {
+                state
+                    .offset_to_position(offset)
+                    .filter(|&position| state.position_to_offset(position) == Some(offset))
+            };
+            let (Some(anchor), Some(head)) = (position(range.start), position(range.end)) else {
+                return false;
+            };
+            state.clear_secondary_cursors();
+            state.set_cursor_with_selection(head, anchor);
+            true
+        })
+        .unwrap_or(false)
+    }
+
+    /// Scroll so that a line (0-indexed) is at the top of the editor.
+    ///
+    /// The position assumes lines are not wrapped.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_precision_loss)]
+    pub fn scroll_to_line(&self, line: usize) {
+        let Some(controller) = self.controller.get_untracked() else {
+            return;
+        };
+        if let Some(textarea) = controller.textarea.get_untracked() {
+            textarea.set_scroll_top((line as f64 * controller.line_height) as i32);
+        }
+    }
+
+    /// Undo the last change.
+    pub fn undo(&self) -> bool {
+        self.edit(ChangeOrigin::Undo, EditorState::undo)
+            .unwrap_or(false)
+    }
+
+    /// Redo the last undone change.
+    pub fn redo(&self) -> bool {
+        self.edit(ChangeOrigin::Redo, EditorState::redo)
+            .unwrap_or(false)
+    }
+
+    /// Get a copy of the editor state.
+    ///
+    /// The copy shares its text with the editor, so this is cheap even for
+    /// large documents.
+    #[must_use]
+    pub fn get_state(&self) -> Option<EditorState> {
+        self.controller.get_untracked()?.state.try_get_value()
+    }
+
+    /// Run a command from the editor's [`CommandRegistry`](super::CommandRegistry)
+    /// by name.
+    ///
+    /// Returns `false` if there is no such command.
+    pub fn execute_command(&self, name: &str) -> bool {
+        self.controller
+            .get_untracked()
+            .is_some_and(|controller| controller.run_command.run(name.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handle_drives_attached_state() {
+        let handle = EditorHandle::new();
+        assert!(!handle.insert_at_cursor("x"));
+        assert!(handle.get_state().is_none());
+
+        let state = StoredValue::new(EditorState::new("world"));
+        handle.attach(EditorController {
+            state,
+            textarea: NodeRef::new(),
+            sync: Callback::new(|_| {}),
+            run_command: Callback::new(|_| false),
+            line_height: 21.0,
+        });
+        assert!(handle.insert_at_cursor("hello "));
+        assert!(handle.set_selection(6..11));
+        let state = handle.get_state().unwrap();
+        assert_eq!(state.content(), "hello world");
+        assert_eq!(state.selected_text().as_deref(), Some("world"));
+        assert!(handle.undo());
+        assert_eq!(handle.get_state().unwrap().content(), "world");
+
+        // "é" takes two bytes
+        assert!(handle.insert_at_cursor("é"));
+        assert!(!handle.set_selection(0..1));
+        assert!(!handle.set_selection(0..100));
+        assert!(handle.set_selection(0..2));
+        assert_eq!(
+            handle.get_state().unwrap().selected_text().as_deref(),
+            Some("é")
+        );
+
+        handle.detach();
+        assert!(!handle.is_attached());
+    }
+}
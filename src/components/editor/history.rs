@@ -6,13 +6,18 @@ use std::time::Instant;
 
 use serde::{Deserialize, Serialize};
 
-use super::cursor::CursorSet;
+use super::{cursor::CursorSet, transaction::ChangeSet};
 
 /// A single history entry representing an edit operation.
+///
+/// Entries store the change rather than a copy of the document, so undoing
+/// applies [`Self::inverse`] and costs as much as the edit itself.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HistoryEntry {
-    /// The content before this edit
-    pub content: String,
+    /// The edit
+    pub changes: ChangeSet,
+    /// The edit that reverts it
+    pub inverse: ChangeSet,
     /// Cursor state before this edit
     pub cursors: CursorSet,
     /// Timestamp when this entry was created (for coalescing)
@@ -23,13 +28,25 @@ pub struct HistoryEntry {
 impl HistoryEntry {
     /// Create a new history entry.
     #[must_use]
-    pub fn new(content: String, cursors: CursorSet) -> Self {
+    pub fn new(changes: ChangeSet, inverse: ChangeSet, cursors: CursorSet) -> Self {
         Self {
-            content,
+            changes,
+            inverse,
             cursors,
             timestamp: Some(Instant::now()),
         }
     }
+
+    /// Get the entry that reverts this one, restoring `cursors` when it is
+    /// reverted in turn.
+    fn reversed(self, cursors: &CursorSet) -> Self {
+        Self {
+            changes: self.inverse,
+            inverse: self.changes,
+            cursors: cursors.clone(),
+            timestamp: None,
+        }
+    }
 }
 
 /// Configuration for history behavior.
@@ -79,92 +96,87 @@ impl History {
         }
     }
 
-    /// Record a new state in history.
+    /// Record an edit in history.
     ///
-    /// This will clear the redo stack and potentially coalesce with the
-    /// previous entry if the edit happened within the coalesce window.
-    pub fn push(&mut self, content: String, cursors: CursorSet) {
+    /// `inverse` reverts `changes`, and `cursors` are the cursors before
+    /// the edit. This will clear the redo stack and potentially coalesce
+    /// with the previous entry if the edit happened within the coalesce
+    /// window.
+    pub fn push(&mut self, changes: ChangeSet, inverse: ChangeSet, cursors: CursorSet) {
         if self.is_undoing {
             return;
         }
 
-        let entry = HistoryEntry::new(content, cursors);
+        let entry = HistoryEntry::new(changes, inverse, cursors);
 
         // Check if we should coalesce with the previous entry
-        if let Some(last) = self.undo_stack.last()
+        if let Some(last) = self.undo_stack.last_mut()
             && let (Some(last_ts), Some(entry_ts)) = (last.timestamp, entry.timestamp)
         {
             let elapsed =
                 u64::try_from(entry_ts.duration_since(last_ts).as_millis()).unwrap_or(u64::MAX);
             if elapsed < self.config.coalesce_window_ms {
-                // Coalesce by not adding a new entry, just update the timestamp
-                // The previous state is preserved
+                // Coalesce by merging the edits; the earlier cursors are
+                // preserved
+                last.changes = last.changes.compose(&entry.changes);
+                last.inverse = entry.inverse.compose(&last.inverse);
+                self.redo_stack.clear();
                 return;
             }
         }
 
-        self.undo_stack.push(entry);
-        self.redo_stack.clear();
-
-        // Trim history if needed
-        if self.undo_stack.len() > self.config.max_entries {
-            self.undo_stack.remove(0);
-        }
+        self.push_entry(entry);
     }
 
-    /// Record a state without coalescing (for explicit save points).
-    pub fn push_checkpoint(&mut self, content: String, cursors: CursorSet) {
+    /// Record an edit without coalescing (for explicit save points).
+    pub fn push_checkpoint(&mut self, changes: ChangeSet, inverse: ChangeSet, cursors: CursorSet) {
         if self.is_undoing {
             return;
         }
 
-        let mut entry = HistoryEntry::new(content, cursors);
+        let mut entry = HistoryEntry::new(changes, inverse, cursors);
         // Set timestamp to None to prevent coalescing with the next edit
         entry.timestamp = None;
+        self.push_entry(entry);
+    }
 
+    /// Add an entry to the undo stack, clearing the redo stack.
+    fn push_entry(&mut self, entry: HistoryEntry) {
         self.undo_stack.push(entry);
         self.redo_stack.clear();
 
+        // Trim history if needed
         if self.undo_stack.len() > self.config.max_entries {
             self.undo_stack.remove(0);
         }
     }
 
+    /// End the current undo step, so the next edit is not merged into it.
+    pub fn checkpoint(&mut self) {
+        if let Some(last) = self.undo_stack.last_mut() {
+            last.timestamp = None;
+        }
+    }
+
     /// Undo the last change.
     ///
-    /// Returns the previous state if available.
-    pub fn undo(
-        &mut self,
-        current_content: &str,
-        current_cursors: &CursorSet,
-    ) -> Option<HistoryEntry> {
+    /// Returns the entry to revert: apply its [`HistoryEntry::inverse`] and
+    /// restore its cursors. `current_cursors` are restored by the matching
+    /// redo.
+    pub fn undo(&mut self, current_cursors: &CursorSet) -> Option<HistoryEntry> {
         let entry = self.undo_stack.pop()?;
-
-        // Save current state to redo stack
-        self.redo_stack.push(HistoryEntry::new(
-            current_content.to_string(),
-            current_cursors.clone(),
-        ));
-
+        self.redo_stack
+            .push(entry.clone().reversed(current_cursors));
         Some(entry)
     }
 
     /// Redo the last undone change.
     ///
-    /// Returns the next state if available.
-    pub fn redo(
-        &mut self,
-        current_content: &str,
-        current_cursors: &CursorSet,
-    ) -> Option<HistoryEntry> {
+    /// Returns the entry to revert, like [`Self::undo`].
+    pub fn redo(&mut self, current_cursors: &CursorSet) -> Option<HistoryEntry> {
         let entry = self.redo_stack.pop()?;
-
-        // Save current state to undo stack
-        self.undo_stack.push(HistoryEntry::new(
-            current_content.to_string(),
-            current_cursors.clone(),
-        ));
-
+        self.undo_stack
+            .push(entry.clone().reversed(current_cursors));
         Some(entry)
     }
 
@@ -212,42 +224,71 @@ impl History {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::components::editor::cursor::{Cursor, CursorPosition};
+    use crate::components::editor::{
+        buffer::TextBuffer,
+        cursor::{Cursor, CursorPosition},
+    };
 
     fn test_cursors() -> CursorSet {
         CursorSet::new(Cursor::new(CursorPosition::zero()))
     }
 
+    /// Record replacing a range of `text`, returning the new text.
+    fn edit(
+        history: &mut History,
+        text: &str,
+        range: std::ops::Range<usize>,
+        with: &str,
+    ) -> String {
+        let changes = ChangeSet::replace(text.len(), range, with);
+        let inverse = changes.invert(&TextBuffer::from(text));
+        history.push(changes.clone(), inverse, test_cursors());
+        changes.apply_to_str(text)
+    }
+
     #[test]
     fn test_undo_redo() {
         let mut history = History::new();
 
-        history.push("state1".to_string(), test_cursors());
+        let text = edit(&mut history, "state1", 5..6, "2");
         std::thread::sleep(std::time::Duration::from_millis(600));
-        history.push("state2".to_string(), test_cursors());
+        let text = edit(&mut history, &text, 5..6, "3");
+        assert_eq!(text, "state3");
+
+        let entry = history.undo(&test_cursors()).unwrap();
+        let text = entry.inverse.apply_to_str(&text);
+        assert_eq!(text, "state2");
+
+        let entry = history.redo(&test_cursors()).unwrap();
+        assert_eq!(entry.inverse.apply_to_str(&text), "state3");
+    }
+
+    #[test]
+    fn test_coalesced_edits_undo_together() {
+        let mut history = History::new();
 
-        let entry = history.undo("state3", &test_cursors());
-        assert!(entry.is_some());
-        assert_eq!(entry.unwrap().content, "state2");
+        let text = edit(&mut history, "ab", 1..1, "x");
+        let text = edit(&mut history, &text, 2..2, "y");
+        assert_eq!(history.undo_count(), 1);
 
-        let entry = history.redo("state2", &test_cursors());
-        assert!(entry.is_some());
-        assert_eq!(entry.unwrap().content, "state3");
+        let entry = history.undo(&test_cursors()).unwrap();
+        assert_eq!(entry.inverse.apply_to_str(&text), "ab");
+        assert_eq!(entry.changes.apply_to_str("ab"), "axyb");
     }
 
     #[test]
     fn test_redo_cleared_on_new_edit() {
         let mut history = History::new();
 
-        history.push("state1".to_string(), test_cursors());
+        let text = edit(&mut history, "state1", 5..6, "2");
         std::thread::sleep(std::time::Duration::from_millis(600));
-        history.push("state2".to_string(), test_cursors());
+        edit(&mut history, &text, 5..6, "3");
 
-        history.undo("state3", &test_cursors());
+        history.undo(&test_cursors());
         assert!(history.can_redo());
 
         std::thread::sleep(std::time::Duration::from_millis(600));
-        history.push("state4".to_string(), test_cursors());
+        edit(&mut history, &text, 5..6, "4");
         assert!(!history.can_redo());
     }
 }
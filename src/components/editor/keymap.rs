@@ -0,0 +1,353 @@
+//! Key bindings
+//!
+//! Maps key chords and multi-stroke sequences (such as `Ctrl+K Ctrl+C`) to
+//! named commands from a [`CommandRegistry`](super::CommandRegistry).
+//!
+//! Bindings are written as `+`-separated modifiers followed by a key, with
+//! whitespace between the strokes of a sequence. `Mod` stands for Cmd on
+//! macOS and Ctrl everywhere else, so one binding works on both.
+
+use std::{collections::HashMap, fmt};
+
+/// A key press with its modifiers, e.g. `Ctrl+Shift+Z`.
+///
+/// The key is a `KeyboardEvent.key` value; single characters are stored in
+/// lowercase.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct KeyChord {
+    /// The key, e.g. `"z"`, `"ArrowUp"` or `"Tab"`
+    pub key: String,
+    /// Whether Ctrl is held
+    pub ctrl: bool,
+    /// Whether Alt (Option on macOS) is held
+    pub alt: bool,
+    /// Whether Shift is held
+    pub shift: bool,
+    /// Whether Meta (Cmd on macOS) is held
+    pub meta: bool,
+}
+
+impl KeyChord {
+    /// Create a chord for a key without modifiers.
+    #[must_use]
+    pub fn new(key: &str) -> Self {
+        Self {
+            key: normalize_key(key),
+            ctrl: false,
+            alt: false,
+            shift: false,
+            meta: false,
+        }
+    }
+
+    /// Parse a chord such as `"Ctrl+Shift+Z"` or `"Mod+/"`.
+    ///
+    /// `mac` decides whether `Mod` means Cmd or Ctrl. Returns `None` for an
+    /// unknown modifier or a missing key.
+    #[must_use]
+    pub fn parse(text: &str, mac: bool) -> Option<Self> {
+        let text = text.trim();
+        // A trailing "++" binds the plus key itself
+        let (modifiers, key) = if text == "+" {
+            ("", "+")
+        } else if let Some(rest) = text.strip_suffix("++") {
+            (rest, "+")
+        } else {
+            text.rsplit_once('+').unwrap_or(("", text))
+        };
+        if key.is_empty() {
+            return None;
+        }
+
+        let mut chord = Self::new(key);
+        for modifier in modifiers.split('+').filter(|m| !m.is_empty()) {
+            match modifier.to_ascii_lowercase().as_str() {
+                "mod" if mac => chord.meta = true,
+                "ctrl" | "control" | "mod" => chord.ctrl = true,
+                "alt" | "option" | "opt" => chord.alt = true,
+                "shift" => chord.shift = true,
+                "meta" | "cmd" | "command" | "super" | "win" => chord.meta = true,
+                _ => return None,
+            }
+        }
+        Some(chord)
+    }
+
+    /// Get the chord for a keyboard event.
+    ///
+    /// Returns `None` for a bare modifier key press, so holding Ctrl does
+    /// not break a pending sequence.
+    #[must_use]
+    pub fn from_event(ev: &web_sys::KeyboardEvent) -> Option<Self> {
+        let key = ev.key();
+        if matches!(
+            key.as_str(),
+            "Control" | "Alt" | "Shift" | "Meta" | "AltGraph" | "CapsLock"
+        ) {
+            return None;
+        }
+        // Option+letter on macOS produces a symbol; bind by the physical key
+        let code = ev.code();
+        let key = match code.strip_prefix("Key") {
+            Some(letter) if ev.alt_key() && !key.is_ascii() => letter.to_string(),
+            _ => key,
+        };
+        Some(Self {
+            key: normalize_key(&key),
+            ctrl: ev.ctrl_key(),
+            alt: ev.alt_key(),
+            shift: ev.shift_key(),
+            meta: ev.meta_key(),
+        })
+    }
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (held, name) in [
+            (self.ctrl, "Ctrl+"),
+            (self.alt, "Alt+"),
+            (self.shift, "Shift+"),
+            (self.meta, "Meta+"),
+        ] {
+            if held {
+                f.write_str(name)?;
+            }
+        }
+        match self.key.as_str() {
+            " " => f.write_str("Space"),
+            key if key.chars().count() == 1 => f.write_str(&key.to_uppercase()),
+            key => f.write_str(key),
+        }
+    }
+}
+
+/// Normalize a key name to its `KeyboardEvent.key` spelling.
+fn normalize_key(key: &str) -> String {
+    if key.chars().count() == 1 {
+        return key.to_lowercase();
+    }
+    match key.to_ascii_lowercase().as_str() {
+        "space" => " ".to_string(),
+        "esc" | "escape" => "Escape".to_string(),
+        "up" | "arrowup" => "ArrowUp".to_string(),
+        "down" | "arrowdown" => "ArrowDown".to_string(),
+        "left" | "arrowleft" => "ArrowLeft".to_string(),
+        "right" | "arrowright" => "ArrowRight".to_string(),
+        "enter" | "return" => "Enter".to_string(),
+        "tab" => "Tab".to_string(),
+        "backspace" => "Backspace".to_string(),
+        "del" | "delete" => "Delete".to_string(),
+        "home" => "Home".to_string(),
+        "end" => "End".to_string(),
+        "pageup" => "PageUp".to_string(),
+        "pagedown" => "PageDown".to_string(),
+        "insert" => "Insert".to_string(),
+        "plus" => "+".to_string(),
+        _ => key.to_string(),
+    }
+}
+
+/// How the editor interprets key presses.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KeyProfile {
+    /// Ordinary editing through the [`Keymap`]
+    #[default]
+    Standard,
+    /// Modal editing with [`Vim`](super::Vim); keys Vim does not use go
+    /// through the [`Keymap`]
+    #[cfg(feature = "vim")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "vim")))]
+    Vim,
+    /// Emacs key bindings with [`Emacs`](super::Emacs); keys Emacs does
+    /// not bind go through the [`Keymap`]
+    #[cfg(feature = "emacs")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "emacs")))]
+    Emacs,
+}
+
+/// The result of looking up keys in a [`Keymap`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyLookup {
+    /// The keys are bound to this command
+    Command(String),
+    /// The keys are the start of one or more longer sequences
+    Prefix,
+    /// Nothing is bound to the keys
+    NoMatch,
+}
+
+/// A set of key bindings.
+#[derive(Debug, Clone)]
+pub struct Keymap {
+    /// Whether `Mod` means Cmd (macOS) rather than Ctrl
+    mac: bool,
+    /// Bound sequences and their command names
+    bindings: HashMap<Vec<KeyChord>, String>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self::standard(crate::helpers::is_mac())
+    }
+}
+
+impl Keymap {
+    /// Create an empty keymap.
+    #[must_use]
+    pub fn new(mac: bool) -> Self {
+        Self {
+            mac,
+            bindings: HashMap::new(),
+        }
+    }
+
+    /// Create a keymap with the standard editor bindings.
+    #[must_use]
+    pub fn standard(mac: bool) -> Self {
+        let mut keymap = Self::new(mac);
+        for (keys, command) in [
+            ("Tab", "indent"),
+            ("Shift+Tab", "outdent"),
+            ("Enter", "insert_newline"),
+            ("Mod+Shift+\\", "jump_to_bracket"),
+            // Shift+\ reports `|` on US layouts
+            ("Mod+Shift+|", "jump_to_bracket"),
+            ("Mod+/", "toggle_line_comment"),
+            ("Shift+Alt+A", "toggle_block_comment"),
+            ("Alt+ArrowUp", "move_lines_up"),
+            ("Alt+ArrowDown", "move_lines_down"),
+            ("Mod+Shift+K", "delete_lines"),
+            ("Alt+Q", "reflow_paragraph"),
+            ("Mod+Enter", "insert_line_below"),
+            ("Mod+Shift+Enter", "insert_line_above"),
+            ("Mod+Z", "undo"),
+            ("Mod+Shift+Z", "redo"),
+            ("Mod+A", "select_all"),
+            ("Escape", "clear_secondary_cursors"),
+            ("Mod+Alt+ArrowUp", "add_cursor_above"),
+            ("Mod+Alt+ArrowDown", "add_cursor_below"),
+            ("Alt+Shift+ArrowLeft", "block_select_left"),
+            ("Alt+Shift+ArrowRight", "block_select_right"),
+            ("Alt+Shift+ArrowUp", "block_select_up"),
+            ("Alt+Shift+ArrowDown", "block_select_down"),
+        ] {
+            keymap.bind(keys, command);
+        }
+        if !mac {
+            keymap.bind("Ctrl+Y", "redo");
+        }
+        keymap
+    }
+
+    /// Check if `Mod` means Cmd in this keymap.
+    #[must_use]
+    pub fn is_mac(&self) -> bool {
+        self.mac
+    }
+
+    /// Bind a key sequence such as `"Ctrl+K Ctrl+C"` to a command.
+    ///
+    /// Replaces any existing binding for the same sequence. Returns `false`
+    /// if the sequence cannot be parsed.
+    pub fn bind(&mut self, keys: &str, command: impl Into<String>) -> bool {
+        let Some(sequence) = self.parse(keys) else {
+            return false;
+        };
+        self.bindings.insert(sequence, command.into());
+        true
+    }
+
+    /// Remove the binding for a key sequence.
+    ///
+    /// Returns the command it was bound to.
+    pub fn unbind(&mut self, keys: &str) -> Option<String> {
+        let sequence = self.parse(keys)?;
+        self.bindings.remove(&sequence)
+    }
+
+    /// Look up the keys pressed so far.
+    #[must_use]
+    pub fn lookup(&self, keys: &[KeyChord]) -> KeyLookup {
+        if let Some(command) = self.bindings.get(keys) {
+            return KeyLookup::Command(command.clone());
+        }
+        let is_prefix = self
+            .bindings
+            .keys()
+            .any(|sequence| sequence.len() > keys.len() && sequence.starts_with(keys));
+        if is_prefix {
+            KeyLookup::Prefix
+        } else {
+            KeyLookup::NoMatch
+        }
+    }
+
+    /// Iterate over the bindings as (sequence, command) pairs.
+    pub fn bindings(&self) -> impl Iterator<Item = (&[KeyChord], &str)> {
+        self.bindings
+            .iter()
+            .map(|(keys, command)| (keys.as_slice(), command.as_str()))
+    }
+
+    /// Parse a whitespace-separated sequence of chords.
+    fn parse(&self, keys: &str) -> Option<Vec<KeyChord>> {
+        let sequence = keys
+            .split_whitespace()
+            .map(|chord| KeyChord::parse(chord, self.mac))
+            .collect::<Option<Vec<_>>>()?;
+        (!sequence.is_empty()).then_some(sequence)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_chord() {
+        let chord = KeyChord::parse("Mod+Shift+Z", true).unwrap();
+        assert!(chord.meta && chord.shift && !chord.ctrl);
+        assert_eq!(chord.key, "z");
+        assert_eq!(chord.to_string(), "Shift+Meta+Z");
+
+        assert_eq!(KeyChord::parse("Ctrl++", false).unwrap().key, "+");
+        assert_eq!(KeyChord::parse("alt+up", false).unwrap().key, "ArrowUp");
+        assert_eq!(KeyChord::parse("Hyper+X", false), None);
+    }
+
+    #[test]
+    fn test_multi_stroke_lookup() {
+        let mut keymap = Keymap::new(false);
+        assert!(keymap.bind("Ctrl+K Ctrl+C", "comment"));
+        assert!(keymap.bind("Mod+S", "save"));
+
+        let ctrl = |key| KeyChord::parse(&format!("Ctrl+{key}"), false).unwrap();
+        assert_eq!(keymap.lookup(&[ctrl("k")]), KeyLookup::Prefix);
+        assert_eq!(
+            keymap.lookup(&[ctrl("k"), ctrl("c")]),
+            KeyLookup::Command("comment".into())
+        );
+        assert_eq!(keymap.lookup(&[ctrl("k"), ctrl("x")]), KeyLookup::NoMatch);
+        assert_eq!(
+            keymap.lookup(&[ctrl("s")]),
+            KeyLookup::Command("save".into())
+        );
+
+        assert_eq!(keymap.unbind("Ctrl+S"), Some("save".into()));
+        assert_eq!(keymap.lookup(&[ctrl("s")]), KeyLookup::NoMatch);
+    }
+
+    #[test]
+    fn test_standard_bindings_are_platform_aware() {
+        let mac = Keymap::standard(true);
+        let cmd_z = KeyChord::parse("Cmd+Z", true).unwrap();
+        let ctrl_z = [KeyChord::parse("Ctrl+Z", true).unwrap()];
+        assert_eq!(mac.lookup(&[cmd_z]), KeyLookup::Command("undo".into()));
+        assert_eq!(mac.lookup(&ctrl_z), KeyLookup::NoMatch);
+
+        let other = Keymap::standard(false);
+        assert_eq!(other.lookup(&ctrl_z), KeyLookup::Command("undo".into()));
+    }
+}
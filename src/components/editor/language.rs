@@ -0,0 +1,302 @@
+//! Document languages
+//!
+//! Identifies the language of a document, for syntax highlighting and
+//! language-aware editing such as indentation.
+
+/// A document language.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Language {
+    /// Rust
+    Rust,
+    /// JavaScript
+    JavaScript,
+    /// TypeScript
+    TypeScript,
+    /// Python
+    Python,
+    /// HTML
+    Html,
+    /// CSS
+    Css,
+    /// JSON
+    Json,
+    /// YAML
+    Yaml,
+    /// TOML
+    Toml,
+    /// Markdown
+    Markdown,
+    /// SQL
+    Sql,
+    /// Shell/Bash
+    Shell,
+    /// Go
+    Go,
+    /// C
+    C,
+    /// C++
+    Cpp,
+    /// Java
+    Java,
+    /// Plain text (no highlighting)
+    #[default]
+    PlainText,
+}
+
+impl Language {
+    /// Detect language from file extension.
+    #[must_use]
+    pub fn from_extension(ext: &str) -> Self {
+        match ext.to_lowercase().as_str() {
+            "rs" => Self::Rust,
+            "js" | "mjs" | "cjs" => Self::JavaScript,
+            "ts" | "mts" | "cts" | "tsx" => Self::TypeScript,
+            "py" | "pyi" => Self::Python,
+            "html" | "htm" => Self::Html,
+            "css" | "scss" | "sass" | "less" => Self::Css,
+            "json" => Self::Json,
+            "yaml" | "yml" => Self::Yaml,
+            "toml" => Self::Toml,
+            "md" | "markdown" => Self::Markdown,
+            "sql" => Self::Sql,
+            "sh" | "bash" | "zsh" | "fish" => Self::Shell,
+            "go" => Self::Go,
+            "c" | "h" => Self::C,
+            "cpp" | "cxx" | "cc" | "hpp" | "hxx" => Self::Cpp,
+            "java" => Self::Java,
+            _ => Self::PlainText,
+        }
+    }
+
+    /// Get a language by name, such as `"rust"` or `"JavaScript"`.
+    ///
+    /// File extensions are accepted too, so `"rs"` also gives Rust.
+    #[must_use]
+    pub fn from_name(name: &str) -> Self {
+        match name.trim().to_lowercase().as_str() {
+            "rust" => Self::Rust,
+            "javascript" | "jsx" => Self::JavaScript,
+            "typescript" => Self::TypeScript,
+            "python" => Self::Python,
+            "json" | "jsonc" => Self::Json,
+            "markdown" => Self::Markdown,
+            "shell" | "bash" | "zsh" => Self::Shell,
+            "golang" => Self::Go,
+            "c++" => Self::Cpp,
+            "text" | "plain" | "plaintext" => Self::PlainText,
+            name => Self::from_extension(name),
+        }
+    }
+
+    /// Check if a line ending in `line` opens a block, so the next line
+    /// should be indented one more level.
+    ///
+    /// That is the case after an opening bracket, and after `:` in Python
+    /// and YAML.
+    #[must_use]
+    pub fn indents_after(self, line: &str) -> bool {
+        let line = line.trim_end();
+        if line.ends_with(['(', '[', '{']) {
+            return true;
+        }
+        matches!(self, Self::Python | Self::Yaml) && line.ends_with(':')
+    }
+
+    /// Get the token that starts a line comment, such as `//`.
+    #[must_use]
+    pub fn line_comment(self) -> Option<&'static str> {
+        match self {
+            Self::Rust
+            | Self::JavaScript
+            | Self::TypeScript
+            | Self::Go
+            | Self::C
+            | Self::Cpp
+            | Self::Java => Some("//"),
+            Self::Python | Self::Shell | Self::Yaml | Self::Toml => Some("#"),
+            Self::Sql => Some("--"),
+            Self::Html | Self::Css | Self::Json | Self::Markdown | Self::PlainText => None,
+        }
+    }
+
+    /// Get the tokens that open and close a block comment, such as `/*`
+    /// and `*/`.
+    #[must_use]
+    pub fn block_comment(self) -> Option<(&'static str, &'static str)> {
+        match self {
+            Self::Rust
+            | Self::JavaScript
+            | Self::TypeScript
+            | Self::Go
+            | Self::C
+            | Self::Cpp
+            | Self::Java
+            | Self::Css
+            | Self::Sql => Some(("/*", "*/")),
+            Self::Html | Self::Markdown => Some(("<!--", "-->")),
+            Self::Python | Self::Shell | Self::Yaml | Self::Toml | Self::Json | Self::PlainText => {
+                None
+            }
+        }
+    }
+
+    /// Get the characters that delimit string literals.
+    ///
+    /// In Rust, `'` only starts a char literal such as `'x'` or `'\n'`,
+    /// not a lifetime.
+    #[must_use]
+    pub fn string_quotes(self) -> &'static [char] {
+        match self {
+            Self::JavaScript | Self::TypeScript | Self::Go => &['"', '\'', '`'],
+            Self::Rust
+            | Self::Python
+            | Self::Shell
+            | Self::Css
+            | Self::Sql
+            | Self::Yaml
+            | Self::Toml
+            | Self::C
+            | Self::Cpp
+            | Self::Java => &['"', '\''],
+            Self::Json => &['"'],
+            Self::Html | Self::Markdown | Self::PlainText => &[],
+        }
+    }
+
+    /// Get the pairs of characters that are closed automatically, as
+    /// `(open, close)`.
+    #[must_use]
+    pub fn auto_pairs(self) -> &'static [(char, char)] {
+        const BRACKETS_AND_QUOTES: &[(char, char)] = &[
+            ('(', ')'),
+            ('[', ']'),
+            ('{', '}'),
+            ('"', '"'),
+            ('\'', '\''),
+            ('`', '`'),
+        ];
+        match self {
+            Self::Rust | Self::Python | Self::Toml | Self::Yaml | Self::Css => {
+                &BRACKETS_AND_QUOTES[..5]
+            }
+            Self::Json => &BRACKETS_AND_QUOTES[..4],
+            Self::Markdown => &[
+                ('(', ')'),
+                ('[', ']'),
+                ('{', '}'),
+                ('"', '"'),
+                ('`', '`'),
+                ('*', '*'),
+                ('_', '_'),
+            ],
+            _ => BRACKETS_AND_QUOTES,
+        }
+    }
+
+    /// Check if typing `open` should also insert its closing character.
+    ///
+    /// `before` is the line up to the cursor and `after` the character
+    /// following it. Pairs are only closed in front of whitespace,
+    /// punctuation or a closing character, and quote-like pairs are not
+    /// closed right after a word, so `don't` and `snake_case` type
+    /// normally. Rust lifetimes and loop labels (`&'a`, `<'a, 'b>`,
+    /// `T: 'static`, `where 'a: 'b`, `'outer: loop`) and Markdown list
+    /// bullets are left alone too.
+    #[must_use]
+    pub fn closes_pair(self, open: char, before: &str, after: Option<char>) -> bool {
+        let pairs = self.auto_pairs();
+        let Some(&(_, close)) = pairs.iter().find(|(o, _)| *o == open) else {
+            return false;
+        };
+        let before_char = before.chars().last();
+        let free_after = after.is_none_or(|c| {
+            c.is_whitespace()
+                || matches!(c, ';' | ',' | '.' | ':')
+                || pairs.iter().any(|&(_, close)| close == c)
+        });
+        if !free_after {
+            return false;
+        }
+        if open == close
+            && before_char.is_some_and(|c| c.is_alphanumeric() || c == '_' || c == open)
+        {
+            return false;
+        }
+        match (self, open) {
+            (Self::Rust, '\'') => {
+                let before = before.trim_end();
+                !(before.is_empty()
+                    || before.ends_with([':', ',', '+', '&', '<', '{', ';'])
+                    || before.ends_with(" where")
+                    || before == "where")
+            }
+            (Self::Markdown, '*' | '_') => !before.trim().is_empty(),
+            _ => true,
+        }
+    }
+
+    /// Get the syntect syntax name.
+    #[must_use]
+    pub fn syntax_name(&self) -> &'static str {
+        match self {
+            Self::Rust => "Rust",
+            Self::JavaScript => "JavaScript",
+            Self::TypeScript => "TypeScript",
+            Self::Python => "Python",
+            Self::Html => "HTML",
+            Self::Css => "CSS",
+            Self::Json => "JSON",
+            Self::Yaml => "YAML",
+            Self::Toml => "TOML",
+            Self::Markdown => "Markdown",
+            Self::Sql => "SQL",
+            Self::Shell => "Bourne Again Shell (bash)",
+            Self::Go => "Go",
+            Self::C => "C",
+            Self::Cpp => "C++",
+            Self::Java => "Java",
+            Self::PlainText => "Plain Text",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_language_from_extension() {
+        assert_eq!(Language::from_extension("rs"), Language::Rust);
+        assert_eq!(Language::from_extension("js"), Language::JavaScript);
+        assert_eq!(Language::from_extension("py"), Language::Python);
+        assert_eq!(Language::from_extension("unknown"), Language::PlainText);
+    }
+
+    #[test]
+    fn test_language_from_name() {
+        assert_eq!(Language::from_name("Rust"), Language::Rust);
+        assert_eq!(Language::from_name("python"), Language::Python);
+        assert_eq!(Language::from_name("ts"), Language::TypeScript);
+        assert_eq!(Language::from_name("klingon"), Language::PlainText);
+    }
+
+    #[test]
+    fn test_closes_pair() {
+        assert!(Language::Rust.closes_pair('(', "foo", None));
+        assert!(!Language::Rust.closes_pair('(', "", Some('x')));
+        assert!(Language::Rust.closes_pair('\'', "let c = ", None));
+        assert!(!Language::Rust.closes_pair('\'', "fn f(x: &", None));
+        assert!(!Language::Rust.closes_pair('\'', "fn f<T: ", None));
+        assert!(!Language::Rust.closes_pair('\'', "struct S<'a, ", None));
+        assert!(!Language::Rust.closes_pair('\'', "fn f<'a, T>() where ", None));
+        assert!(!Language::Rust.closes_pair('\'', "where 'a: ", None));
+        assert!(!Language::Rust.closes_pair('\'', "T: Send + ", None));
+        assert!(!Language::Rust.closes_pair('\'', "    ", None));
+        assert!(!Language::Rust.closes_pair('\'', "fn f() { ", None));
+        assert!(Language::Rust.closes_pair('\'', "if c == ", None));
+        assert!(!Language::PlainText.closes_pair('\'', "don", None));
+        assert!(!Language::Rust.closes_pair('`', "", None));
+        assert!(Language::Markdown.closes_pair('*', "some ", None));
+        assert!(!Language::Markdown.closes_pair('*', "  ", None));
+    }
+}
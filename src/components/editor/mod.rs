@@ -3,7 +3,26 @@
 //! This module provides a rich text editor component with features including:
 //!
 //! - **Basic Editing** - Text input, cursor movement, selection
+//! - **Multiple Cursors** - Alt+Click and Ctrl+Alt+Up/Down add cursors
+//! - **Block Selection** - Rectangular selection and editing with virtual space
+//! - **Rope Storage** - Logarithmic-time edits on large documents
+//! - **Line Endings** - LF, CRLF and CR documents keep their line ending
 //! - **Undo/Redo** - Full history management
+//! - **Auto-Indent** - New lines keep their indentation and indent after an opening bracket
+//! - **Auto-Close** - Language-aware closing of brackets and quotes
+//! - **Whitespace Rendering** - Shows spaces, tabs and line breaks and highlights trailing whitespace
+//! - **Bracket Matching** - Highlights the partner of the bracket at the cursor
+//! - **Comments** - Toggle line and block comments with each language's syntax
+//! - **Line Commands** - Move, copy, delete, join, sort and deduplicate lines
+//! - **Rulers and Reflow** - Column rulers and hard-wrapping of paragraphs and comments
+//! - **Clipboard** - Line-wise copy and paste, one pasted line per cursor
+//! - **Text Transforms** - Case conversion, Base64 and URL encoding of selections
+//! - **Editor Handle** - Focus, edit, select, scroll and run commands from outside the editor
+//! - **Keymaps** - Rebindable key chords and sequences for named commands
+//! - **Vim Mode** - Modal editing with motions, operators and registers (with `vim` feature)
+//! - **Emacs Mode** - Emacs key bindings with a kill ring and incremental search (with `emacs` feature)
+//! - **Transactions** - Invertible, composable change sets for every edit
+//! - **Change Events** - Changed ranges, cursors and origin of each edit through `on_transaction`
 //! - **Line Numbers** - Optional line number gutter
 //! - **Syntax Highlighting** - Code syntax coloring (with `syntax-highlighting` feature)
 //! - **Find & Replace** - Search and replace functionality (with `find-replace` feature)
@@ -51,11 +70,26 @@
 //! ```
 
 // Core modules (always available with editor feature)
+mod brackets;
+mod buffer;
+mod commands;
+pub mod coords;
 mod core;
 mod cursor;
+#[cfg(feature = "emacs")]
+#[cfg_attr(docsrs, doc(cfg(feature = "emacs")))]
+mod emacs;
+mod handle;
 mod history;
+mod keymap;
+mod language;
+mod movement;
+mod overlay;
+mod reflow;
 mod selection;
 mod state;
+mod transaction;
+mod transform;
 
 // Feature-gated modules
 #[cfg(feature = "find-replace")]
@@ -82,6 +116,10 @@ mod statistics;
 #[cfg_attr(docsrs, doc(cfg(feature = "syntax-highlighting")))]
 mod syntax;
 
+#[cfg(feature = "vim")]
+#[cfg_attr(docsrs, doc(cfg(feature = "vim")))]
+mod vim;
+
 // ============================================================================
 // Public re-exports
 // ============================================================================
@@ -89,7 +127,13 @@ mod syntax;
 // Core types (always available)
 pub use core::{DEFAULT_STYLES, Editor, EditorProps};
 
+pub use brackets::{BRACKET_PAIRS, BracketMatch, find_partner, match_at};
+pub use buffer::TextBuffer;
+pub use commands::{CommandFn, CommandRegistry};
 pub use cursor::{Cursor, CursorPosition, CursorSet};
+#[cfg(feature = "emacs")]
+#[cfg_attr(docsrs, doc(cfg(feature = "emacs")))]
+pub use emacs::{Emacs, EmacsResponse};
 // Feature-gated re-exports
 #[cfg(feature = "find-replace")]
 #[cfg_attr(docsrs, doc(cfg(feature = "find-replace")))]
@@ -97,18 +141,30 @@ pub use find_replace::{FindOptions, FindResult, FindState};
 #[cfg(feature = "folding")]
 #[cfg_attr(docsrs, doc(cfg(feature = "folding")))]
 pub use folding::{FoldKind, FoldRegion, FoldState, detect_markdown_folds};
+pub use handle::EditorHandle;
 pub use history::{History, HistoryConfig, HistoryEntry};
+pub use keymap::{KeyChord, KeyLookup, KeyProfile, Keymap};
+pub use language::Language;
 #[cfg(feature = "line-numbers")]
 #[cfg_attr(docsrs, doc(cfg(feature = "line-numbers")))]
 pub use line_numbers::{count_lines, gutter_width};
 #[cfg(feature = "minimap")]
 #[cfg_attr(docsrs, doc(cfg(feature = "minimap")))]
 pub use minimap::{MINIMAP_STYLES, Minimap, MinimapOutput};
-pub use selection::{Selection, SelectionMode};
-pub use state::{EditorConfig, EditorState};
+pub use movement::Movement;
+pub use reflow::{REFLOW_MARKERS, reflow};
+pub use selection::{BlockSelection, Selection, SelectionMode};
+pub use state::{EditorConfig, EditorState, SortOrder, WhitespaceMode};
 #[cfg(feature = "statistics")]
 #[cfg_attr(docsrs, doc(cfg(feature = "statistics")))]
 pub use statistics::{DocumentStats, TextStats};
 #[cfg(feature = "syntax-highlighting")]
 #[cfg_attr(docsrs, doc(cfg(feature = "syntax-highlighting")))]
-pub use syntax::{HighlightedLine, HighlightedSpan, Highlighter, Language, SyntaxConfig};
+pub use syntax::{HighlightedLine, HighlightedSpan, Highlighter, SyntaxConfig};
+pub use transaction::{
+    Assoc, ChangeEvent, ChangeOrigin, ChangeSet, Operation, TextChange, Transaction,
+};
+pub use transform::TextTransform;
+#[cfg(feature = "vim")]
+#[cfg_attr(docsrs, doc(cfg(feature = "vim")))]
+pub use vim::{Register, RegisterKind, Vim, VimKey, VimMode, VimResponse};
@@ -0,0 +1,253 @@
+//! Cursor movement
+//!
+//! Keyboard-style movements that apply to every cursor in a
+//! [`CursorSet`](super::CursorSet). Horizontal movements step over grapheme
+//! clusters; vertical movements keep the cursor's preferred column.
+
+use serde::{Deserialize, Serialize};
+
+use super::{
+    buffer::TextBuffer,
+    cursor::{Cursor, CursorPosition},
+    selection::is_word_char,
+};
+
+/// A cursor movement.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Movement {
+    /// One grapheme cluster to the left
+    CharLeft,
+    /// One grapheme cluster to the right
+    CharRight,
+    /// To the start of the previous word
+    WordLeft,
+    /// To the end of the next word
+    WordRight,
+    /// One line up
+    LineUp,
+    /// One line down
+    LineDown,
+    /// To the first non-whitespace character, or column 0 if already there
+    LineStart,
+    /// To the end of the line
+    LineEnd,
+    /// Up by a number of lines
+    PageUp(usize),
+    /// Down by a number of lines
+    PageDown(usize),
+    /// To the start of the document
+    DocumentStart,
+    /// To the end of the document
+    DocumentEnd,
+}
+
+impl Movement {
+    /// Check if this movement keeps the preferred column.
+    #[must_use]
+    pub const fn is_vertical(self) -> bool {
+        matches!(
+            self,
+            Self::LineUp | Self::LineDown | Self::PageUp(_) | Self::PageDown(_)
+        )
+    }
+
+    /// Move a cursor, extending its selection if `extend` is set.
+    ///
+    /// Without `extend`, a selection collapses: left and right movements
+    /// stop at the edge of the selection instead of moving past it.
+    pub fn apply(self, buffer: &TextBuffer, cursor: &mut Cursor, extend: bool) {
+        if !extend && cursor.has_selection() {
+            let edge = match self {
+                Self::CharLeft => Some(cursor.selection_start()),
+                Self::CharRight => Some(cursor.selection_end()),
+                _ => None,
+            };
+            if let Some(edge) = edge {
+                cursor.move_to(edge, false);
+                cursor.clear_preferred_column();
+                return;
+            }
+        }
+
+        let head = cursor.head;
+        let target = match self {
+            Self::CharLeft => step(buffer, head, TextBuffer::prev_grapheme_boundary),
+            Self::CharRight => step(buffer, head, TextBuffer::next_grapheme_boundary),
+            Self::WordLeft => word_left(buffer, head),
+            Self::WordRight => word_right(buffer, head),
+            Self::LineUp => vertical(buffer, cursor, -1),
+            Self::LineDown => vertical(buffer, cursor, 1),
+            Self::PageUp(lines) => vertical(buffer, cursor, -signed(lines)),
+            Self::PageDown(lines) => vertical(buffer, cursor, signed(lines)),
+            Self::LineStart => {
+                let indent = buffer.line(head.line).map_or(0, |line| {
+                    line.chars().take_while(|c| c.is_whitespace()).count()
+                });
+                let column = if head.column == indent { 0 } else { indent };
+                CursorPosition::new(head.line, column)
+            }
+            Self::LineEnd => CursorPosition::new(head.line, line_len(buffer, head.line)),
+            Self::DocumentStart => CursorPosition::zero(),
+            Self::DocumentEnd => {
+                let last = buffer.line_count() - 1;
+                CursorPosition::new(last, line_len(buffer, last))
+            }
+        };
+
+        let preferred = cursor.preferred_column;
+        cursor.move_to(target, extend);
+        if self.is_vertical() {
+            cursor.set_preferred_column(preferred.unwrap_or(head.column));
+        } else {
+            cursor.clear_preferred_column();
+        }
+    }
+}
+
+/// Get the length of a line in chars.
+fn line_len(buffer: &TextBuffer, line: usize) -> usize {
+    buffer.line_len_chars(line).unwrap_or(0)
+}
+
+/// Convert a line count to a signed delta, saturating.
+fn signed(lines: usize) -> isize {
+    isize::try_from(lines).unwrap_or(isize::MAX)
+}
+
+/// Move to the offset produced by a byte-offset step function.
+fn step(
+    buffer: &TextBuffer,
+    position: CursorPosition,
+    boundary: fn(&TextBuffer, usize) -> usize,
+) -> CursorPosition {
+    buffer
+        .line_col_to_offset(position.line, position.column)
+        .map(|offset| boundary(buffer, offset))
+        .and_then(|offset| buffer.offset_to_line_col(offset))
+        .map_or(position, |(line, column)| CursorPosition::new(line, column))
+}
+
+/// Move up (negative) or down (positive) by `delta` lines.
+///
+/// Moving past the first or last line goes to the start or end of the
+/// document.
+fn vertical(buffer: &TextBuffer, cursor: &Cursor, delta: isize) -> CursorPosition {
+    if delta < 0 && cursor.head.line < delta.unsigned_abs() {
+        return CursorPosition::zero();
+    }
+    let last = buffer.line_count() - 1;
+    let line = cursor.head.line.saturating_add_signed(delta);
+    if line > last {
+        return CursorPosition::new(last, line_len(buffer, last));
+    }
+    let column = cursor.preferred_column.unwrap_or(cursor.head.column);
+    CursorPosition::new(line, column.min(line_len(buffer, line)))
+}
+
+/// The kind of character a word movement skips over as one run.
+fn char_class(c: char) -> u8 {
+    if c.is_whitespace() {
+        0
+    } else if is_word_char(c) {
+        1
+    } else {
+        2
+    }
+}
+
+/// Move to the start of the previous word, crossing to the previous line
+/// at column 0.
+fn word_left(buffer: &TextBuffer, position: CursorPosition) -> CursorPosition {
+    if position.column == 0 {
+        return step(buffer, position, TextBuffer::prev_char_boundary);
+    }
+    let chars: Vec<char> = buffer
+        .line(position.line)
+        .unwrap_or_default()
+        .chars()
+        .collect();
+    let mut column = position.column.min(chars.len());
+    while column > 0 && chars[column - 1].is_whitespace() {
+        column -= 1;
+    }
+    if let Some(class) = column.checked_sub(1).map(|i| char_class(chars[i])) {
+        while column > 0 && char_class(chars[column - 1]) == class {
+            column -= 1;
+        }
+    }
+    CursorPosition::new(position.line, column)
+}
+
+/// Move to the end of the next word, crossing to the next line at the end
+/// of a line.
+fn word_right(buffer: &TextBuffer, position: CursorPosition) -> CursorPosition {
+    let chars: Vec<char> = buffer
+        .line(position.line)
+        .unwrap_or_default()
+        .chars()
+        .collect();
+    if position.column >= chars.len() {
+        return step(buffer, position, TextBuffer::next_char_boundary);
+    }
+    let mut column = position.column;
+    while column < chars.len() && chars[column].is_whitespace() {
+        column += 1;
+    }
+    if let Some(class) = chars.get(column).map(|&c| char_class(c)) {
+        while column < chars.len() && char_class(chars[column]) == class {
+            column += 1;
+        }
+    }
+    CursorPosition::new(position.line, column)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn moved(text: &str, from: (usize, usize), movement: Movement) -> (usize, usize) {
+        let buffer = TextBuffer::from(text);
+        let mut cursor = Cursor::new(CursorPosition::new(from.0, from.1));
+        movement.apply(&buffer, &mut cursor, false);
+        (cursor.head.line, cursor.head.column)
+    }
+
+    #[test]
+    fn test_char_and_word_movement() {
+        let text = "let foo_bar = 1;\nnext";
+        assert_eq!(moved(text, (0, 0), Movement::CharRight), (0, 1));
+        assert_eq!(moved(text, (1, 0), Movement::CharLeft), (0, 16));
+        assert_eq!(moved(text, (0, 3), Movement::WordRight), (0, 11));
+        assert_eq!(moved(text, (0, 11), Movement::WordRight), (0, 13));
+        assert_eq!(moved(text, (0, 11), Movement::WordLeft), (0, 4));
+        assert_eq!(moved(text, (0, 16), Movement::WordRight), (1, 0));
+        assert_eq!(moved(text, (1, 0), Movement::WordLeft), (0, 16));
+    }
+
+    #[test]
+    fn test_vertical_movement_keeps_preferred_column() {
+        let buffer = TextBuffer::from("long line\nab\nanother line");
+        let mut cursor = Cursor::new(CursorPosition::new(0, 7));
+
+        Movement::LineDown.apply(&buffer, &mut cursor, false);
+        assert_eq!(cursor.head, CursorPosition::new(1, 2));
+        Movement::LineDown.apply(&buffer, &mut cursor, true);
+        assert_eq!(cursor.head, CursorPosition::new(2, 7));
+        assert_eq!(cursor.anchor, CursorPosition::new(1, 2));
+
+        Movement::PageUp(10).apply(&buffer, &mut cursor, false);
+        assert_eq!(cursor.head, CursorPosition::zero());
+        Movement::DocumentEnd.apply(&buffer, &mut cursor, false);
+        assert_eq!(cursor.head, CursorPosition::new(2, 12));
+        assert_eq!(cursor.preferred_column, None);
+    }
+
+    #[test]
+    fn test_smart_home() {
+        let text = "    indented";
+        assert_eq!(moved(text, (0, 8), Movement::LineStart), (0, 4));
+        assert_eq!(moved(text, (0, 4), Movement::LineStart), (0, 0));
+        assert_eq!(moved(text, (0, 0), Movement::LineStart), (0, 4));
+        assert_eq!(moved(text, (0, 0), Movement::LineEnd), (0, 12));
+    }
+}
@@ -0,0 +1,580 @@
+//! Overlay rendering layer
+//!
+//! The textarea can only draw one caret and one selection. Everything else
+//! (extra cursors, highlights) is drawn by an overlay that sits behind the
+//! transparent textarea and lays out the same text in the same font, with
+//! the text itself invisible. Decorations are spans around the characters
+//! they belong to, so they line up with the textarea even when lines wrap.
+
+use leptos::prelude::*;
+
+use super::{
+    buffer::TextBuffer,
+    cursor::CursorPosition,
+    state::{EditorState, WhitespaceMode},
+    transaction::{Assoc, ChangeSet},
+};
+
+/// A styled range on a single line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decoration {
+    /// Line number (0-indexed)
+    pub line: usize,
+    /// Start column (in chars, inclusive)
+    pub start: usize,
+    /// End column (in chars, exclusive); equal to `start` for a marker
+    /// such as a cursor
+    pub end: usize,
+    /// CSS class applied to the range
+    pub class: &'static str,
+}
+
+impl Decoration {
+    /// Create a decoration covering `start..end` on `line`.
+    #[must_use]
+    pub const fn new(line: usize, start: usize, end: usize, class: &'static str) -> Self {
+        Self {
+            line,
+            start,
+            end,
+            class,
+        }
+    }
+
+    /// Create a zero-width marker at a column.
+    #[must_use]
+    pub const fn marker(line: usize, column: usize, class: &'static str) -> Self {
+        Self::new(line, column, column, class)
+    }
+}
+
+/// A run of text with the classes of every decoration covering it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    /// The text of the run (empty for a marker)
+    pub text: String,
+    /// Space-separated CSS classes (empty for undecorated text)
+    pub class: String,
+}
+
+impl Segment {
+    fn plain(text: String) -> Self {
+        Self {
+            text,
+            class: String::new(),
+        }
+    }
+}
+
+/// Split one line into segments for the decorations on it.
+///
+/// Markers come before any text that starts at the same column.
+#[must_use]
+pub fn line_segments(line: &str, decorations: &[&Decoration]) -> Vec<Segment> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut points: Vec<usize> = decorations
+        .iter()
+        .flat_map(|d| [d.start, d.end])
+        .chain([0, chars.len()])
+        .map(|p| p.min(chars.len()))
+        .collect();
+    points.sort_unstable();
+    points.dedup();
+
+    let mut segments = Vec::new();
+    for (i, &point) in points.iter().enumerate() {
+        for marker in decorations
+            .iter()
+            .filter(|d| d.start == d.end && d.start.min(chars.len()) == point)
+        {
+            segments.push(Segment {
+                text: String::new(),
+                class: marker.class.to_string(),
+            });
+        }
+
+        let Some(&next) = points.get(i + 1) else {
+            break;
+        };
+        let class = decorations
+            .iter()
+            .filter(|d| d.start < d.end && d.start <= point && d.end >= next)
+            .map(|d| d.class)
+            .collect::<Vec<_>>()
+            .join(" ");
+        segments.push(Segment {
+            text: chars[point..next].iter().collect(),
+            class,
+        });
+    }
+    segments
+}
+
+/// Split a whole document into segments.
+///
+/// Undecorated lines and line breaks are merged into plain runs, so the
+/// number of segments grows with the number of decorations rather than the
+/// size of the document.
+#[must_use]
+pub fn document_segments(buffer: &TextBuffer, decorations: &[Decoration]) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let mut plain = String::new();
+    let mut sorted: Vec<&Decoration> = decorations.iter().collect();
+    sorted.sort_by_key(|d| d.line);
+    let mut rest = sorted.as_slice();
+
+    for line in 0..buffer.line_count() {
+        let text = buffer.line(line).unwrap_or_default();
+        let split = rest.partition_point(|d| d.line <= line);
+        let (on_line, after) = rest.split_at(split);
+        rest = after;
+
+        if on_line.is_empty() {
+            plain.push_str(&text);
+        } else {
+            for segment in line_segments(&text, on_line) {
+                if segment.class.is_empty() {
+                    plain.push_str(&segment.text);
+                } else {
+                    if !plain.is_empty() {
+                        segments.push(Segment::plain(std::mem::take(&mut plain)));
+                    }
+                    segments.push(segment);
+                }
+            }
+        }
+        if line + 1 < buffer.line_count() {
+            plain.push('\n');
+        }
+    }
+
+    if !plain.is_empty() {
+        segments.push(Segment::plain(plain));
+    }
+    segments
+}
+
+/// Decorations for every cursor except the primary one.
+///
+/// The textarea draws the primary cursor and selection natively.
+#[must_use]
+pub fn secondary_cursor_decorations(state: &EditorState) -> Vec<Decoration> {
+    let mut decorations = Vec::new();
+    for cursor in state.cursors.all().iter().skip(1) {
+        let start = cursor.selection_start();
+        let end = cursor.selection_end();
+        for line in start.line..=end.line {
+            let from = if line == start.line { start.column } else { 0 };
+            let to = if line == end.line {
+                end.column
+            } else {
+                state.buffer.line_len_chars(line).unwrap_or(0)
+            };
+            if from < to {
+                decorations.push(Decoration::new(line, from, to, "leptos-editor-selection"));
+            }
+        }
+        decorations.push(Decoration::marker(
+            cursor.head.line,
+            cursor.head.column,
+            "leptos-editor-cursor",
+        ));
+    }
+    decorations
+}
+
+/// Decorations for the bracket next to each cursor and its partner.
+#[must_use]
+pub fn bracket_decorations(state: &EditorState) -> Vec<Decoration> {
+    state
+        .matching_brackets()
+        .into_iter()
+        .flat_map(|found| [found.bracket, found.partner])
+        .filter_map(|offset| state.offset_to_position(offset))
+        .map(|pos| {
+            Decoration::new(
+                pos.line,
+                pos.column,
+                pos.column + 1,
+                "leptos-editor-bracket-match",
+            )
+        })
+        .collect()
+}
+
+/// Decorations that make whitespace visible, per
+/// [`EditorConfig::show_whitespace`](super::EditorConfig::show_whitespace).
+///
+/// Each run of shown spaces or tabs gets one decoration, whose `·` or `→`
+/// glyphs the stylesheet repeats rather than using one element per
+/// character. A run of tabs starts at a tab stop, so the repeated arrows
+/// line up with the tabs. Each shown line break gets a marker at the end of its line, and
+/// each run of trailing whitespace a highlight (except in
+/// [`WhitespaceMode::Selection`], which only looks at selected lines).
+#[must_use]
+pub fn whitespace_decorations(state: &EditorState) -> Vec<Decoration> {
+    match state.config.show_whitespace {
+        WhitespaceMode::None => Vec::new(),
+        WhitespaceMode::Selection => selected_whitespace_decorations(state),
+        _ => (0..state.line_count())
+            .flat_map(|line| line_whitespace_decorations(state, line, &[]))
+            .collect(),
+    }
+}
+
+/// Whitespace decorations of the selected lines, for
+/// [`WhitespaceMode::Selection`].
+fn selected_whitespace_decorations(state: &EditorState) -> Vec<Decoration> {
+    let selections: Vec<_> = state
+        .cursors
+        .all()
+        .iter()
+        .filter(|cursor| cursor.has_selection())
+        .map(|cursor| (cursor.selection_start(), cursor.selection_end()))
+        .collect();
+    let mut lines: Vec<usize> = selections
+        .iter()
+        .flat_map(|(start, end)| start.line..=end.line)
+        .collect();
+    lines.sort_unstable();
+    lines.dedup();
+    lines
+        .into_iter()
+        .flat_map(|line| line_whitespace_decorations(state, line, &selections))
+        .collect()
+}
+
+/// Whitespace decorations of one line; `selections` are the selected ranges
+/// in [`WhitespaceMode::Selection`].
+fn line_whitespace_decorations(
+    state: &EditorState,
+    line: usize,
+    selections: &[(CursorPosition, CursorPosition)],
+) -> Vec<Decoration> {
+    let mode = state.config.show_whitespace;
+    let selected = |column: usize| {
+        selections.iter().any(|(start, end)| {
+            (start.line, start.column) <= (line, column) && (line, column) < (end.line, end.column)
+        })
+    };
+    let tab_size = state.config.tab_size.max(1);
+    let mut decorations = Vec::new();
+    let text = state.buffer.line(line).unwrap_or_default();
+    let is_blank = |c: char| matches!(c, ' ' | '\t');
+    let len = text.chars().count();
+    let content_start = text.chars().position(|c| !is_blank(c)).unwrap_or(len);
+    let trailing_start = len - text.chars().rev().take_while(|&c| is_blank(c)).count();
+
+    // The open run: its kind, start and end columns, and whether it can
+    // grow (a run of tabs only can if it starts at a tab stop)
+    let mut run: Option<(char, usize, usize, bool)> = None;
+    let mut visual = 0;
+    let mut prev = None;
+    let mut chars = text.chars().enumerate().peekable();
+    while let Some((column, c)) = chars.next() {
+        let next = chars.peek().map(|&(_, c)| c);
+        let show = match (c, mode) {
+            (' ' | '\t', WhitespaceMode::All) => true,
+            (' ' | '\t', WhitespaceMode::Trailing) => column >= trailing_start,
+            (' ' | '\t', WhitespaceMode::Selection) => selected(column),
+            (' ' | '\t', WhitespaceMode::Boundary) => {
+                column < content_start
+                    || column >= trailing_start
+                    || c == '\t'
+                    || prev == Some(' ')
+                    || next == Some(' ')
+            }
+            _ => false,
+        };
+        match run {
+            Some((kind, _, ref mut end, true)) if show && kind == c && *end == column => {
+                *end += 1;
+            }
+            _ => {
+                push_run(&mut decorations, line, run.take());
+                let grows = c == ' ' || visual % tab_size == 0;
+                run = show.then_some((c, column, column + 1, grows));
+            }
+        }
+        visual = if c == '\t' {
+            (visual / tab_size + 1) * tab_size
+        } else {
+            visual + 1
+        };
+        prev = Some(c);
+    }
+    push_run(&mut decorations, line, run);
+
+    if trailing_start < len && mode != WhitespaceMode::Selection {
+        decorations.push(Decoration::new(
+            line,
+            trailing_start,
+            len,
+            "leptos-editor-ws-trailing",
+        ));
+    }
+    let show_break = match mode {
+        WhitespaceMode::All => true,
+        WhitespaceMode::Selection => selected(len),
+        _ => false,
+    };
+    if show_break && line + 1 < state.line_count() {
+        decorations.push(Decoration::marker(line, len, "leptos-editor-ws-eol"));
+    }
+    decorations
+}
+
+/// [`whitespace_decorations`] kept per line between refreshes, so that
+/// after an edit only the lines it touched are looked at again.
+#[derive(Debug, Clone, Default)]
+pub struct WhitespaceCache {
+    /// The version, mode and tab size the lines were computed for
+    key: Option<(u64, WhitespaceMode, usize)>,
+    /// The decorations of each line
+    lines: Vec<Vec<Decoration>>,
+}
+
+impl WhitespaceCache {
+    /// Create an empty cache.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the whitespace decorations of the document, updating the cache.
+    ///
+    /// When the state is one version past the cache, only the lines
+    /// touched by its [`EditorState::last_change`] are computed again;
+    /// otherwise every line is.
+    pub fn decorations(&mut self, state: &EditorState) -> Vec<Decoration> {
+        let mode = state.config.show_whitespace;
+        if matches!(mode, WhitespaceMode::None | WhitespaceMode::Selection) {
+            self.key = None;
+            self.lines.clear();
+            return whitespace_decorations(state);
+        }
+
+        let key = (state.version, mode, state.config.tab_size);
+        if self.key != Some(key) {
+            let follows = state
+                .version
+                .checked_sub(1)
+                .is_some_and(|previous| self.key == Some((previous, mode, state.config.tab_size)));
+            let updated = match &state.last_change {
+                Some((version, changes)) if follows && *version == state.version => {
+                    self.update(state, changes)
+                }
+                _ => false,
+            };
+            if !updated {
+                self.lines = (0..state.line_count())
+                    .map(|line| line_whitespace_decorations(state, line, &[]))
+                    .collect();
+            }
+            self.key = Some(key);
+        }
+        self.lines
+            .iter()
+            .enumerate()
+            .flat_map(|(line, decorations)| {
+                decorations.iter().map(move |decoration| Decoration {
+                    line,
+                    ..decoration.clone()
+                })
+            })
+            .collect()
+    }
+
+    /// Recompute the lines between the first and last edit of `changes`,
+    /// which turned the cached document into the state's. Returns `false`
+    /// if the cache does not fit the edit.
+    fn update(&mut self, state: &EditorState, changes: &ChangeSet) -> bool {
+        let edits = changes.text_changes();
+        let (Some(first), Some(last)) = (edits.first(), edits.last()) else {
+            return true;
+        };
+        let buffer = &state.buffer;
+        let first_line = buffer.line_of_offset(first.offset);
+        let new_last =
+            buffer.line_of_offset(changes.map_position(last.offset + last.deleted, Assoc::After));
+        // Lines after the last edit are unchanged, so count from the end
+        let Some(old_last) = (new_last + self.lines.len()).checked_sub(buffer.line_count()) else {
+            return false;
+        };
+        if old_last < first_line || old_last >= self.lines.len() {
+            return false;
+        }
+        let fresh =
+            (first_line..=new_last).map(|line| line_whitespace_decorations(state, line, &[]));
+        self.lines.splice(first_line..=old_last, fresh);
+        true
+    }
+}
+
+/// Add the decoration for a run of spaces or tabs.
+fn push_run(
+    decorations: &mut Vec<Decoration>,
+    line: usize,
+    run: Option<(char, usize, usize, bool)>,
+) {
+    if let Some((kind, start, end, _)) = run {
+        let class = if kind == '\t' {
+            "leptos-editor-ws-tab"
+        } else {
+            "leptos-editor-ws-space"
+        };
+        decorations.push(Decoration::new(line, start, end, class));
+    }
+}
+
+/// Render segments as overlay content.
+pub fn render_segments(segments: Vec<Segment>) -> impl IntoView {
+    segments
+        .into_iter()
+        .map(|segment| {
+            if segment.class.is_empty() {
+                segment.text.into_any()
+            } else {
+                view! { <span class=segment.class>{segment.text}</span> }.into_any()
+            }
+        })
+        .collect::<Vec<_>>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::components::editor::cursor::CursorPosition;
+
+    #[test]
+    fn test_line_segments() {
+        let selection = Decoration::new(0, 1, 3, "sel");
+        let cursor = Decoration::marker(0, 3, "cur");
+        let segments = line_segments("a日本b", &[&selection, &cursor]);
+
+        let parts: Vec<_> = segments
+            .iter()
+            .map(|s| (s.text.as_str(), s.class.as_str()))
+            .collect();
+        assert_eq!(parts, [("a", ""), ("日本", "sel"), ("", "cur"), ("b", "")]);
+    }
+
+    #[test]
+    fn test_document_segments_merge_plain_lines() {
+        let buffer = TextBuffer::from("one\ntwo\nthree");
+        let segments = document_segments(&buffer, &[Decoration::marker(1, 1, "cur")]);
+
+        assert_eq!(segments.len(), 3);
+        assert_eq!(segments[0].text, "one\nt");
+        assert_eq!(segments[1].class, "cur");
+        assert_eq!(segments[2].text, "wo\nthree");
+    }
+
+    #[test]
+    fn test_whitespace_decorations() {
+        let mut state = EditorState::new("\tlet  x = 1; \nend");
+        let shown = |state: &EditorState| {
+            whitespace_decorations(state)
+                .iter()
+                .map(|d| (d.line, d.start, d.end, d.class))
+                .collect::<Vec<_>>()
+        };
+
+        // Runs of spaces share one decoration
+        state.config.show_whitespace = WhitespaceMode::Boundary;
+        assert_eq!(
+            shown(&state),
+            [
+                (0, 0, 1, "leptos-editor-ws-tab"),
+                (0, 4, 6, "leptos-editor-ws-space"),
+                (0, 12, 13, "leptos-editor-ws-space"),
+                (0, 12, 13, "leptos-editor-ws-trailing"),
+            ]
+        );
+
+        state.config.show_whitespace = WhitespaceMode::All;
+        assert_eq!(
+            shown(&state).last(),
+            Some(&(0, 13, 13, "leptos-editor-ws-eol"))
+        );
+
+        assert_eq!(WhitespaceMode::from(false), WhitespaceMode::None);
+        state.config.show_whitespace = true.into();
+        assert_eq!(shown(&state).len(), 7);
+
+        state.config.show_whitespace = WhitespaceMode::Trailing;
+        assert_eq!(
+            shown(&state),
+            [
+                (0, 12, 13, "leptos-editor-ws-space"),
+                (0, 12, 13, "leptos-editor-ws-trailing"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_whitespace_in_selection_and_tab_runs() {
+        let mut state = EditorState::new("a b  c\nx\t\t\ty \nlast  ");
+        state.config.show_whitespace = WhitespaceMode::Selection;
+        assert!(whitespace_decorations(&state).is_empty());
+
+        // Only selected whitespace and line breaks, without the trailing
+        // highlight; lines outside the selection are skipped
+        state.set_cursor_with_selection(CursorPosition::new(1, 3), CursorPosition::new(0, 3));
+        let shown: Vec<_> = whitespace_decorations(&state)
+            .iter()
+            .map(|d| (d.line, d.start, d.end, d.class))
+            .collect();
+        assert_eq!(
+            shown,
+            [
+                (0, 3, 5, "leptos-editor-ws-space"),
+                (0, 6, 6, "leptos-editor-ws-eol"),
+                (1, 1, 2, "leptos-editor-ws-tab"),
+                (1, 2, 3, "leptos-editor-ws-tab"),
+            ]
+        );
+
+        // The first tab after `x` is not at a tab stop, so it has its own run
+        state.config.show_whitespace = WhitespaceMode::All;
+        let tabs: Vec<_> = whitespace_decorations(&state)
+            .into_iter()
+            .filter(|d| d.class == "leptos-editor-ws-tab")
+            .map(|d| (d.start, d.end))
+            .collect();
+        assert_eq!(tabs, [(1, 2), (2, 4)]);
+    }
+
+    #[test]
+    fn test_whitespace_cache_follows_edits() {
+        let mut state = EditorState::new("a  b\n\tc \nd\n  e");
+        state.config.show_whitespace = WhitespaceMode::All;
+        let mut cache = WhitespaceCache::new();
+        assert_eq!(cache.decorations(&state), whitespace_decorations(&state));
+
+        // An edit only recomputes its own line: the marker left on line 3
+        // survives an edit on line 0
+        cache.lines[3].push(Decoration::marker(3, 0, "kept"));
+        state.set_cursor(CursorPosition::new(0, 1));
+        state.insert(" ");
+        let decorations = cache.decorations(&state);
+        assert!(decorations.contains(&Decoration::marker(3, 0, "kept")));
+        cache.lines[3].pop();
+
+        // Lines added, removed and restored by undo move the rest along
+        state.set_cursor(CursorPosition::new(1, 0));
+        state.insert("x  \n\n");
+        assert_eq!(cache.decorations(&state), whitespace_decorations(&state));
+        state.set_cursor(CursorPosition::new(4, 0));
+        state.delete_backward();
+        assert_eq!(cache.decorations(&state), whitespace_decorations(&state));
+        assert!(state.undo());
+        assert_eq!(cache.decorations(&state), whitespace_decorations(&state));
+
+        // Several edits between refreshes rebuild every line
+        state.set_cursor(CursorPosition::new(5, 3));
+        state.insert("\t");
+        state.insert(" \n");
+        assert_eq!(cache.decorations(&state), whitespace_decorations(&state));
+    }
+}
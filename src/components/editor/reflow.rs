@@ -0,0 +1,128 @@
+//! Paragraph reflow
+//!
+//! Hard-wraps paragraphs to a maximum width, keeping the indentation and
+//! comment or quote prefix of their lines. Used by
+//! [`EditorState::reflow_paragraph`](super::EditorState::reflow_paragraph).
+
+/// Prefixes kept at the start of every reflowed line, in addition to the
+/// language's line comment.
+pub const REFLOW_MARKERS: [&str; 5] = ["///", "//!", "//", "#", ">"];
+
+/// Split a line into its prefix and its text.
+///
+/// The prefix is the indentation followed by the longest matching marker
+/// and one space after it, if there is one.
+#[must_use]
+pub fn split_prefix<'a>(line: &'a str, markers: &[&str]) -> (&'a str, &'a str) {
+    let indent = line.len() - line.trim_start_matches([' ', '\t']).len();
+    let marker = markers
+        .iter()
+        .filter(|marker| line[indent..].starts_with(**marker))
+        .map(|marker| marker.len())
+        .max()
+        .unwrap_or(0);
+    let mut end = indent + marker;
+    if marker > 0 && line[end..].starts_with(' ') {
+        end += 1;
+    }
+    line.split_at(end)
+}
+
+/// Check whether two lines belong to the same paragraph: both have text
+/// after the same prefix.
+#[must_use]
+pub fn same_paragraph(a: &str, b: &str, markers: &[&str]) -> bool {
+    let (a_prefix, a_text) = split_prefix(a, markers);
+    let (b_prefix, b_text) = split_prefix(b, markers);
+    !a_text.trim().is_empty()
+        && !b_text.trim().is_empty()
+        && a_prefix.trim_end() == b_prefix.trim_end()
+}
+
+/// Reflow lines so that none is wider than `width` columns.
+///
+/// Each run of lines with text after the same prefix is joined and wrapped
+/// again under the prefix of its first line. Lines without text separate
+/// paragraphs and are kept as they are. A word wider than the space left
+/// after the prefix gets a line of its own. Tabs in a prefix count up to
+/// the next multiple of `tab_size`.
+#[must_use]
+pub fn reflow(lines: &[&str], width: usize, tab_size: usize, markers: &[&str]) -> Vec<String> {
+    let mut reflowed = Vec::new();
+    let mut i = 0;
+    while i < lines.len() {
+        let (prefix, text) = split_prefix(lines[i], markers);
+        if text.trim().is_empty() {
+            reflowed.push(lines[i].to_string());
+            i += 1;
+            continue;
+        }
+
+        let mut words = Vec::new();
+        let mut end = i;
+        while end < lines.len() && (end == i || same_paragraph(lines[i], lines[end], markers)) {
+            words.extend(split_prefix(lines[end], markers).1.split_whitespace());
+            end += 1;
+        }
+        wrap(&mut reflowed, prefix, &words, width, tab_size);
+        i = end;
+    }
+    reflowed
+}
+
+/// Fill lines with words after a prefix.
+fn wrap(lines: &mut Vec<String>, prefix: &str, words: &[&str], width: usize, tab_size: usize) {
+    let prefix_width = prefix.chars().fold(0, |column, c| {
+        if c == '\t' {
+            (column / tab_size.max(1) + 1) * tab_size.max(1)
+        } else {
+            column + 1
+        }
+    });
+    let mut line = prefix.to_string();
+    let mut line_width = prefix_width;
+    for (i, word) in words.iter().enumerate() {
+        let word_width = word.chars().count();
+        if i > 0 && line_width + 1 + word_width > width {
+            lines.push(std::mem::replace(&mut line, prefix.to_string()));
+            line_width = prefix_width;
+        } else if i > 0 {
+            line.push(' ');
+            line_width += 1;
+        }
+        line.push_str(word);
+        line_width += word_width;
+    }
+    lines.push(line);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reflow_keeps_prefixes() {
+        let lines = [
+            "    // The quick brown fox",
+            "    // jumps over the lazy dog.",
+            "    //",
+            "    // Second paragraph.",
+        ];
+        assert_eq!(
+            reflow(&lines, 24, 4, &REFLOW_MARKERS),
+            [
+                "    // The quick brown",
+                "    // fox jumps over",
+                "    // the lazy dog.",
+                "    //",
+                "    // Second paragraph.",
+            ]
+        );
+
+        let quote = ["> one two", "> three", "plain text that is long"];
+        assert_eq!(
+            reflow(&quote, 10, 4, &REFLOW_MARKERS),
+            ["> one two", "> three", "plain text", "that is", "long"]
+        );
+    }
+}
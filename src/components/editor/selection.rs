@@ -2,6 +2,8 @@
 //!
 //! Manages text selection, selection ranges, and selection operations.
 
+use std::ops::{Range, RangeInclusive};
+
 use serde::{Deserialize, Serialize};
 
 use super::cursor::CursorPosition;
@@ -81,6 +83,46 @@ impl Selection {
     }
 }
 
+/// A rectangular (column) selection between two corners.
+///
+/// Columns are in chars and may lie past the end of a line (virtual space).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlockSelection {
+    /// The corner where the selection started
+    pub anchor: CursorPosition,
+    /// The corner that moves when the selection is extended
+    pub head: CursorPosition,
+}
+
+impl BlockSelection {
+    /// Create a block selection between two corners.
+    #[must_use]
+    pub const fn new(anchor: CursorPosition, head: CursorPosition) -> Self {
+        Self { anchor, head }
+    }
+
+    /// Get the lines covered by the block.
+    #[must_use]
+    pub fn lines(&self) -> RangeInclusive<usize> {
+        self.anchor.line.min(self.head.line)..=self.anchor.line.max(self.head.line)
+    }
+
+    /// Get the columns covered by the block.
+    #[must_use]
+    pub fn columns(&self) -> Range<usize> {
+        self.anchor.column.min(self.head.column)..self.anchor.column.max(self.head.column)
+    }
+
+    /// Get a zero-width block at `column` on the same lines.
+    #[must_use]
+    pub const fn collapsed_at(&self, column: usize) -> Self {
+        Self {
+            anchor: CursorPosition::new(self.anchor.line, column),
+            head: CursorPosition::new(self.head.line, column),
+        }
+    }
+}
+
 /// The type of selection being made.
 #[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
 pub enum SelectionMode {
@@ -106,37 +148,25 @@ pub enum SelectionDirection {
 }
 
 /// Get the word boundaries around a position in text.
+///
+/// `column` and the returned `(start, end)` columns are in chars.
 #[must_use]
-#[allow(dead_code)]
 pub fn word_at_position(text: &str, line: usize, column: usize) -> Option<(usize, usize)> {
-    let lines: Vec<&str> = text.lines().collect();
-    let line_text = lines.get(line)?;
+    let line_text = text.split('\n').nth(line)?;
+    let chars: Vec<char> = line_text.chars().collect();
 
-    if column > line_text.len() {
+    if column > chars.len() {
         return None;
     }
 
-    // Find word start
-    let mut start = column;
-    for (i, c) in line_text[..column].char_indices().rev() {
-        if !is_word_char(c) {
-            start = i + c.len_utf8();
-            break;
-        }
-        if i == 0 {
-            start = 0;
-        }
-    }
-
-    // Find word end
-    let mut end = column;
-    for (i, c) in line_text[column..].char_indices() {
-        if !is_word_char(c) {
-            end = column + i;
-            break;
-        }
-        end = column + i + c.len_utf8();
-    }
+    let start = chars[..column]
+        .iter()
+        .rposition(|&c| !is_word_char(c))
+        .map_or(0, |i| i + 1);
+    let end = chars[column..]
+        .iter()
+        .position(|&c| !is_word_char(c))
+        .map_or(chars.len(), |i| column + i);
 
     if start == end {
         None
@@ -146,8 +176,7 @@ pub fn word_at_position(text: &str, line: usize, column: usize) -> Option<(usize
 }
 
 /// Check if a character is part of a word.
-#[allow(dead_code)]
-fn is_word_char(c: char) -> bool {
+pub(crate) fn is_word_char(c: char) -> bool {
     c.is_alphanumeric() || c == '_'
 }
 
@@ -181,5 +210,9 @@ mod tests {
         assert_eq!(word_at_position(text, 0, 2), Some((0, 5)));
         assert_eq!(word_at_position(text, 0, 8), Some((6, 11)));
         assert_eq!(word_at_position(text, 0, 15), Some((12, 19)));
+
+        // Columns are chars, not bytes
+        assert_eq!(word_at_position("日本 語", 0, 1), Some((0, 2)));
+        assert_eq!(word_at_position("日本 語", 0, 4), Some((3, 4)));
     }
 }
@@ -2,13 +2,37 @@
 //!
 //! Centralized state for the editor component.
 
-use serde::{Deserialize, Serialize};
+use std::{
+    borrow::Cow,
+    cmp::Ordering,
+    ops::{Range, RangeInclusive},
+};
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::helpers::LineEnding;
 
 use super::{
+    brackets::{self, BracketMatch},
+    buffer::TextBuffer,
     cursor::{Cursor, CursorPosition, CursorSet},
-    history::History,
+    history::{History, HistoryEntry},
+    language::Language,
+    movement::Movement,
+    reflow::{REFLOW_MARKERS, reflow, same_paragraph, split_prefix},
+    selection::{BlockSelection, Selection, SelectionMode, word_at_position},
+    transaction::{Assoc, ChangeEvent, ChangeOrigin, ChangeSet, Transaction},
+    transform::TextTransform,
 };
 
+#[cfg(feature = "find-replace")]
+use super::find_replace::FindState;
+#[cfg(feature = "folding")]
+use super::folding::FoldState;
+
+/// A replacement, with the selection to place within the new text.
+type PlacedEdit = (Range<usize>, (String, Range<usize>));
+
 /// Editor configuration options.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[allow(clippy::struct_excessive_bools)]
@@ -23,8 +47,9 @@ pub struct EditorConfig {
     pub show_line_numbers: bool,
     /// Whether to highlight the current line
     pub highlight_current_line: bool,
-    /// Whether to show whitespace characters
-    pub show_whitespace: bool,
+    /// Which whitespace characters to show
+    #[serde(deserialize_with = "deserialize_whitespace_mode")]
+    pub show_whitespace: WhitespaceMode,
     /// Whether bracket matching is enabled
     pub match_brackets: bool,
     /// Whether auto-indent is enabled
@@ -35,8 +60,11 @@ pub struct EditorConfig {
     pub font_size: f32,
     /// Line height multiplier (1.0 = same as font size)
     pub line_height: f32,
-    /// Maximum line width (0 = no limit)
+    /// Maximum line width (0 = no limit), shown as a ruler and used by
+    /// [`EditorState::reflow_paragraph`]
     pub max_line_width: usize,
+    /// Columns to draw vertical rulers at
+    pub rulers: Vec<usize>,
     /// Whether the editor is read-only
     pub read_only: bool,
 }
@@ -49,23 +77,93 @@ impl Default for EditorConfig {
             word_wrap: true,
             show_line_numbers: true,
             highlight_current_line: true,
-            show_whitespace: false,
+            show_whitespace: WhitespaceMode::None,
             match_brackets: true,
             auto_indent: true,
             auto_close_brackets: true,
             font_size: 14.0,
             line_height: 1.5,
             max_line_width: 0,
+            rulers: Vec::new(),
             read_only: false,
         }
     }
 }
 
+impl EditorConfig {
+    /// Get the text inserted for one level of indentation.
+    #[must_use]
+    pub fn indent_unit(&self) -> String {
+        if self.insert_spaces {
+            " ".repeat(self.tab_size)
+        } else {
+            "\t".to_string()
+        }
+    }
+
+    /// Get the columns to draw rulers at: [`Self::rulers`] and
+    /// [`Self::max_line_width`], sorted and without repeats.
+    #[must_use]
+    pub fn ruler_columns(&self) -> Vec<usize> {
+        let mut columns = self.rulers.clone();
+        if self.max_line_width > 0 {
+            columns.push(self.max_line_width);
+        }
+        columns.sort_unstable();
+        columns.dedup();
+        columns
+    }
+}
+
+/// Which whitespace [`EditorConfig::show_whitespace`] makes visible.
+///
+/// Spaces are drawn as `·`, tabs as `→` and line breaks as `¬`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WhitespaceMode {
+    /// Show no whitespace
+    #[default]
+    None,
+    /// Show all whitespace except single spaces between words
+    Boundary,
+    /// Show whitespace inside selections
+    Selection,
+    /// Show trailing whitespace only
+    Trailing,
+    /// Show all whitespace and line breaks
+    All,
+}
+
+/// `true` shows all whitespace and `false` none, as the `bool`
+/// `show_whitespace` setting did before there were modes.
+impl From<bool> for WhitespaceMode {
+    fn from(show: bool) -> Self {
+        if show { Self::All } else { Self::None }
+    }
+}
+
+/// Deserialize [`EditorConfig::show_whitespace`] from a mode, or from a
+/// `bool` as saved before there were modes.
+fn deserialize_whitespace_mode<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<WhitespaceMode, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Setting {
+        Show(bool),
+        Mode(WhitespaceMode),
+    }
+
+    Ok(match Setting::deserialize(deserializer)? {
+        Setting::Show(show) => show.into(),
+        Setting::Mode(mode) => mode,
+    })
+}
+
 /// The complete state of an editor instance.
 #[derive(Debug, Clone)]
 pub struct EditorState {
     /// The document content
-    pub content: String,
+    pub buffer: TextBuffer,
     /// Cursor positions (supports multi-cursor)
     pub cursors: CursorSet,
     /// Edit history for undo/redo
@@ -82,12 +180,32 @@ pub struct EditorState {
     pub scroll_offset: f32,
     /// Detected or explicitly set language
     pub language: Option<String>,
+    /// Granularity used when extending the selection with the mouse
+    pub selection_mode: SelectionMode,
+    /// Where a word, line or block selection started
+    pub selection_origin: Option<Selection>,
+    /// The last block selection; only active while the cursors match it
+    pub block_selection: Option<BlockSelection>,
+    /// Line ending of the document; the buffer itself always uses `\n`
+    pub line_ending: LineEnding,
+    /// Changes since the last [`Self::take_change`], composed into one, and
+    /// the cursors before them
+    pub pending_change: Option<(ChangeSet, CursorSet)>,
+    /// The most recent change to the text and the version it produced, so
+    /// views can update only what it touched
+    pub last_change: Option<(u64, ChangeSet)>,
+    /// Fold regions, kept in place as the document is edited
+    #[cfg(feature = "folding")]
+    pub folds: FoldState,
+    /// Find matches, kept in place as the document is edited
+    #[cfg(feature = "find-replace")]
+    pub find: FindState,
 }
 
 impl Default for EditorState {
     fn default() -> Self {
         Self {
-            content: String::new(),
+            buffer: TextBuffer::new(),
             cursors: CursorSet::new(Cursor::zero()),
             history: History::new(),
             config: EditorConfig::default(),
@@ -96,6 +214,16 @@ impl Default for EditorState {
             scroll_line: 0,
             scroll_offset: 0.0,
             language: None,
+            selection_mode: SelectionMode::Character,
+            selection_origin: None,
+            block_selection: None,
+            line_ending: LineEnding::default(),
+            pending_change: None,
+            last_change: None,
+            #[cfg(feature = "folding")]
+            folds: FoldState::new(),
+            #[cfg(feature = "find-replace")]
+            find: FindState::new(),
         }
     }
 }
@@ -103,46 +231,167 @@ impl Default for EditorState {
 impl EditorState {
     /// Create a new editor state with the given content.
     #[must_use]
-    pub fn new(content: impl Into<String>) -> Self {
-        Self {
-            content: content.into(),
-            ..Default::default()
-        }
+    pub fn new(content: impl Into<TextBuffer>) -> Self {
+        Self::with_config(content, EditorConfig::default())
     }
 
     /// Create with custom configuration.
+    ///
+    /// The content's line ending is detected and its line breaks are
+    /// stored as `\n`.
     #[must_use]
-    pub fn with_config(content: impl Into<String>, config: EditorConfig) -> Self {
+    pub fn with_config(content: impl Into<TextBuffer>, config: EditorConfig) -> Self {
+        let (buffer, line_ending) = normalize_buffer(content.into());
         Self {
-            content: content.into(),
+            buffer,
             config,
+            line_ending: line_ending.unwrap_or_default(),
             ..Default::default()
         }
     }
 
-    /// Get the current content.
+    /// Get the current content as a string.
+    ///
+    /// This copies the whole document; prefer [`Self::buffer`] for reads
+    /// that only need part of it.
+    #[must_use]
+    pub fn content(&self) -> String {
+        self.buffer.to_string()
+    }
+
+    /// Get the content with the document's [`LineEnding`], as it should be
+    /// saved.
     #[must_use]
-    pub fn content(&self) -> &str {
-        &self.content
+    pub fn document_text(&self) -> String {
+        let content = self.content();
+        match self.line_ending.apply(&content) {
+            Cow::Borrowed(_) => content,
+            Cow::Owned(text) => text,
+        }
     }
 
     /// Set new content.
+    ///
+    /// The change is recorded as the minimal edit between the old and new
+    /// text, so cursors outside the changed range keep their place.
     pub fn set_content(&mut self, content: impl Into<String>) {
         let new_content = content.into();
-        if new_content != self.content {
-            // Save to history before modifying
-            self.history
-                .push(self.content.clone(), self.cursors.clone());
-            self.content = new_content;
-            self.version += 1;
-            self.is_modified = true;
+        let new_content = LineEnding::normalize(&new_content);
+        if self.buffer != *new_content {
+            let changes = ChangeSet::diff(&self.buffer.to_string(), &new_content);
+            self.apply_unchecked(&Transaction::new(changes));
+        }
+    }
+
+    /// Update the document to `content`, an edited copy of it in which only
+    /// the UTF-16 range `replaced` of the current text was replaced by the
+    /// text up to `new_end` in `content`.
+    ///
+    /// This is how the textarea reports typing: only the window is
+    /// compared, so an edit costs the same in any size of document. Falls
+    /// back to [`Self::set_content`] if the window does not fit `content`.
+    pub fn set_content_within(&mut self, content: &str, replaced: Range<usize>, new_end: usize) {
+        let buffer = &self.buffer;
+        let start = buffer.utf16_to_offset(replaced.start);
+        let old_end = buffer.utf16_to_offset(replaced.end);
+        let new_end = new_end
+            .checked_sub(replaced.start)
+            .zip(content.get(start..))
+            .and_then(|(units, rest)| utf16_prefix_len(rest, units))
+            .map(|len| start + len);
+        let fits = buffer.offset_to_utf16(start) == replaced.start
+            && buffer.offset_to_utf16(old_end) == replaced.end
+            && start <= old_end
+            && new_end.is_some_and(|end| content.len() - end == buffer.len() - old_end);
+        let (Some(new_end), true) = (new_end, fits) else {
+            self.set_content(content);
+            return;
+        };
+
+        let old = buffer.slice(start..old_end).unwrap_or_default();
+        let new = LineEnding::normalize(&content[start..new_end]);
+        let edits: Vec<_> = ChangeSet::diff(&old, &new)
+            .text_changes()
+            .into_iter()
+            .map(|change| {
+                let from = start + change.offset;
+                (from..from + change.deleted, change.inserted)
+            })
+            .collect();
+        if !edits.is_empty() {
+            let changes = ChangeSet::from_edits(buffer.len(), edits);
+            self.apply_unchecked(&Transaction::new(changes));
+        }
+    }
+
+    /// Bring the document in line with content changed outside the editor.
+    ///
+    /// Like [`Self::set_content`], but the change is always a separate
+    /// undo step, and the line ending is taken from the new content if it
+    /// has line breaks. Returns `false` if nothing changed.
+    pub fn sync_content(&mut self, content: &str) -> bool {
+        let line_ending = LineEnding::detect(content).unwrap_or(self.line_ending);
+        let ending_changed = line_ending != self.line_ending;
+        self.line_ending = line_ending;
+
+        let content = LineEnding::normalize(content);
+        if self.buffer == *content {
+            return ending_changed;
         }
+        self.history.checkpoint();
+        self.set_content(content);
+        self.history.checkpoint();
+        true
+    }
+
+    /// Change the line ending used by [`Self::document_text`].
+    ///
+    /// The content is stored with `\n` line breaks either way, so this is
+    /// not an undoable edit, but it does count as a modification. Returns
+    /// `false` if the editor is read-only or the line ending is unchanged.
+    pub fn set_line_ending(&mut self, line_ending: LineEnding) -> bool {
+        if self.config.read_only || line_ending == self.line_ending {
+            return false;
+        }
+        self.line_ending = line_ending;
+        self.version += 1;
+        self.is_modified = true;
+        true
+    }
+
+    /// Load a new document, clearing the history.
+    ///
+    /// The cursor moves to the start and the content counts as saved.
+    pub fn reset_content(&mut self, content: impl Into<TextBuffer>) {
+        let (content, line_ending) = normalize_buffer(content.into());
+        self.line_ending = line_ending.unwrap_or_default();
+        let changes = ChangeSet::replace(
+            self.buffer.len(),
+            0..self.buffer.len(),
+            &content.to_string(),
+        );
+        self.record_change(changes.clone(), self.cursors.clone());
+        let before = std::mem::replace(&mut self.buffer, content);
+        self.map_annotations(&changes, &before);
+        self.cursors = CursorSet::new(Cursor::zero());
+        self.history.clear();
+        self.selection_origin = None;
+        self.block_selection = None;
+        self.is_modified = false;
     }
 
     /// Replace content without adding to history (for external updates).
+    ///
+    /// The history is cleared, since its edits no longer apply to the new
+    /// content.
     pub fn replace_content(&mut self, content: impl Into<String>) {
-        self.content = content.into();
-        self.version += 1;
+        let content = content.into();
+        let content = LineEnding::normalize(&content).into_owned();
+        let changes = ChangeSet::replace(self.buffer.len(), 0..self.buffer.len(), &content);
+        self.record_change(changes.clone(), self.cursors.clone());
+        let before = std::mem::replace(&mut self.buffer, TextBuffer::from(content));
+        self.map_annotations(&changes, &before);
+        self.history.clear();
     }
 
     /// Get the primary cursor position.
@@ -163,294 +412,1773 @@ impl EditorState {
         cursor.anchor = anchor;
     }
 
+    /// Get the text selected by the primary cursor, if any.
+    #[must_use]
+    pub fn selected_text(&self) -> Option<String> {
+        self.selection_range(self.cursors.primary())
+            .filter(|range| !range.is_empty())
+            .and_then(|range| self.buffer.slice(range))
+    }
+
     /// Get the line count.
     #[must_use]
     pub fn line_count(&self) -> usize {
-        if self.content.is_empty() {
-            1
+        self.buffer.line_count()
+    }
+
+    /// Get a specific line (0-indexed), without its line terminator.
+    #[must_use]
+    pub fn get_line(&self, index: usize) -> Option<Cow<'_, str>> {
+        self.buffer.line(index)
+    }
+
+    /// Apply a transaction to the document.
+    ///
+    /// Records the previous state in history, applies the changes and then
+    /// either uses the transaction's selection or maps the existing cursors
+    /// through the changes. Returns `false` (and changes nothing) if the
+    /// editor is read-only or the transaction was built for a different
+    /// document length.
+    pub fn apply(&mut self, transaction: &Transaction) -> bool {
+        if self.config.read_only || transaction.changes().len_before() != self.buffer.len() {
+            return false;
+        }
+        self.apply_unchecked(transaction);
+        true
+    }
+
+    /// Apply a transaction without the read-only check.
+    fn apply_unchecked(&mut self, transaction: &Transaction) {
+        let changes = transaction.changes();
+        let before = self.buffer.clone();
+        self.history.push(
+            changes.clone(),
+            changes.invert(&before),
+            self.cursors.clone(),
+        );
+        self.record_change(changes.clone(), self.cursors.clone());
+        changes.apply(&mut self.buffer);
+        self.map_annotations(changes, &before);
+
+        if let Some(selection) = transaction.selection() {
+            self.cursors = selection.clone();
         } else {
-            self.content.chars().filter(|&c| c == '\n').count() + 1
+            let after = &self.buffer;
+            self.cursors.map_positions(|pos| {
+                before
+                    .line_col_to_offset(pos.line, pos.column)
+                    .map(|offset| changes.map_position(offset, Assoc::After))
+                    .and_then(|offset| after.offset_to_line_col(offset))
+                    .map_or(pos, |(line, column)| CursorPosition::new(line, column))
+            });
         }
+
+        self.is_modified = true;
     }
 
-    /// Get a specific line (0-indexed).
-    #[must_use]
-    pub fn get_line(&self, index: usize) -> Option<&str> {
-        self.content.lines().nth(index)
+    /// Keep fold regions and find matches in place across `changes`, which
+    /// were just applied to `before`.
+    #[cfg_attr(
+        not(any(feature = "folding", feature = "find-replace")),
+        allow(clippy::unused_self)
+    )]
+    fn map_annotations(&mut self, changes: &ChangeSet, before: &TextBuffer) {
+        #[cfg(feature = "folding")]
+        self.folds.map_through(changes, before, &self.buffer);
+        #[cfg(feature = "find-replace")]
+        self.find.map_through(changes);
+        #[cfg(not(feature = "folding"))]
+        let _ = before;
+        #[cfg(not(any(feature = "folding", feature = "find-replace")))]
+        let _ = changes;
     }
 
-    /// Insert text at the current cursor position.
+    /// Add changes to [`Self::pending_change`] and keep them as
+    /// [`Self::last_change`] of a new [`Self::version`].
+    fn record_change(&mut self, changes: ChangeSet, cursors: CursorSet) {
+        self.version += 1;
+        self.last_change = Some((self.version, changes.clone()));
+        self.pending_change = match self.pending_change.take() {
+            Some((pending, before)) if pending.len_after() == changes.len_before() => {
+                Some((pending.compose(&changes), before))
+            }
+            _ => Some((changes, cursors)),
+        };
+    }
+
+    /// Take the changes made since the last call as a [`ChangeEvent`].
+    ///
+    /// Returns `None` if the document has not changed.
+    pub fn take_change(&mut self, origin: ChangeOrigin) -> Option<ChangeEvent> {
+        let (changes, cursors_before) = self.pending_change.take()?;
+        let changes = changes.text_changes();
+        (!changes.is_empty()).then(|| ChangeEvent {
+            changes,
+            cursors_before,
+            cursors_after: self.cursors.clone(),
+            origin,
+            version: self.version,
+        })
+    }
+
+    /// Replace a range at every cursor in one undoable step.
+    ///
+    /// `edit` receives each cursor's selection as a byte range and returns
+    /// the range to replace and its replacement, or `None` to leave that
+    /// cursor alone. All edits are made against the document as it was
+    /// before the call; a range that overlaps an earlier one is dropped.
+    fn edit_cursors(
+        &mut self,
+        mut edit: impl FnMut(&Self, Range<usize>) -> Option<(Range<usize>, String)>,
+    ) -> bool {
+        let mut edits: Vec<_> = self
+            .cursors
+            .all()
+            .iter()
+            .filter_map(|cursor| self.selection_range(cursor))
+            .filter_map(|range| edit(self, range))
+            .collect();
+        drop_overlapping(&mut edits);
+        if edits.is_empty() {
+            return false;
+        }
+
+        let changes = ChangeSet::from_edits(self.buffer.len(), edits);
+        self.apply(&Transaction::new(changes))
+    }
+
+    /// Replace the selection at every cursor and place each new selection
+    /// inside its replacement.
+    ///
+    /// Like [`Self::edit_cursors`], but `edit` also returns the new
+    /// selection as a byte range within the replacement text.
+    fn edit_cursors_placing(
+        &mut self,
+        mut edit: impl FnMut(&Self, Range<usize>) -> PlacedEdit,
+    ) -> bool {
+        let mut edits: Vec<_> = self
+            .cursors
+            .all()
+            .iter()
+            .filter_map(|cursor| self.selection_range(cursor))
+            .map(|range| edit(self, range))
+            .collect();
+        drop_overlapping(&mut edits);
+        if edits.is_empty() {
+            return false;
+        }
+
+        let changes = ChangeSet::from_edits(
+            self.buffer.len(),
+            edits
+                .iter()
+                .map(|(range, (text, _))| (range.clone(), text.as_str())),
+        );
+        if changes.is_empty() {
+            return false;
+        }
+        let mut after = self.buffer.clone();
+        changes.apply(&mut after);
+        let mut carets = edits.iter().filter_map(|(range, (_, selection))| {
+            let start = changes.map_position(range.start, Assoc::Before);
+            let position = |offset| {
+                let (line, column) = after.offset_to_line_col(start + offset)?;
+                Some(CursorPosition::new(line, column))
+            };
+            Some(Cursor::with_selection(
+                position(selection.end)?,
+                position(selection.start)?,
+            ))
+        });
+        let Some(first) = carets.next() else {
+            return false;
+        };
+        let mut cursors = CursorSet::new(first);
+        carets.for_each(|cursor| cursors.add(cursor));
+        self.apply(&Transaction::new(changes).with_selection(cursors))
+    }
+
+    /// Get the byte range covered by a cursor's selection.
+    fn selection_range(&self, cursor: &Cursor) -> Option<Range<usize>> {
+        Some(
+            self.position_to_offset(cursor.selection_start())?
+                ..self.position_to_offset(cursor.selection_end())?,
+        )
+    }
+
+    /// Insert text at every cursor.
+    ///
+    /// Replaces the selections if there are any.
     pub fn insert(&mut self, text: &str) {
-        if self.config.read_only {
+        let text = &*LineEnding::normalize(text);
+        if let Some(block) = self.active_block()
+            && !text.contains('\n')
+        {
+            self.edit_block(block, block.columns(), &[text]);
             return;
         }
+        self.edit_cursors(|_, range| Some((range, text.to_string())));
+    }
 
-        let position = self.cursor_position();
-        if let Some(offset) = self.position_to_offset(position) {
-            self.history
-                .push(self.content.clone(), self.cursors.clone());
+    /// Indent at every cursor.
+    ///
+    /// If any selection spans several lines, every line touched by a
+    /// selection is indented by one level ([`EditorConfig::indent_unit`])
+    /// and the selections are kept. Otherwise one level is inserted at
+    /// each cursor.
+    pub fn indent(&mut self) {
+        let unit = self.config.indent_unit();
+        let multi_line = self
+            .cursors
+            .all()
+            .iter()
+            .any(|cursor| cursor.head.line != cursor.anchor.line);
+        if !multi_line || self.active_block().is_some() {
+            self.insert(&unit);
+            return;
+        }
+        let edits: Vec<_> = self
+            .selected_lines()
+            .into_iter()
+            .filter_map(|line| self.buffer.line_start(line))
+            .map(|start| (start..start, unit.as_str()))
+            .collect();
+        let changes = ChangeSet::from_edits(self.buffer.len(), edits);
+        self.apply(&Transaction::new(changes));
+    }
 
-            // Handle selection - delete selected text first
-            let cursor = self.cursors.primary();
-            if cursor.has_selection() {
-                let (start, end) = (
-                    self.position_to_offset(cursor.selection_start()),
-                    self.position_to_offset(cursor.selection_end()),
-                );
-                if let (Some(start), Some(end)) = (start, end) {
-                    self.content =
-                        format!("{}{}{}", &self.content[..start], text, &self.content[end..]);
-                    // Move cursor to end of inserted text
-                    let new_offset = start + text.len();
-                    if let Some(new_pos) = self.offset_to_position(new_offset) {
-                        self.set_cursor(new_pos);
+    /// Remove one level of indentation from every line touched by a
+    /// cursor, keeping the selections.
+    ///
+    /// A leading tab counts as one level; leading spaces are removed back
+    /// to the previous multiple of [`EditorConfig::tab_size`].
+    pub fn outdent(&mut self) {
+        let tab_size = self.config.tab_size.max(1);
+        let edits: Vec<_> = self
+            .selected_lines()
+            .into_iter()
+            .filter_map(|line| {
+                let start = self.buffer.line_start(line)?;
+                let text = self.buffer.line(line)?;
+                let len = if text.starts_with('\t') {
+                    1
+                } else {
+                    let spaces = text.chars().take_while(|c| *c == ' ').count();
+                    match spaces % tab_size {
+                        0 => spaces.min(tab_size),
+                        partial => partial,
                     }
-                }
+                };
+                (len > 0).then(|| (start..start + len, ""))
+            })
+            .collect();
+        if edits.is_empty() {
+            return;
+        }
+        let changes = ChangeSet::from_edits(self.buffer.len(), edits);
+        self.apply(&Transaction::new(changes));
+    }
+
+    /// Get the lines touched by the cursors, in order.
+    fn selected_lines(&self) -> Vec<usize> {
+        self.selected_line_groups().into_iter().flatten().collect()
+    }
+
+    /// Get the lines touched by each cursor, in order, with the groups of
+    /// cursors that share a line merged.
+    ///
+    /// A selection that ends at the start of a line does not include that
+    /// line.
+    fn selected_line_groups(&self) -> Vec<RangeInclusive<usize>> {
+        let mut groups: Vec<RangeInclusive<usize>> = Vec::new();
+        for cursor in self.cursors.all() {
+            let start = cursor.selection_start();
+            let end = cursor.selection_end();
+            let last = if end.column == 0 && end.line > start.line {
+                end.line - 1
             } else {
-                // No selection - just insert
-                self.content.insert_str(offset, text);
-                let new_offset = offset + text.len();
-                if let Some(new_pos) = self.offset_to_position(new_offset) {
-                    self.set_cursor(new_pos);
+                end.line
+            };
+            groups.push(start.line..=last);
+        }
+        groups.sort_unstable_by_key(|lines| *lines.start());
+
+        let mut merged: Vec<RangeInclusive<usize>> = Vec::new();
+        for lines in groups {
+            match merged.last_mut() {
+                Some(last) if lines.start() <= last.end() => {
+                    *last = *last.start()..=*last.end().max(lines.end());
                 }
+                _ => merged.push(lines),
             }
-
-            self.version += 1;
-            self.is_modified = true;
         }
+        merged
     }
 
-    /// Delete the character before the cursor (backspace).
-    pub fn delete_backward(&mut self) {
-        if self.config.read_only {
+    /// Comment out or uncomment every line touched by a cursor.
+    ///
+    /// Uses the language's line comment token, or wraps each line in its
+    /// block comment tokens when it has none (as in HTML and CSS). The
+    /// marker goes at the smallest indentation of the lines of each
+    /// selection, and blank lines are skipped. If every non-blank line is
+    /// already commented, the comments are removed instead.
+    pub fn toggle_line_comment(&mut self) {
+        let language = self.language_kind();
+        let Some((open, close)) =
+            language
+                .line_comment()
+                .map(|token| (token, None))
+                .or_else(|| {
+                    language
+                        .block_comment()
+                        .map(|(open, close)| (open, Some(close)))
+                })
+        else {
             return;
-        }
+        };
 
-        let cursor = self.cursors.primary();
-        if cursor.has_selection() {
-            self.delete_selection();
+        let mut edits = Vec::new();
+        for lines in self.selected_line_groups() {
+            let texts: Vec<_> = lines
+                .filter_map(|line| Some((self.buffer.line_start(line)?, self.buffer.line(line)?)))
+                .filter(|(_, text)| !text.trim().is_empty())
+                .collect();
+            let commented = texts.iter().all(|(_, text)| {
+                let body = text.trim();
+                body.strip_prefix(open)
+                    .is_some_and(|rest| close.is_none_or(|close| rest.ends_with(close)))
+            });
+
+            if commented {
+                for (start, text) in &texts {
+                    let from = start + indent_len(text);
+                    let mut to = from + open.len();
+                    if text[to - start..].starts_with(' ') {
+                        to += 1;
+                    }
+                    edits.push((from..to, String::new()));
+                    if let Some(close) = close {
+                        let end = start + text.trim_end().len();
+                        let mut from = end - close.len();
+                        if from > to && text[..from - start].ends_with(' ') {
+                            from -= 1;
+                        }
+                        edits.push((from..end, String::new()));
+                    }
+                }
+            } else {
+                let indent = texts
+                    .iter()
+                    .map(|(_, text)| indent_len(text))
+                    .min()
+                    .unwrap_or(0);
+                for (start, text) in &texts {
+                    edits.push((start + indent..start + indent, format!("{open} ")));
+                    if let Some(close) = close {
+                        let end = start + text.trim_end().len();
+                        edits.push((end..end, format!(" {close}")));
+                    }
+                }
+            }
+        }
+        drop_overlapping(&mut edits);
+        if edits.is_empty() {
             return;
         }
+        let changes = ChangeSet::from_edits(self.buffer.len(), edits);
+        self.apply(&Transaction::new(changes));
+    }
 
-        let position = cursor.head;
-        if let Some(offset) = self.position_to_offset(position) {
-            if offset == 0 {
-                return;
+    /// Wrap each selection in the language's block comment tokens, or
+    /// unwrap it if it already is a block comment.
+    ///
+    /// An empty selection toggles the comment around the text of its line.
+    pub fn toggle_block_comment(&mut self) {
+        let Some((open, close)) = self.language_kind().block_comment() else {
+            return;
+        };
+        self.edit_cursors_placing(|state, range| {
+            let range = if range.is_empty() {
+                let line = state
+                    .buffer
+                    .line_range(state.buffer.line_of_offset(range.start))
+                    .unwrap_or(range);
+                let text = state.buffer.slice(line.clone()).unwrap_or_default();
+                let start = line.start + indent_len(&text);
+                start..(line.start + text.trim_end().len()).max(start)
+            } else {
+                range
+            };
+            // Take in comment tokens just around the selection
+            let range = [format!("{open} "), open.to_string()]
+                .into_iter()
+                .zip([format!(" {close}"), close.to_string()])
+                .find_map(|(before, after)| {
+                    let start = range.start.checked_sub(before.len())?;
+                    let end = range.end + after.len();
+                    (state.buffer.slice(start..range.start)? == before
+                        && state.buffer.slice(range.end..end)? == after)
+                        .then_some(start..end)
+                })
+                .unwrap_or(range);
+            let text = state.buffer.slice(range.clone()).unwrap_or_default();
+            if let Some(inner) = text
+                .strip_prefix(open)
+                .and_then(|rest| rest.strip_suffix(close))
+            {
+                let inner = inner.strip_prefix(' ').unwrap_or(inner);
+                let inner = inner.strip_suffix(' ').unwrap_or(inner);
+                let len = inner.len();
+                (range, (inner.to_string(), 0..len))
+            } else {
+                let start = open.len() + 1;
+                let len = text.len();
+                (
+                    range,
+                    (format!("{open} {text} {close}"), start..start + len),
+                )
             }
+        });
+    }
 
-            self.history
-                .push(self.content.clone(), self.cursors.clone());
+    /// Move the lines touched by the cursors up by one line.
+    pub fn move_lines_up(&mut self) {
+        self.move_lines(true);
+    }
 
-            // Find the previous character boundary
-            let prev_offset = self.content[..offset]
-                .char_indices()
-                .last()
-                .map_or(0, |(i, _)| i);
+    /// Move the lines touched by the cursors down by one line.
+    pub fn move_lines_down(&mut self) {
+        self.move_lines(false);
+    }
 
-            self.content = format!(
-                "{}{}",
-                &self.content[..prev_offset],
-                &self.content[offset..]
-            );
+    /// Swap each block of touched lines with the line above or below it.
+    ///
+    /// Does nothing if a block is already at the top or bottom.
+    fn move_lines(&mut self, up: bool) {
+        let mut blocks: Vec<RangeInclusive<usize>> = Vec::new();
+        for lines in self.selected_line_groups() {
+            match blocks.last_mut() {
+                Some(last) if *lines.start() == last.end() + 1 => {
+                    *last = *last.start()..=*lines.end();
+                }
+                _ => blocks.push(lines),
+            }
+        }
+        let last_line = self.line_count() - 1;
+        let at_edge = if up {
+            blocks.first().is_some_and(|lines| *lines.start() == 0)
+        } else {
+            blocks.last().is_some_and(|lines| *lines.end() == last_line)
+        };
+        if at_edge {
+            return;
+        }
 
-            if let Some(new_pos) = self.offset_to_position(prev_offset) {
-                self.set_cursor(new_pos);
+        let edits: Vec<_> = blocks
+            .iter()
+            .filter_map(|lines| {
+                let (a, b) = (*lines.start(), *lines.end());
+                let block = self.line_span(a..=b)?;
+                let block = self.buffer.slice(block)?;
+                if up {
+                    let other = self.buffer.line(a - 1)?;
+                    Some((self.line_span(a - 1..=b)?, format!("{block}\n{other}")))
+                } else {
+                    let other = self.buffer.line(b + 1)?;
+                    Some((self.line_span(a..=b + 1)?, format!("{other}\n{block}")))
+                }
+            })
+            .collect();
+        // Only the ends of selections that stop at the start of a line can
+        // be on the line after a block, and they stay after it
+        self.apply_line_edits(edits, |line| {
+            for lines in &blocks {
+                let (a, b) = (*lines.start(), *lines.end());
+                match (up, line) {
+                    (true, line) if lines.contains(&line) => return line - 1,
+                    (false, line) if lines.contains(&line) => return line + 1,
+                    (true, line) if line == b + 1 || line + 1 == a => return b,
+                    (false, line) if line == b + 1 => return b + 2,
+                    _ => {}
+                }
             }
+            line
+        });
+    }
 
-            self.version += 1;
-            self.is_modified = true;
-        }
+    /// Duplicate the lines touched by the cursors, keeping the cursors on
+    /// the upper copy.
+    pub fn copy_lines_up(&mut self) {
+        self.copy_lines(true);
     }
 
-    /// Delete the character after the cursor (delete).
-    pub fn delete_forward(&mut self) {
-        if self.config.read_only {
-            return;
-        }
+    /// Duplicate the lines touched by the cursors, moving the cursors to
+    /// the lower copy.
+    pub fn copy_lines_down(&mut self) {
+        self.copy_lines(false);
+    }
+
+    /// Insert a copy of each block of touched lines above it.
+    fn copy_lines(&mut self, up: bool) {
+        let groups = self.selected_line_groups();
+        let edits: Vec<_> = groups
+            .iter()
+            .filter_map(|lines| {
+                let span = self.line_span(lines.clone())?;
+                let text = self.buffer.slice(span.clone())?;
+                Some((span.start..span.start, format!("{text}\n")))
+            })
+            .collect();
+        self.apply_line_edits(edits, |line| {
+            let mut shift = 0;
+            for lines in &groups {
+                let count = lines.end() - lines.start() + 1;
+                if *lines.end() < line || (!up && lines.contains(&line)) {
+                    shift += count;
+                }
+            }
+            line + shift
+        });
+    }
+
+    /// Delete the lines touched by the cursors.
+    pub fn delete_lines(&mut self) {
+        let groups = self.selected_line_groups();
+        let line_count = self.line_count();
+        let edits: Vec<_> = groups
+            .iter()
+            .filter_map(|lines| {
+                let (a, b) = (*lines.start(), *lines.end());
+                let range = if b + 1 < line_count {
+                    self.buffer.line_start(a)?..self.buffer.line_start(b + 1)?
+                } else if a > 0 {
+                    self.buffer.line_range(a - 1)?.end..self.buffer.len()
+                } else {
+                    0..self.buffer.len()
+                };
+                Some((range, String::new()))
+            })
+            .collect();
+        self.apply_line_edits(edits, |line| {
+            let mut deleted = 0;
+            for lines in &groups {
+                if lines.contains(&line) {
+                    return lines.start() - deleted;
+                }
+                if *lines.end() < line {
+                    deleted += lines.end() - lines.start() + 1;
+                }
+            }
+            line - deleted
+        });
+    }
 
-        let cursor = self.cursors.primary();
-        if cursor.has_selection() {
-            self.delete_selection();
+    /// Join the lines of each selection, or the cursor's line and the next
+    /// one.
+    ///
+    /// The line breaks and the indentation after them become a single
+    /// space, or nothing next to an empty line or before a closing bracket.
+    pub fn join_lines(&mut self) {
+        let last_line = self.line_count() - 1;
+        let mut edits = Vec::new();
+        for lines in self.selected_line_groups() {
+            let (a, b) = (*lines.start(), *lines.end());
+            let b = if a == b { (b + 1).min(last_line) } else { b };
+            for line in a..b {
+                let (Some(start), Some(text), Some(next_start), Some(next)) = (
+                    self.buffer.line_start(line),
+                    self.buffer.line(line),
+                    self.buffer.line_start(line + 1),
+                    self.buffer.line(line + 1),
+                ) else {
+                    continue;
+                };
+                let kept = text.trim_end();
+                let rest = next.trim_start();
+                let separator =
+                    if kept.is_empty() || rest.is_empty() || rest.starts_with([')', ']', '}']) {
+                        ""
+                    } else {
+                        " "
+                    };
+                let range = start + kept.len()..next_start + (next.len() - rest.len());
+                edits.push((range, separator.to_string()));
+            }
+        }
+        drop_overlapping(&mut edits);
+        if edits.is_empty() {
             return;
         }
+        let changes = ChangeSet::from_edits(self.buffer.len(), edits);
+        self.apply(&Transaction::new(changes));
+    }
 
-        let position = cursor.head;
-        if let Some(offset) = self.position_to_offset(position) {
-            if offset >= self.content.len() {
-                return;
-            }
+    /// Start an empty line above each cursor's line, with the same
+    /// indentation, and move the cursor there.
+    pub fn insert_line_above(&mut self) {
+        self.edit_cursors_placing(|state, range| {
+            let start = state
+                .buffer
+                .line_start(state.buffer.line_of_offset(range.start))
+                .unwrap_or(0);
+            let line = state
+                .buffer
+                .line(state.buffer.line_of_offset(start))
+                .unwrap_or_default();
+            let indent = &line[..indent_len(&line)];
+            let len = indent.len();
+            (start..start, (format!("{indent}\n"), len..len))
+        });
+    }
+
+    /// Start an empty line below each cursor's line, with the same
+    /// indentation, and move the cursor there.
+    pub fn insert_line_below(&mut self) {
+        self.edit_cursors_placing(|state, range| {
+            let line = state.buffer.line_of_offset(range.end);
+            let end = state
+                .buffer
+                .line_range(line)
+                .map_or(range.end, |line| line.end);
+            let text = state.buffer.line(line).unwrap_or_default();
+            let text = format!("\n{}", &text[..indent_len(&text)]);
+            let len = text.len();
+            (end..end, (text, len..len))
+        });
+    }
 
-            self.history
-                .push(self.content.clone(), self.cursors.clone());
+    /// Sort the lines of each selection that spans several lines, or the
+    /// whole document if there is none.
+    pub fn sort_lines(&mut self, order: SortOrder) {
+        self.rewrite_lines(|lines| match order {
+            SortOrder::Ascending => lines.sort(),
+            SortOrder::Descending => lines.sort_by(|a, b| b.cmp(a)),
+            SortOrder::CaseInsensitive => lines.sort_by_cached_key(|line| line.to_lowercase()),
+            SortOrder::Natural => lines.sort_by(|a, b| natural_cmp(a, b)),
+        });
+    }
 
-            // Find the next character boundary
-            let next_offset = self.content[offset..]
-                .char_indices()
-                .nth(1)
-                .map_or(self.content.len(), |(i, _)| offset + i);
+    /// Remove repeated lines from each selection that spans several lines,
+    /// or from the whole document if there is none, keeping the first of
+    /// each.
+    pub fn remove_duplicate_lines(&mut self) {
+        self.rewrite_lines(|lines| {
+            let mut seen = std::collections::HashSet::new();
+            lines.retain(|line| seen.insert(line.clone()));
+        });
+    }
 
-            self.content = format!(
-                "{}{}",
-                &self.content[..offset],
-                &self.content[next_offset..]
-            );
+    /// Reverse the order of the lines of each selection that spans several
+    /// lines, or of the whole document if there is none.
+    pub fn reverse_lines(&mut self) {
+        self.rewrite_lines(|lines| lines.reverse());
+    }
 
-            self.version += 1;
-            self.is_modified = true;
+    /// Rewrite the lines of each multi-line selection, or of the whole
+    /// document, keeping the cursors where they are.
+    fn rewrite_lines(&mut self, mut rewrite: impl FnMut(&mut Vec<String>)) {
+        let mut groups: Vec<_> = self
+            .selected_line_groups()
+            .into_iter()
+            .filter(|lines| lines.start() != lines.end())
+            .collect();
+        if groups.is_empty() {
+            groups.push(0..=self.line_count() - 1);
         }
+        let edits: Vec<_> = groups
+            .into_iter()
+            .filter_map(|lines| {
+                let span = self.line_span(lines.clone())?;
+                let mut text: Vec<String> = lines
+                    .map(|line| self.buffer.line(line).unwrap_or_default().into_owned())
+                    .collect();
+                rewrite(&mut text);
+                Some((span, text.join("\n")))
+            })
+            .filter(|(span, text)| self.buffer.slice(span.clone()).as_ref() != Some(text))
+            .collect();
+        self.apply_line_edits(edits, |line| line);
     }
 
-    /// Delete the current selection.
-    fn delete_selection(&mut self) {
-        let cursor = self.cursors.primary();
-        if !cursor.has_selection() {
+    /// Replace the text selected at every cursor with `transform` applied
+    /// to it, keeping it selected.
+    ///
+    /// A cursor without a selection transforms the word it is in. Text that
+    /// cannot be transformed, such as invalid Base64, is left alone.
+    pub fn transform_selections(&mut self, transform: TextTransform) {
+        let target = |state: &Self, range: Range<usize>| {
+            if !range.is_empty() {
+                return range;
+            }
+            state
+                .offset_to_position(range.start)
+                .and_then(|position| state.word_range(position))
+                .and_then(|(start, end)| {
+                    Some(state.position_to_offset(start)?..state.position_to_offset(end)?)
+                })
+                .unwrap_or(range)
+        };
+        let changed = self
+            .cursors
+            .all()
+            .iter()
+            .filter_map(|cursor| self.selection_range(cursor))
+            .filter_map(|range| self.buffer.slice(target(self, range)))
+            .any(|text| transform.apply(&text).is_some_and(|new| new != text));
+        if !changed {
             return;
         }
+        self.edit_cursors_placing(|state, range| {
+            let word = target(state, range.clone());
+            let text = state.buffer.slice(word.clone()).unwrap_or_default();
+            let new = transform.apply(&text).unwrap_or(text);
+            let selection = if range.is_empty() {
+                let caret = range.start - word.start;
+                let caret = if new.is_char_boundary(caret) {
+                    caret
+                } else {
+                    new.len()
+                };
+                caret..caret
+            } else {
+                0..new.len()
+            };
+            (word, (new, selection))
+        });
+    }
+
+    /// Swap the characters on either side of every cursor, moving the
+    /// cursor past them.
+    ///
+    /// Characters are grapheme clusters, so combining marks and emoji
+    /// sequences move as a whole. At the end of a line, the two characters
+    /// before the cursor are swapped instead.
+    pub fn transpose_chars(&mut self) {
+        self.edit_cursors_placing(|state, range| {
+            let buffer = &state.buffer;
+            let offset = range.end;
+            let line = buffer
+                .line_range(buffer.line_of_offset(offset))
+                .unwrap_or(offset..offset);
+            let mid = if offset == line.end {
+                buffer.prev_grapheme_boundary(offset)
+            } else {
+                offset
+            };
+            let start = buffer.prev_grapheme_boundary(mid);
+            let end = buffer.next_grapheme_boundary(mid);
+            match (buffer.slice(start..mid), buffer.slice(mid..end)) {
+                (Some(first), Some(second))
+                    if start >= line.start && start < mid && mid < end && end <= line.end =>
+                {
+                    let text = format!("{second}{first}");
+                    let len = text.len();
+                    (start..end, (text, len..len))
+                }
+                _ => (offset..offset, (String::new(), 0..0)),
+            }
+        });
+    }
+
+    /// Remove whitespace from the end of the lines of each multi-line
+    /// selection, or of the whole document if there is none.
+    pub fn trim_trailing_whitespace(&mut self) {
+        self.rewrite_lines(|lines| {
+            for line in lines {
+                line.truncate(line.trim_end().len());
+            }
+        });
+    }
 
-        let start_pos = cursor.selection_start();
-        let end_pos = cursor.selection_end();
+    /// Convert the indentation of the lines of each multi-line selection,
+    /// or of the whole document, to spaces.
+    ///
+    /// Tabs expand to the next multiple of [`EditorConfig::tab_size`].
+    pub fn indentation_to_spaces(&mut self) {
+        let tab_size = self.config.tab_size.max(1);
+        self.rewrite_lines(|lines| {
+            for line in lines {
+                let indent = indent_len(line);
+                let width = indent_width(&line[..indent], tab_size);
+                line.replace_range(..indent, &" ".repeat(width));
+            }
+        });
+    }
 
-        if let (Some(start), Some(end)) = (
-            self.position_to_offset(start_pos),
-            self.position_to_offset(end_pos),
-        ) {
-            self.history
-                .push(self.content.clone(), self.cursors.clone());
+    /// Convert the indentation of the lines of each multi-line selection,
+    /// or of the whole document, to tabs.
+    ///
+    /// Each [`EditorConfig::tab_size`] columns become a tab; any columns
+    /// left over stay spaces.
+    pub fn indentation_to_tabs(&mut self) {
+        let tab_size = self.config.tab_size.max(1);
+        self.rewrite_lines(|lines| {
+            for line in lines {
+                let indent = indent_len(line);
+                let width = indent_width(&line[..indent], tab_size);
+                let tabs = "\t".repeat(width / tab_size) + &" ".repeat(width % tab_size);
+                line.replace_range(..indent, &tabs);
+            }
+        });
+    }
 
-            self.content = format!("{}{}", &self.content[..start], &self.content[end..]);
-            self.set_cursor(start_pos);
+    /// Hard-wrap the paragraph at each cursor, or the lines of each
+    /// multi-line selection, to [`EditorConfig::max_line_width`] columns
+    /// (80 when it is 0).
+    ///
+    /// Indentation and comment or quote prefixes such as `//`, `#` and `>`
+    /// are kept on every line (see [`reflow`](super::reflow::reflow)).
+    /// Cursors move to the end of their reflowed text.
+    pub fn reflow_paragraph(&mut self) {
+        let width = match self.config.max_line_width {
+            0 => 80,
+            width => width,
+        };
+        let mut markers = REFLOW_MARKERS.to_vec();
+        markers.extend(self.language_kind().line_comment());
 
-            self.version += 1;
-            self.is_modified = true;
+        let line = |line: usize| self.buffer.line(line).unwrap_or_default();
+        let mut edits: Vec<(Range<usize>, String)> = self
+            .selected_line_groups()
+            .into_iter()
+            .filter_map(|group| {
+                let (mut first, mut last) = (*group.start(), *group.end());
+                if first == last {
+                    if split_prefix(&line(first), &markers).1.trim().is_empty() {
+                        return None;
+                    }
+                    // Walk out from the cursor's line to the paragraph's ends
+                    while first > 0 && same_paragraph(&line(first - 1), &line(first), &markers) {
+                        first -= 1;
+                    }
+                    while last + 1 < self.line_count()
+                        && same_paragraph(&line(last), &line(last + 1), &markers)
+                    {
+                        last += 1;
+                    }
+                }
+                let lines: Vec<_> = (first..=last).map(line).collect();
+                let old: Vec<&str> = lines.iter().map(AsRef::as_ref).collect();
+                let new = reflow(&old, width, self.config.tab_size, &markers).join("\n");
+                Some((self.line_span(first..=last)?, new))
+            })
+            .collect();
+        drop_overlapping(&mut edits);
+        if edits.is_empty() {
+            return;
         }
+
+        let changes = ChangeSet::from_edits(self.buffer.len(), edits.iter().cloned());
+        let mut after = self.buffer.clone();
+        changes.apply(&mut after);
+        let mut cursors = self.cursors.clone();
+        cursors.map_positions(|pos| {
+            let offset = self.position_to_offset(pos).unwrap_or(0);
+            let offset = edits
+                .iter()
+                .find(|(range, _)| range.contains(&offset) || range.end == offset)
+                .map_or_else(
+                    || changes.map_position(offset, Assoc::After),
+                    |(range, text)| changes.map_position(range.start, Assoc::Before) + text.len(),
+                );
+            after
+                .offset_to_line_col(offset)
+                .map_or(pos, |(line, column)| CursorPosition::new(line, column))
+        });
+        self.apply(&Transaction::new(changes).with_selection(cursors));
     }
 
-    /// Undo the last change.
-    pub fn undo(&mut self) -> bool {
-        if let Some(entry) = self.history.undo(&self.content, &self.cursors) {
-            self.content = entry.content;
-            self.cursors = entry.cursors;
-            self.version += 1;
-            true
-        } else {
-            false
-        }
+    /// Get the byte range of a run of lines, without the final line break.
+    fn line_span(&self, lines: RangeInclusive<usize>) -> Option<Range<usize>> {
+        Some(self.buffer.line_start(*lines.start())?..self.buffer.line_range(*lines.end())?.end)
     }
 
-    /// Redo the last undone change.
-    pub fn redo(&mut self) -> bool {
-        if let Some(entry) = self.history.redo(&self.content, &self.cursors) {
-            self.content = entry.content;
-            self.cursors = entry.cursors;
-            self.version += 1;
-            true
-        } else {
-            false
+    /// Apply edits to whole lines in one step, moving each cursor to the
+    /// line `place` gives for its old line.
+    ///
+    /// Columns are kept, clamped to the new lines.
+    fn apply_line_edits(
+        &mut self,
+        mut edits: Vec<(Range<usize>, String)>,
+        place: impl Fn(usize) -> usize,
+    ) {
+        drop_overlapping(&mut edits);
+        if edits.is_empty() {
+            return;
         }
+        let changes = ChangeSet::from_edits(self.buffer.len(), edits);
+        let mut after = self.buffer.clone();
+        changes.apply(&mut after);
+        let mut cursors = self.cursors.clone();
+        cursors.map_positions(|pos| {
+            clamp_to(&after, CursorPosition::new(place(pos.line), pos.column))
+        });
+        self.apply(&Transaction::new(changes).with_selection(cursors));
     }
 
-    /// Check if undo is available.
+    /// Get the document's [`Language`] from [`Self::language`].
     #[must_use]
-    pub fn can_undo(&self) -> bool {
-        self.history.can_undo()
+    pub fn language_kind(&self) -> Language {
+        self.language
+            .as_deref()
+            .map(Language::from_name)
+            .unwrap_or_default()
     }
 
-    /// Check if redo is available.
-    #[must_use]
-    pub fn can_redo(&self) -> bool {
-        self.history.can_redo()
+    /// Start a new line at every cursor.
+    ///
+    /// With [`EditorConfig::auto_indent`], the new line keeps the current
+    /// line's indentation, one level deeper after a line that opens a block
+    /// (see [`Language::indents_after`]). Between a pair of brackets, as in
+    /// `{|}`, the closing bracket moves down to a line of its own.
+    pub fn insert_newline(&mut self) {
+        if !self.config.auto_indent {
+            self.insert("\n");
+            return;
+        }
+        let language = self.language_kind();
+        let unit = self.config.indent_unit();
+        self.edit_cursors_placing(|state, range| {
+            let buffer = &state.buffer;
+            let line_start = buffer
+                .line_start(buffer.line_of_offset(range.start))
+                .unwrap_or(range.start);
+            let line_end = buffer
+                .line_range(buffer.line_of_offset(range.end))
+                .map_or(range.end, |line| line.end);
+            let before = buffer.slice(line_start..range.start).unwrap_or_default();
+            let after = buffer.slice(range.end..line_end).unwrap_or_default();
+
+            let indent: String = before
+                .chars()
+                .take_while(|c| *c == ' ' || *c == '\t')
+                .collect();
+            let mut text = format!("\n{indent}");
+            let mut caret = text.len();
+            if language.indents_after(&before) {
+                text.push_str(&unit);
+                caret = text.len();
+                let closer = match before.trim_end().chars().last() {
+                    Some('(') => Some(')'),
+                    Some('[') => Some(']'),
+                    Some('{') => Some('}'),
+                    _ => None,
+                };
+                if closer.is_some_and(|closer| after.trim_start().starts_with(closer)) {
+                    text.push('\n');
+                    text.push_str(&indent);
+                }
+            }
+            (range, (text, caret..caret))
+        });
     }
 
-    /// Mark the content as saved (clears modified flag).
-    pub fn mark_saved(&mut self) {
-        self.is_modified = false;
+    /// Type `ch` at every cursor with automatic closing pairs.
+    ///
+    /// Uses the pairs of [`Self::language_kind`]: an opening character
+    /// also inserts its closing one (see [`Language::closes_pair`]) or wraps
+    /// the selection, and a closing character types over the same character
+    /// after the cursor. Returns `false`, changing nothing, if
+    /// [`EditorConfig::auto_close_brackets`] is off or `ch` would be typed
+    /// normally at every cursor.
+    pub fn auto_close(&mut self, ch: char) -> bool {
+        if !self.config.auto_close_brackets {
+            return false;
+        }
+        let special = self
+            .cursors
+            .all()
+            .iter()
+            .filter_map(|cursor| self.selection_range(cursor))
+            .any(|range| self.pair_edit(ch, range).is_some());
+        if !special {
+            return false;
+        }
+        self.edit_cursors_placing(|state, range| {
+            state.pair_edit(ch, range.clone()).unwrap_or_else(|| {
+                let len = ch.len_utf8();
+                (range, (ch.to_string(), len..len))
+            })
+        })
     }
 
-    /// Convert a cursor position to a byte offset.
-    #[must_use]
-    pub fn position_to_offset(&self, position: CursorPosition) -> Option<usize> {
-        let mut current_line = 0;
-        let mut offset = 0;
-
-        for (i, ch) in self.content.char_indices() {
-            if current_line == position.line {
-                let line_start = i;
-                let mut col = 0;
-                for (j, c) in self.content[line_start..].char_indices() {
-                    if col == position.column {
-                        return Some(line_start + j);
-                    }
-                    if c == '\n' {
-                        break;
-                    }
-                    col += 1;
-                }
-                // Position at end of line
-                if col == position.column {
-                    return Some(
-                        line_start
-                            + self.content[line_start..]
-                                .find('\n')
-                                .unwrap_or(self.content.len() - line_start),
-                    );
-                }
-                return None;
+    /// Get the edit for typing `ch` over `range` if auto-closing changes it.
+    fn pair_edit(&self, ch: char, range: Range<usize>) -> Option<PlacedEdit> {
+        let language = self.language_kind();
+        let pairs = language.auto_pairs();
+        let close = pairs
+            .iter()
+            .find(|(open, _)| *open == ch)
+            .map(|pair| pair.1);
+        let len = ch.len_utf8();
+
+        if !range.is_empty() {
+            let close = close?;
+            let selected = self.buffer.slice(range.clone())?;
+            let inner = len..len + selected.len();
+            return Some((range, (format!("{ch}{selected}{close}"), inner)));
+        }
+        let after = self.buffer.char_at(range.end);
+        if after == Some(ch) && pairs.iter().any(|(_, close)| *close == ch) {
+            return Some((range.start..range.end + len, (ch.to_string(), len..len)));
+        }
+        let line_start = self
+            .buffer
+            .line_start(self.buffer.line_of_offset(range.start))?;
+        let before = self.buffer.slice(line_start..range.start)?;
+        let close = close.filter(|_| language.closes_pair(ch, &before, after))?;
+        Some((range, (format!("{ch}{close}"), len..len)))
+    }
+
+    /// Delete the empty pair around every cursor, as in `(|)`.
+    ///
+    /// Returns `false`, changing nothing, if
+    /// [`EditorConfig::auto_close_brackets`] is off or any cursor is not
+    /// between an empty pair.
+    pub fn delete_pair(&mut self) -> bool {
+        if !self.config.auto_close_brackets {
+            return false;
+        }
+        let pairs = self.language_kind().auto_pairs();
+        let pair_around = |state: &Self, range: Range<usize>| {
+            let open = state
+                .buffer
+                .char_at(state.buffer.prev_char_boundary(range.start))?;
+            let close = state.buffer.char_at(range.end)?;
+            (range.is_empty() && range.start > 0 && pairs.contains(&(open, close))).then(|| {
+                (
+                    range.start - open.len_utf8()..range.end + close.len_utf8(),
+                    String::new(),
+                )
+            })
+        };
+        let all_pairs = self
+            .cursors
+            .all()
+            .iter()
+            .filter_map(|cursor| self.selection_range(cursor))
+            .all(|range| pair_around(self, range).is_some());
+        all_pairs && self.edit_cursors(pair_around)
+    }
+
+    /// Delete the grapheme cluster before each cursor (backspace).
+    ///
+    /// Deletes the selections instead if there are any.
+    pub fn delete_backward(&mut self) {
+        if let Some(block) = self.active_block() {
+            let columns = block.columns();
+            if columns.is_empty() && columns.start > 0 {
+                self.edit_block(block, columns.start - 1..columns.start, &[""]);
+            } else if !columns.is_empty() {
+                self.edit_block(block, columns, &[""]);
             }
-            if ch == '\n' {
-                current_line += 1;
+            return;
+        }
+        if self.delete_pair() {
+            return;
+        }
+        self.edit_cursors(|state, range| {
+            if !range.is_empty() {
+                Some((range, String::new()))
+            } else if range.start > 0 {
+                let prev = state.buffer.prev_grapheme_boundary(range.start);
+                Some((prev..range.start, String::new()))
+            } else {
+                None
             }
-            offset = i + ch.len_utf8();
+        });
+    }
+
+    /// Delete the grapheme cluster after each cursor (delete).
+    ///
+    /// Deletes the selections instead if there are any.
+    pub fn delete_forward(&mut self) {
+        if let Some(block) = self.active_block() {
+            let columns = block.columns();
+            let end = columns.end.max(columns.start + 1);
+            self.edit_block(block, columns.start..end, &[""]);
+            return;
         }
+        self.edit_cursors(|state, range| {
+            if !range.is_empty() {
+                Some((range, String::new()))
+            } else if range.end < state.buffer.len() {
+                let next = state.buffer.next_grapheme_boundary(range.end);
+                Some((range.end..next, String::new()))
+            } else {
+                None
+            }
+        });
+    }
 
-        // Handle position at end of last line
-        if current_line == position.line && position.column == 0 {
-            return Some(offset);
+    /// Get the text to copy and whether it is made of whole lines.
+    ///
+    /// The selections are joined with line breaks. If nothing is selected,
+    /// the lines under the cursors are copied instead, each with its line
+    /// break.
+    #[must_use]
+    pub fn copy_text(&self) -> (String, bool) {
+        let selections: Vec<String> = self
+            .cursors
+            .all()
+            .iter()
+            .filter_map(|cursor| self.selection_range(cursor))
+            .filter(|range| !range.is_empty())
+            .filter_map(|range| self.buffer.slice(range))
+            .collect();
+        if !selections.is_empty() {
+            return (selections.join("\n"), false);
         }
 
-        None
+        let mut text = String::new();
+        for line in self.selected_lines() {
+            text.push_str(&self.buffer.line(line).unwrap_or_default());
+            text.push('\n');
+        }
+        (text, true)
     }
 
-    /// Convert a byte offset to a cursor position.
-    #[must_use]
-    pub fn offset_to_position(&self, offset: usize) -> Option<CursorPosition> {
-        if offset > self.content.len() {
-            return None;
+    /// Copy and delete the selections, or the lines under the cursors if
+    /// nothing is selected.
+    ///
+    /// Returns what [`Self::copy_text`] returned before the deletion.
+    pub fn cut(&mut self) -> (String, bool) {
+        let (text, linewise) = self.copy_text();
+        if linewise {
+            self.delete_lines();
+        } else {
+            self.edit_cursors(|_, range| (!range.is_empty()).then(|| (range, String::new())));
         }
+        (text, linewise)
+    }
 
-        let mut line = 0;
-        let mut col = 0;
+    /// Paste text at every cursor as its own undo step.
+    ///
+    /// Whole lines (`linewise`) are inserted above the line of each cursor
+    /// that has no selection. If the text has one line per cursor, each
+    /// cursor gets its own line.
+    pub fn paste(&mut self, text: &str, linewise: bool) -> bool {
+        let text = LineEnding::normalize(text).into_owned();
+        if text.is_empty() {
+            return false;
+        }
+        let rows: Vec<&str> = text
+            .strip_suffix('\n')
+            .unwrap_or(&text)
+            .split('\n')
+            .collect();
+        let cursor_count = self.cursors.all().len();
+        let texts: Vec<String> = if cursor_count > 1 && rows.len() == cursor_count {
+            rows.iter()
+                .map(|row| {
+                    if linewise {
+                        format!("{row}\n")
+                    } else {
+                        (*row).to_string()
+                    }
+                })
+                .collect()
+        } else if linewise && !text.ends_with('\n') {
+            vec![format!("{text}\n"); cursor_count]
+        } else {
+            vec![text.clone(); cursor_count]
+        };
 
-        for (i, ch) in self.content.char_indices() {
-            if i >= offset {
-                return Some(CursorPosition::new(line, col));
-            }
-            if ch == '\n' {
-                line += 1;
-                col = 0;
+        let mut texts = texts.into_iter();
+        self.history.checkpoint();
+        let pasted = self.edit_cursors(|state, range| {
+            let text = texts.next()?;
+            if linewise && range.is_empty() {
+                let start = state
+                    .buffer
+                    .line_start(state.buffer.line_of_offset(range.start))?;
+                Some((start..start, text))
             } else {
-                col += 1;
+                Some((range, text))
             }
-        }
+        });
+        self.history.checkpoint();
+        pasted
+    }
 
-        // Position at end of content
-        Some(CursorPosition::new(line, col))
+    /// Move every cursor, extending the selections if `extend` is set.
+    pub fn move_cursors(&mut self, movement: Movement, extend: bool) {
+        let buffer = &self.buffer;
+        self.cursors
+            .map_cursors(|cursor| movement.apply(buffer, cursor, extend));
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+    /// Select the word at a position with a single cursor.
+    ///
+    /// Switches to word selection mode. Returns `false` and just moves the
+    /// cursor if there is no word at the position.
+    pub fn select_word_at(&mut self, position: CursorPosition) -> bool {
+        let position = self.clamp_position(position);
+        let word = self.word_range(position);
+        let (start, end) = word.unwrap_or((position, position));
 
-    #[test]
-    fn test_editor_state_new() {
-        let state = EditorState::new("Hello, World!");
-        assert_eq!(state.content(), "Hello, World!");
-        assert_eq!(state.line_count(), 1);
-        assert!(!state.is_modified);
+        self.cursors = CursorSet::new(Cursor::with_selection(end, start));
+        self.selection_mode = SelectionMode::Word;
+        self.selection_origin = Some(Selection::new(start, end));
+        word.is_some()
     }
 
-    #[test]
-    fn test_insert() {
+    /// Select the whole document with a single cursor.
+    pub fn select_all(&mut self) {
+        let last = self.line_count() - 1;
+        let end = CursorPosition::new(last, self.buffer.line_len_chars(last).unwrap_or(0));
+        self.cursors = CursorSet::new(Cursor::with_selection(end, CursorPosition::zero()));
+    }
+
+    /// Select a whole line, including its line break, with a single cursor.
+    ///
+    /// Switches to line selection mode. Returns `false` if the line does
+    /// not exist.
+    pub fn select_line(&mut self, line: usize) -> bool {
+        if line >= self.line_count() {
+            return false;
+        }
+        let (start, end) = self.line_bounds(line);
+
+        self.cursors = CursorSet::new(Cursor::with_selection(end, start));
+        self.selection_mode = SelectionMode::Line;
+        self.selection_origin = Some(Selection::new(start, end));
+        true
+    }
+
+    /// Extend the primary selection to a position.
+    ///
+    /// In word and line mode the selection grows by whole words or lines
+    /// and always keeps the word or line it started from. In block mode it
+    /// becomes a block from where the selection started.
+    pub fn extend_selection_to(&mut self, position: CursorPosition) {
+        let position = self.clamp_position(position);
+        if let (SelectionMode::Block, Some(origin)) = (self.selection_mode, self.selection_origin) {
+            self.select_block(origin.start, position);
+            return;
+        }
+        let unit = match (self.selection_mode, self.selection_origin) {
+            (SelectionMode::Word, Some(origin)) => Some((
+                origin,
+                self.word_range(position).unwrap_or((position, position)),
+            )),
+            (SelectionMode::Line, Some(origin)) => Some((origin, self.line_bounds(position.line))),
+            _ => None,
+        };
+
+        let cursor = self.cursors.primary_mut();
+        match unit {
+            Some((origin, (start, _))) if position < origin.start => {
+                cursor.anchor = origin.end;
+                cursor.head = start;
+            }
+            Some((origin, (_, end))) => {
+                cursor.anchor = origin.start;
+                cursor.head = end.max(origin.end);
+            }
+            None => cursor.head = position,
+        }
+    }
+
+    /// Get the start and end of the word at a position.
+    fn word_range(&self, position: CursorPosition) -> Option<(CursorPosition, CursorPosition)> {
+        let line = self.buffer.line(position.line)?;
+        let (start, end) = word_at_position(&line, 0, position.column)?;
+        Some((
+            CursorPosition::new(position.line, start),
+            CursorPosition::new(position.line, end),
+        ))
+    }
+
+    /// Get the start of a line and the start of the next one (or the end of
+    /// the document on the last line).
+    fn line_bounds(&self, line: usize) -> (CursorPosition, CursorPosition) {
+        let end = if line + 1 < self.line_count() {
+            CursorPosition::new(line + 1, 0)
+        } else {
+            CursorPosition::new(line, self.buffer.line_len_chars(line).unwrap_or(0))
+        };
+        (CursorPosition::new(line, 0), end)
+    }
+
+    /// Select a rectangular block with one cursor per line.
+    ///
+    /// Columns may lie past the end of a line; the cursors on short lines
+    /// stop at the line end, and typing pads those lines with spaces.
+    pub fn select_block(&mut self, anchor: CursorPosition, head: CursorPosition) {
+        let last = self.line_count() - 1;
+        let block = BlockSelection::new(
+            CursorPosition::new(anchor.line.min(last), anchor.column),
+            CursorPosition::new(head.line.min(last), head.column),
+        );
+        self.cursors = self.block_cursors(&block);
+        self.selection_mode = SelectionMode::Block;
+        self.block_selection = Some(block);
+    }
+
+    /// Grow or shrink the block selection by one line or column.
+    ///
+    /// Starts a block from the primary cursor if there is none. Only
+    /// `CharLeft`, `CharRight`, `LineUp` and `LineDown` apply; other
+    /// movements are ignored.
+    pub fn extend_block(&mut self, movement: Movement) {
+        let block = self.active_block().unwrap_or_else(|| {
+            let cursor = self.cursors.primary();
+            BlockSelection::new(cursor.anchor, cursor.head)
+        });
+        let mut head = block.head;
+        match movement {
+            Movement::CharLeft => head.column = head.column.saturating_sub(1),
+            Movement::CharRight => head.column += 1,
+            Movement::LineUp => head.line = head.line.saturating_sub(1),
+            Movement::LineDown => head.line += 1,
+            _ => return,
+        }
+        self.select_block(block.anchor, head);
+    }
+
+    /// Get the block selection, if the cursors have not moved away from it.
+    #[must_use]
+    pub fn active_block(&self) -> Option<BlockSelection> {
+        self.block_selection
+            .filter(|block| self.block_cursors(block) == self.cursors)
+    }
+
+    /// Get the text of the active block selection, one line per block row.
+    ///
+    /// Returns `None` for a block without columns, such as a column of
+    /// cursors.
+    #[must_use]
+    pub fn block_text(&self) -> Option<String> {
+        let block = self.active_block()?;
+        let columns = block.columns();
+        if columns.is_empty() {
+            return None;
+        }
+        let rows: Vec<String> = block
+            .lines()
+            .map(|line| {
+                let text = self.buffer.line(line).unwrap_or_default();
+                text.chars()
+                    .skip(columns.start)
+                    .take(columns.len())
+                    .collect()
+            })
+            .collect();
+        Some(rows.join("\n"))
+    }
+
+    /// Delete the active block selection and return its text.
+    ///
+    /// Returns `None`, leaving the text alone, if there is no block or it
+    /// has no columns.
+    pub fn cut_block(&mut self) -> Option<String> {
+        let text = self.block_text()?;
+        let block = self.active_block()?;
+        self.edit_block(block, block.columns(), &[""])
+            .then_some(text)
+    }
+
+    /// Paste text as a rectangular block.
+    ///
+    /// With an active block of the same height, each line of `text`
+    /// replaces one row of the block. Otherwise the lines are inserted
+    /// below each other starting at the primary cursor's column, adding
+    /// lines to the end of the document if needed.
+    pub fn paste_block(&mut self, text: &str) -> bool {
+        let rows: Vec<&str> = text
+            .split('\n')
+            .map(|row| row.trim_end_matches('\r'))
+            .collect();
+        if let Some(block) = self
+            .active_block()
+            .filter(|block| block.lines().count() == rows.len())
+        {
+            return self.edit_block(block, block.columns(), &rows);
+        }
+
+        let start = self.cursors.primary().selection_start();
+        let end = CursorPosition::new(start.line + rows.len() - 1, start.column);
+        self.edit_block(
+            BlockSelection::new(start, end),
+            start.column..start.column,
+            &rows,
+        )
+    }
+
+    /// Get one cursor per line of a block, clamped to the line ends.
+    fn block_cursors(&self, block: &BlockSelection) -> CursorSet {
+        let mut cursors = block.lines().map(|line| {
+            let len = self.buffer.line_len_chars(line).unwrap_or(0);
+            Cursor::with_selection(
+                CursorPosition::new(line, block.head.column.min(len)),
+                CursorPosition::new(line, block.anchor.column.min(len)),
+            )
+        });
+        let mut set = CursorSet::new(cursors.next().unwrap_or_default());
+        cursors.for_each(|cursor| set.add(cursor));
+        set
+    }
+
+    /// Replace `columns` on every row of a block in one undoable step.
+    ///
+    /// Row `i` gets `texts[i]`, or `texts[0]` if there are fewer texts than
+    /// rows. Rows shorter than the start column are padded with spaces
+    /// when something is inserted, and rows past the end of the document
+    /// are appended. If every row got text of the same width, a zero-width
+    /// block is left after it.
+    fn edit_block(&mut self, block: BlockSelection, columns: Range<usize>, texts: &[&str]) -> bool {
+        let line_count = self.line_count();
+        let mut edits = Vec::new();
+        let mut appended = String::new();
+
+        for (i, line) in block.lines().enumerate() {
+            let text = texts
+                .get(i)
+                .or_else(|| texts.first())
+                .copied()
+                .unwrap_or_default();
+            if line >= line_count {
+                appended.push('\n');
+                appended.push_str(&" ".repeat(columns.start));
+                appended.push_str(text);
+                continue;
+            }
+
+            let len = self.buffer.line_len_chars(line).unwrap_or(0);
+            let (Some(start), Some(end)) = (
+                self.buffer.line_col_to_offset(line, columns.start.min(len)),
+                self.buffer.line_col_to_offset(line, columns.end.min(len)),
+            ) else {
+                continue;
+            };
+            if start == end && text.is_empty() {
+                continue;
+            }
+            let padding = if text.is_empty() {
+                0
+            } else {
+                columns.start.saturating_sub(len)
+            };
+            edits.push((start..end, format!("{}{text}", " ".repeat(padding))));
+        }
+        if !appended.is_empty() {
+            edits.push((self.buffer.len()..self.buffer.len(), appended));
+        }
+        if edits.is_empty() {
+            return false;
+        }
+
+        let changes = ChangeSet::from_edits(self.buffer.len(), edits);
+        if !self.apply(&Transaction::new(changes)) {
+            return false;
+        }
+
+        let widths: Vec<usize> = block
+            .lines()
+            .enumerate()
+            .map(|(i, _)| {
+                texts
+                    .get(i)
+                    .or_else(|| texts.first())
+                    .map_or(0, |text| text.chars().count())
+            })
+            .collect();
+        if widths.windows(2).all(|pair| pair[0] == pair[1]) {
+            let block = block.collapsed_at(columns.start + widths.first().copied().unwrap_or(0));
+            self.select_block(block.anchor, block.head);
+        } else {
+            self.block_selection = None;
+        }
+        true
+    }
+
+    /// Get the bracket next to each cursor and its partner.
+    ///
+    /// Empty if [`EditorConfig::match_brackets`] is off.
+    #[must_use]
+    pub fn matching_brackets(&self) -> Vec<BracketMatch> {
+        if !self.config.match_brackets {
+            return Vec::new();
+        }
+        let language = self.language_kind();
+        self.cursors
+            .all()
+            .iter()
+            .filter_map(|cursor| self.position_to_offset(cursor.head))
+            .filter_map(|offset| brackets::match_at(&self.buffer, language, offset))
+            .collect()
+    }
+
+    /// Move every cursor next to a bracket to its partner.
+    ///
+    /// A cursor before a bracket moves before the partner, and one after a
+    /// bracket moves after it, so jumping twice returns to the start.
+    pub fn jump_to_bracket(&mut self) {
+        let language = self.language_kind();
+        let buffer = &self.buffer;
+        self.cursors.map_cursors(|cursor| {
+            let Some(offset) = buffer.line_col_to_offset(cursor.head.line, cursor.head.column)
+            else {
+                return;
+            };
+            let Some(found) = brackets::match_at(buffer, language, offset) else {
+                return;
+            };
+            let target = if found.bracket == offset {
+                found.partner
+            } else {
+                buffer.next_char_boundary(found.partner)
+            };
+            if let Some((line, column)) = buffer.offset_to_line_col(target) {
+                cursor.move_to(CursorPosition::new(line, column), false);
+            }
+        });
+    }
+
+    /// Add a cursor at a position, clamped to the document.
+    pub fn add_cursor(&mut self, position: CursorPosition) {
+        let position = self.clamp_position(position);
+        self.cursors.add(Cursor::new(position));
+    }
+
+    /// Add a cursor on the line above the topmost cursor.
+    ///
+    /// The new cursor keeps the column of the one it was added from, or
+    /// the end of the line if that is shorter. Returns `false` if the
+    /// topmost cursor is already on the first line.
+    pub fn add_cursor_above(&mut self) -> bool {
+        let Some(&from) = self.cursors.all().iter().min_by_key(|c| c.head) else {
+            return false;
+        };
+        if from.head.line == 0 {
+            return false;
+        }
+        self.add_cursor_on_line(&from, from.head.line - 1);
+        true
+    }
+
+    /// Add a cursor on the line below the bottommost cursor.
+    ///
+    /// Returns `false` if the bottommost cursor is already on the last line.
+    pub fn add_cursor_below(&mut self) -> bool {
+        let Some(&from) = self.cursors.all().iter().max_by_key(|c| c.head) else {
+            return false;
+        };
+        if from.head.line + 1 >= self.line_count() {
+            return false;
+        }
+        self.add_cursor_on_line(&from, from.head.line + 1);
+        true
+    }
+
+    /// Add a cursor on `line` at the preferred column of `from`.
+    fn add_cursor_on_line(&mut self, from: &Cursor, line: usize) {
+        let column = from.preferred_column.unwrap_or(from.head.column);
+        let mut cursor = Cursor::new(self.clamp_position(CursorPosition::new(line, column)));
+        cursor.set_preferred_column(column);
+        self.cursors.add(cursor);
+    }
+
+    /// Remove every cursor except the primary one.
+    pub fn clear_secondary_cursors(&mut self) {
+        self.cursors.collapse_to_primary();
+    }
+
+    /// Clamp a position to the nearest position that exists in the document.
+    #[must_use]
+    pub fn clamp_position(&self, position: CursorPosition) -> CursorPosition {
+        clamp_to(&self.buffer, position)
+    }
+
+    /// Undo the last change.
+    pub fn undo(&mut self) -> bool {
+        if let Some(entry) = self.history.undo(&self.cursors) {
+            self.restore(entry);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Redo the last undone change.
+    pub fn redo(&mut self) -> bool {
+        if let Some(entry) = self.history.redo(&self.cursors) {
+            self.restore(entry);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Go back or forward to a state from the history by reverting an
+    /// entry.
+    fn restore(&mut self, entry: HistoryEntry) {
+        self.record_change(entry.inverse.clone(), self.cursors.clone());
+        let before = self.buffer.clone();
+        entry.inverse.apply(&mut self.buffer);
+        self.map_annotations(&entry.inverse, &before);
+        self.cursors = entry.cursors;
+    }
+
+    /// Check if undo is available.
+    #[must_use]
+    pub fn can_undo(&self) -> bool {
+        self.history.can_undo()
+    }
+
+    /// Check if redo is available.
+    #[must_use]
+    pub fn can_redo(&self) -> bool {
+        self.history.can_redo()
+    }
+
+    /// Mark the content as saved (clears modified flag).
+    pub fn mark_saved(&mut self) {
+        self.is_modified = false;
+    }
+
+    /// Convert a cursor position to a byte offset.
+    #[must_use]
+    pub fn position_to_offset(&self, position: CursorPosition) -> Option<usize> {
+        self.buffer
+            .line_col_to_offset(position.line, position.column)
+    }
+
+    /// Convert a byte offset to a cursor position.
+    ///
+    /// The returned column is in chars; see [`super::coords`] for the
+    /// other text units.
+    #[must_use]
+    pub fn offset_to_position(&self, offset: usize) -> Option<CursorPosition> {
+        self.buffer
+            .offset_to_line_col(offset)
+            .map(|(line, column)| CursorPosition::new(line, column))
+    }
+}
+
+/// How [`EditorState::sort_lines`] orders lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortOrder {
+    /// By character code, A to Z
+    Ascending,
+    /// By character code, Z to A
+    Descending,
+    /// Ignoring case
+    CaseInsensitive,
+    /// With runs of digits compared as numbers, so `file2` comes before
+    /// `file10`
+    Natural,
+}
+
+/// Store a buffer's line breaks as `\n`, returning the line ending it
+/// used if it has any line breaks.
+fn normalize_buffer(buffer: TextBuffer) -> (TextBuffer, Option<LineEnding>) {
+    let text = buffer.to_string();
+    let line_ending = LineEnding::detect(&text);
+    match LineEnding::normalize(&text) {
+        Cow::Borrowed(_) => (buffer, line_ending),
+        Cow::Owned(normalized) => (TextBuffer::from(normalized), line_ending),
+    }
+}
+
+/// Clamp a position to the nearest position that exists in `buffer`.
+fn clamp_to(buffer: &TextBuffer, position: CursorPosition) -> CursorPosition {
+    let line = position.line.min(buffer.line_count().saturating_sub(1));
+    let column = position
+        .column
+        .min(buffer.line_len_chars(line).unwrap_or(0));
+    CursorPosition::new(line, column)
+}
+
+/// Compare two strings with runs of digits compared by their value.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+    loop {
+        let ordering = match (a.peek(), b.peek()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let x: String = std::iter::from_fn(|| a.next_if(char::is_ascii_digit)).collect();
+                let y: String = std::iter::from_fn(|| b.next_if(char::is_ascii_digit)).collect();
+                let (x, y) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                x.len().cmp(&y.len()).then_with(|| x.cmp(y))
+            }
+            (Some(x), Some(y)) => {
+                let ordering = x.cmp(y);
+                a.next();
+                b.next();
+                ordering
+            }
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+/// Get the length in bytes of the first `units` UTF-16 code units of
+/// `text`, or `None` if that is not a whole number of characters.
+fn utf16_prefix_len(text: &str, units: usize) -> Option<usize> {
+    let mut counted = 0;
+    for (i, c) in text.char_indices() {
+        if counted >= units {
+            return (counted == units).then_some(i);
+        }
+        counted += c.len_utf16();
+    }
+    (counted == units).then_some(text.len())
+}
+
+/// Get the length in bytes of a line's leading whitespace.
+fn indent_len(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+/// Get the width in columns of leading whitespace, with tabs expanding to
+/// the next tab stop.
+fn indent_width(indent: &str, tab_size: usize) -> usize {
+    indent.chars().fold(0, |width, c| {
+        if c == '\t' {
+            (width / tab_size + 1) * tab_size
+        } else {
+            width + 1
+        }
+    })
+}
+
+/// Sort edits by range and drop any that overlap an earlier one, or repeat
+/// its range.
+fn drop_overlapping<T>(edits: &mut Vec<(Range<usize>, T)>) {
+    edits.sort_by_key(|(range, _)| (range.start, range.end));
+    let mut last: Option<Range<usize>> = None;
+    edits.retain(|(range, _)| {
+        let keep = last
+            .as_ref()
+            .is_none_or(|last| range.start >= last.end && range != last);
+        if keep {
+            last = Some(range.clone());
+        }
+        keep
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_editor_state_new() {
+        let state = EditorState::new("Hello, World!");
+        assert_eq!(state.content(), "Hello, World!");
+        assert_eq!(state.line_count(), 1);
+        assert!(!state.is_modified);
+    }
+
+    #[test]
+    fn test_insert() {
         let mut state = EditorState::new("");
         state.insert("Hello");
         assert_eq!(state.content(), "Hello");
@@ -469,6 +2197,25 @@ mod tests {
         assert_eq!(state.content(), "modified");
     }
 
+    #[test]
+    fn test_set_content_within_window() {
+        let mut state = EditorState::new("a😀b\nc");
+        // Typing after the emoji, which is two UTF-16 units long
+        state.set_content_within("a😀xb\nc", 3..3, 4);
+        assert_eq!(state.content(), "a😀xb\nc");
+        // Deleting the emoji backward
+        state.set_content_within("axb\nc", 1..3, 1);
+        assert_eq!(state.content(), "axb\nc");
+        assert!(state.undo());
+        assert_eq!(state.content(), "a😀b\nc");
+
+        // Windows that do not fit the content fall back to a full update
+        state.set_content_within("a😀yb\nc", 2..2, 3);
+        assert_eq!(state.content(), "a😀yb\nc");
+        state.set_content_within("zzz", 0..0, 0);
+        assert_eq!(state.content(), "zzz");
+    }
+
     #[test]
     fn test_position_offset_conversion() {
         let state = EditorState::new("hello\nworld\nfoo");
@@ -483,4 +2230,651 @@ mod tests {
         assert_eq!(state.offset_to_position(0), Some(CursorPosition::new(0, 0)));
         assert_eq!(state.offset_to_position(6), Some(CursorPosition::new(1, 0)));
     }
+
+    #[test]
+    fn test_apply_transaction_maps_cursors() {
+        let mut state = EditorState::new("hello world");
+        state.set_cursor(CursorPosition::new(0, 6));
+
+        let changes = ChangeSet::from_edits(11, [(0..0, ">> "), (6..11, "rope")]);
+        assert!(state.apply(&Transaction::new(changes)));
+        assert_eq!(state.content(), ">> hello rope");
+        assert_eq!(state.cursor_position(), CursorPosition::new(0, 13));
+
+        // A transaction for a different document length is rejected
+        assert!(!state.apply(&Transaction::new(ChangeSet::new(3))));
+
+        assert!(state.undo());
+        assert_eq!(state.content(), "hello world");
+        assert_eq!(state.cursor_position(), CursorPosition::new(0, 6));
+    }
+
+    #[test]
+    fn test_delete_removes_whole_grapheme() {
+        let mut state = EditorState::new("a👍🏽");
+        // The emoji is two chars, so the end of the line is column 3
+        state.set_cursor(CursorPosition::new(0, 3));
+        state.delete_backward();
+        assert_eq!(state.content(), "a");
+        assert_eq!(state.cursor_position(), CursorPosition::new(0, 1));
+    }
+
+    #[test]
+    fn test_multi_cursor_edits_are_one_step() {
+        let mut state = EditorState::new("one\ntwo\nthree");
+        state.set_cursor(CursorPosition::new(0, 3));
+        assert!(state.add_cursor_below());
+        assert!(state.add_cursor_below());
+        // The third line is longer, so its cursor keeps column 3
+        assert_eq!(state.cursors.all()[2].head, CursorPosition::new(2, 3));
+
+        state.insert("!");
+        assert_eq!(state.content(), "one!\ntwo!\nthr!ee");
+        assert_eq!(state.history.undo_count(), 1);
+        state.delete_backward();
+        state.delete_backward();
+        assert_eq!(state.content(), "on\ntw\nthee");
+        let heads: Vec<_> = state.cursors.all().iter().map(|c| c.head).collect();
+        assert_eq!(
+            heads,
+            [
+                CursorPosition::new(0, 2),
+                CursorPosition::new(1, 2),
+                CursorPosition::new(2, 2)
+            ]
+        );
+
+        // Edits inside the coalesce window undo together
+        assert!(state.undo());
+        assert_eq!(state.content(), "one\ntwo\nthree");
+        assert_eq!(state.cursors.all().len(), 3);
+        assert!(!state.add_cursor_below());
+    }
+
+    #[test]
+    fn test_move_every_cursor() {
+        let mut state = EditorState::new("foo bar\nbaz qux");
+        state.add_cursor(CursorPosition::new(1, 0));
+
+        state.move_cursors(Movement::WordRight, true);
+        assert_eq!(state.selected_text().as_deref(), Some("foo"));
+        assert_eq!(state.cursors.all()[1].head, CursorPosition::new(1, 3));
+
+        state.move_cursors(Movement::DocumentStart, false);
+        assert!(!state.cursors.is_multi());
+    }
+
+    #[test]
+    fn test_word_and_line_selection() {
+        let mut state = EditorState::new("alpha beta gamma\nsecond line\nthird");
+
+        assert!(state.select_word_at(CursorPosition::new(0, 7)));
+        assert_eq!(state.selected_text().as_deref(), Some("beta"));
+        // Dragging extends by whole words in either direction
+        state.extend_selection_to(CursorPosition::new(0, 12));
+        assert_eq!(state.selected_text().as_deref(), Some("beta gamma"));
+        state.extend_selection_to(CursorPosition::new(0, 2));
+        assert_eq!(state.selected_text().as_deref(), Some("alpha beta"));
+
+        assert!(state.select_line(1));
+        assert_eq!(state.selected_text().as_deref(), Some("second line\n"));
+        state.extend_selection_to(CursorPosition::new(2, 1));
+        assert_eq!(state.selected_text().as_deref(), Some("second line\nthird"));
+        assert!(!state.select_line(3));
+    }
+
+    #[test]
+    fn test_line_selection_at_document_end() {
+        // Triple-click on the last line selects up to the end of the document
+        let mut state = EditorState::new("one\ntwo");
+        assert!(state.select_line(1));
+        assert_eq!(state.selected_text().as_deref(), Some("two"));
+        state.extend_selection_to(CursorPosition::new(5, 0));
+        assert_eq!(state.selected_text().as_deref(), Some("two"));
+
+        // After a final line break the last line is empty
+        let mut state = EditorState::new("one\n");
+        assert!(state.select_line(1));
+        assert_eq!(state.selected_text(), None);
+        assert_eq!(state.cursor_position(), CursorPosition::new(1, 0));
+        state.extend_selection_to(CursorPosition::new(0, 2));
+        assert_eq!(state.selected_text().as_deref(), Some("one\n"));
+    }
+
+    #[test]
+    fn test_block_selection_with_virtual_space() {
+        let mut state = EditorState::new("name  age\nbob\nalice 30");
+        state.select_block(CursorPosition::new(0, 6), CursorPosition::new(2, 9));
+        assert_eq!(state.cursors.all().len(), 3);
+        assert_eq!(state.block_text().as_deref(), Some("age\n\n30"));
+
+        // The short middle line is padded out to the block
+        state.insert("x");
+        assert_eq!(state.content(), "name  x\nbob   x\nalice x");
+        state.delete_backward();
+        assert_eq!(state.content(), "name  \nbob   \nalice ");
+
+        assert!(state.paste_block("1\n2\n3"));
+        assert_eq!(state.content(), "name  1\nbob   2\nalice 3");
+        state.select_block(CursorPosition::new(0, 6), CursorPosition::new(1, 7));
+        assert_eq!(state.cut_block().as_deref(), Some("1\n2"));
+        assert_eq!(state.content(), "name  \nbob   \nalice 3");
+
+        state.move_cursors(Movement::DocumentEnd, false);
+        assert_eq!(state.active_block(), None);
+    }
+
+    #[test]
+    fn test_zero_width_block_clipboard() {
+        let mut state = EditorState::new("abc\ndef\nghi");
+        state.select_block(CursorPosition::new(0, 2), CursorPosition::new(1, 2));
+        assert!(state.active_block().is_some());
+        assert_eq!(state.block_text(), None);
+
+        // Copy and cut fall back to the whole lines under the cursors
+        assert_eq!(state.cut_block(), None);
+        assert_eq!(state.content(), "abc\ndef\nghi");
+        assert_eq!(state.copy_text(), ("abc\ndef\n".to_string(), true));
+        assert_eq!(state.cut(), ("abc\ndef\n".to_string(), true));
+        assert_eq!(state.content(), "ghi");
+    }
+
+    #[test]
+    fn test_line_lookup_on_large_document() {
+        let content = "line\n".repeat(100_000);
+        let mut state = EditorState::new(content.as_str());
+        assert_eq!(state.line_count(), 100_001);
+        assert_eq!(state.get_line(99_999).as_deref(), Some("line"));
+        assert_eq!(
+            state.position_to_offset(CursorPosition::new(75_000, 2)),
+            Some(375_002)
+        );
+
+        state.set_cursor(CursorPosition::new(50_000, 4));
+        state.insert("\nnew");
+        assert_eq!(state.line_count(), 100_002);
+        assert_eq!(state.get_line(50_001).as_deref(), Some("new"));
+        assert_eq!(
+            state.offset_to_position(content.len() + 4),
+            Some(CursorPosition::new(100_001, 0))
+        );
+    }
+
+    #[test]
+    fn test_insert_newline_auto_indents() {
+        let mut state = EditorState::new("    let x = 1;\nfn main() {}");
+        state.set_cursor(CursorPosition::new(0, 14));
+        state.add_cursor(CursorPosition::new(1, 11));
+        state.insert_newline();
+        assert_eq!(
+            state.content(),
+            "    let x = 1;\n    \nfn main() {\n    \n}"
+        );
+        let heads: Vec<_> = state.cursors.all().iter().map(|c| c.head).collect();
+        assert_eq!(
+            heads,
+            [CursorPosition::new(1, 4), CursorPosition::new(3, 4)]
+        );
+
+        let mut state = EditorState::new("if x:");
+        state.language = Some("python".to_string());
+        state.set_cursor(CursorPosition::new(0, 5));
+        state.insert_newline();
+        assert_eq!(state.content(), "if x:\n    ");
+
+        state.config.auto_indent = false;
+        state.insert_newline();
+        assert_eq!(state.content(), "if x:\n    \n");
+    }
+
+    #[test]
+    fn test_auto_close_pairs() {
+        let mut state = EditorState::new("");
+        state.language = Some("rust".to_string());
+        assert!(state.auto_close('('));
+        assert!(state.auto_close('"'));
+        assert!(!state.auto_close('x'));
+        state.insert("x");
+        assert_eq!(state.content(), "(\"x\")");
+        assert!(state.auto_close('"'));
+        assert!(state.auto_close(')'));
+        assert_eq!(state.content(), "(\"x\")");
+        assert_eq!(state.cursor_position(), CursorPosition::new(0, 5));
+
+        // Wrapping keeps the text selected
+        state.set_cursor_with_selection(CursorPosition::new(0, 2), CursorPosition::new(0, 3));
+        assert!(state.auto_close('['));
+        assert_eq!(state.content(), "(\"[x]\")");
+        assert_eq!(state.selected_text().as_deref(), Some("x"));
+
+        // No pair for a lifetime; Backspace removes an empty pair
+        state.set_content("&");
+        state.set_cursor(CursorPosition::new(0, 1));
+        assert!(!state.auto_close('\''));
+        assert!(state.auto_close('{'));
+        state.delete_backward();
+        assert_eq!(state.content(), "&");
+    }
+
+    #[test]
+    fn test_jump_to_bracket() {
+        let mut state = EditorState::new("if (a) {\n    b();\n}");
+        state.set_cursor(CursorPosition::new(0, 7));
+        let found = state.matching_brackets();
+        assert_eq!(found.len(), 1);
+        assert_eq!((found[0].bracket, found[0].partner), (7, 18));
+
+        state.jump_to_bracket();
+        assert_eq!(state.cursor_position(), CursorPosition::new(2, 0));
+        state.jump_to_bracket();
+        assert_eq!(state.cursor_position(), CursorPosition::new(0, 7));
+
+        state.config.match_brackets = false;
+        assert!(state.matching_brackets().is_empty());
+    }
+
+    #[test]
+    fn test_indent_and_outdent_lines() {
+        let mut state = EditorState::new("a\n  b\nc\nd");
+        state.set_cursor_with_selection(CursorPosition::new(2, 0), CursorPosition::new(0, 1));
+        state.indent();
+        assert_eq!(state.content(), "    a\n      b\nc\nd");
+        assert_eq!(state.history.undo_count(), 1);
+        assert_eq!(state.selected_text().as_deref(), Some("\n      b\n"));
+
+        state.outdent();
+        state.outdent();
+        assert_eq!(state.content(), "a\nb\nc\nd");
+
+        state.config.insert_spaces = false;
+        state.set_cursor(CursorPosition::new(3, 1));
+        state.indent();
+        assert_eq!(state.content(), "a\nb\nc\nd\t");
+        state.set_content("\td");
+        state.outdent();
+        assert_eq!(state.content(), "d");
+    }
+
+    #[test]
+    fn test_outdent_mixed_indentation() {
+        let mut state = EditorState::new("\t  a\n  \tb\n    c");
+        // The selection ends at the start of line 2, which is left alone
+        state.set_cursor_with_selection(CursorPosition::new(2, 0), CursorPosition::new(0, 0));
+        state.outdent();
+        assert_eq!(state.content(), "  a\n\tb\n    c");
+        state.outdent();
+        assert_eq!(state.content(), "a\nb\n    c");
+        state.outdent();
+        assert_eq!(state.content(), "a\nb\n    c");
+    }
+
+    #[test]
+    fn test_toggle_comments() {
+        let mut state = EditorState::new("fn f() {\n    a();\n\n  b();\n}");
+        state.language = Some("rust".to_string());
+        state.set_cursor_with_selection(CursorPosition::new(3, 2), CursorPosition::new(1, 4));
+        state.toggle_line_comment();
+        assert_eq!(state.content(), "fn f() {\n  //   a();\n\n  // b();\n}");
+        state.toggle_line_comment();
+        assert_eq!(state.content(), "fn f() {\n    a();\n\n  b();\n}");
+
+        state.set_cursor_with_selection(CursorPosition::new(1, 8), CursorPosition::new(1, 4));
+        state.toggle_block_comment();
+        assert_eq!(state.get_line(1).as_deref(), Some("    /* a(); */"));
+        assert_eq!(state.selected_text().as_deref(), Some("a();"));
+        state.toggle_block_comment();
+        assert_eq!(state.get_line(1).as_deref(), Some("    a();"));
+        state.set_cursor(CursorPosition::new(1, 0));
+        state.toggle_block_comment();
+        assert_eq!(state.get_line(1).as_deref(), Some("    /* a(); */"));
+
+        let mut state = EditorState::new("<p>\n  <b>");
+        state.language = Some("html".to_string());
+        state.select_all();
+        state.toggle_line_comment();
+        assert_eq!(state.content(), "<!-- <p> -->\n<!--   <b> -->");
+    }
+
+    #[test]
+    fn test_toggle_comments_mixed_and_unsupported() {
+        // Some lines commented: the rest are commented too
+        let mut state = EditorState::new("# a\nb\n  # c");
+        state.language = Some("python".to_string());
+        state.select_all();
+        state.toggle_line_comment();
+        assert_eq!(state.content(), "# # a\n# b\n#   # c");
+        state.toggle_line_comment();
+        assert_eq!(state.content(), "# a\nb\n  # c");
+
+        // CSS has only block comments
+        let mut state = EditorState::new("a {}\n/* b {} */");
+        state.language = Some("css".to_string());
+        state.select_all();
+        state.toggle_line_comment();
+        assert_eq!(state.content(), "/* a {} */\n/* /* b {} */ */");
+        state.toggle_line_comment();
+        assert_eq!(state.content(), "a {}\n/* b {} */");
+
+        // Plain text has no comments at all
+        let mut state = EditorState::new("text");
+        state.toggle_line_comment();
+        state.toggle_block_comment();
+        assert_eq!(state.content(), "text");
+        assert!(!state.can_undo());
+    }
+
+    #[test]
+    fn test_move_copy_and_delete_lines() {
+        let mut state = EditorState::new("a\nb\nc\nd");
+        state.set_cursor(CursorPosition::new(1, 1));
+        state.add_cursor(CursorPosition::new(2, 0));
+        state.move_lines_up();
+        assert_eq!(state.content(), "b\nc\na\nd");
+        let heads: Vec<_> = state.cursors.all().iter().map(|c| c.head).collect();
+        assert_eq!(
+            heads,
+            [CursorPosition::new(0, 1), CursorPosition::new(1, 0)]
+        );
+        state.move_lines_up();
+        assert_eq!(state.content(), "b\nc\na\nd");
+        state.move_lines_down();
+        state.move_lines_down();
+        assert_eq!(state.content(), "a\nd\nb\nc");
+
+        state.clear_secondary_cursors();
+        state.set_cursor_with_selection(CursorPosition::new(3, 0), CursorPosition::new(1, 0));
+        state.copy_lines_down();
+        assert_eq!(state.content(), "a\nd\nb\nd\nb\nc");
+        assert_eq!(state.cursors.primary().anchor, CursorPosition::new(3, 0));
+        state.delete_lines();
+        assert_eq!(state.content(), "a\nd\nb\nc");
+        assert_eq!(state.cursor_position(), CursorPosition::new(3, 0));
+        assert_eq!(state.history.undo_count(), 1);
+    }
+
+    #[test]
+    fn test_join_and_insert_lines() {
+        let mut state = EditorState::new("f(\n    x\n)\n  y");
+        state.select_all();
+        state.join_lines();
+        assert_eq!(state.content(), "f( x) y");
+
+        let mut state = EditorState::new("  a\nb");
+        state.set_cursor(CursorPosition::new(0, 2));
+        state.insert_line_below();
+        state.insert("1");
+        state.insert_line_above();
+        state.insert("2");
+        assert_eq!(state.content(), "  a\n  2\n  1\nb");
+    }
+
+    #[test]
+    fn test_sort_and_rewrite_lines() {
+        let mut state = EditorState::new("item10\nItem2\nitem2\nitem1");
+        state.sort_lines(SortOrder::Ascending);
+        assert_eq!(state.content(), "Item2\nitem1\nitem10\nitem2");
+        state.sort_lines(SortOrder::Natural);
+        assert_eq!(state.content(), "Item2\nitem1\nitem2\nitem10");
+        state.sort_lines(SortOrder::CaseInsensitive);
+        assert_eq!(state.content(), "item1\nitem10\nItem2\nitem2");
+
+        state.set_cursor_with_selection(CursorPosition::new(3, 5), CursorPosition::new(1, 0));
+        state.reverse_lines();
+        assert_eq!(state.content(), "item1\nitem2\nItem2\nitem10");
+        state.set_content("b\na\nb\na");
+        state.remove_duplicate_lines();
+        assert_eq!(state.content(), "b\na");
+        assert_eq!(state.cursors.primary().head, CursorPosition::new(1, 1));
+    }
+
+    #[test]
+    fn test_transforms_at_every_cursor() {
+        let mut state = EditorState::new("let userName = 1;\nlet userId = 2;");
+        state.set_cursor(CursorPosition::new(0, 6));
+        state.add_cursor(CursorPosition::new(1, 6));
+        state.transform_selections(TextTransform::SnakeCase);
+        assert_eq!(state.content(), "let user_name = 1;\nlet user_id = 2;");
+        assert_eq!(state.cursor_position(), CursorPosition::new(0, 6));
+
+        state.clear_secondary_cursors();
+        state.set_cursor_with_selection(CursorPosition::new(0, 3), CursorPosition::new(0, 0));
+        state.transform_selections(TextTransform::Base64Encode);
+        assert_eq!(state.selected_text().as_deref(), Some("bGV0"));
+        state.transform_selections(TextTransform::Base64Decode);
+        assert_eq!(state.get_line(0).as_deref(), Some("let user_name = 1;"));
+
+        state.set_cursor(CursorPosition::new(1, 2));
+        state.transpose_chars();
+        assert_eq!(state.get_line(1).as_deref(), Some("lte user_id = 2;"));
+        assert_eq!(state.cursor_position(), CursorPosition::new(1, 3));
+    }
+
+    #[test]
+    fn test_transpose_graphemes() {
+        // "e" with a combining acute accent stays one character
+        let mut state = EditorState::new("ae\u{301}b");
+        state.set_cursor(CursorPosition::new(0, 1));
+        state.transpose_chars();
+        assert_eq!(state.content(), "e\u{301}ab");
+        assert_eq!(state.cursor_position(), CursorPosition::new(0, 3));
+
+        // At the end of the line the last two characters swap
+        state.set_cursor(CursorPosition::new(0, 4));
+        state.transpose_chars();
+        assert_eq!(state.content(), "e\u{301}ba");
+    }
+
+    #[test]
+    fn test_transpose_without_two_characters() {
+        for content in ["", "a"] {
+            let mut state = EditorState::new(content);
+            state.set_cursor(CursorPosition::new(0, content.len()));
+            state.transpose_chars();
+            assert_eq!(state.content(), content);
+            assert!(!state.can_undo());
+            assert!(!state.is_modified);
+            assert_eq!(state.version, 0);
+        }
+    }
+
+    #[test]
+    fn test_whitespace_commands() {
+        let mut state = EditorState::new("\tif x {  \n  \t y \n}");
+        state.trim_trailing_whitespace();
+        assert_eq!(state.content(), "\tif x {\n  \t y\n}");
+        state.indentation_to_spaces();
+        assert_eq!(state.content(), "    if x {\n     y\n}");
+        state.config.tab_size = 2;
+        state.indentation_to_tabs();
+        assert_eq!(state.content(), "\t\tif x {\n\t\t y\n}");
+    }
+
+    #[test]
+    fn test_line_wise_clipboard() {
+        let mut state = EditorState::new("one\ntwo");
+        state.set_cursor(CursorPosition::new(1, 1));
+        let (text, linewise) = state.copy_text();
+        assert_eq!((text.as_str(), linewise), ("two\n", true));
+        assert!(state.paste(&text, linewise));
+        assert_eq!(state.content(), "one\ntwo\ntwo");
+        assert_eq!(state.cursor_position(), CursorPosition::new(2, 1));
+        assert!(state.undo());
+        assert_eq!(state.content(), "one\ntwo");
+
+        // One pasted line per cursor
+        state.add_cursor(CursorPosition::new(0, 3));
+        state.paste("a\nb", false);
+        assert_eq!(state.content(), "onea\ntbwo");
+
+        state.clear_secondary_cursors();
+        state.set_cursor(CursorPosition::new(1, 0));
+        assert_eq!(state.cut(), ("tbwo\n".to_string(), true));
+        assert_eq!(state.content(), "onea");
+    }
+
+    #[test]
+    fn test_external_content_changes() {
+        let mut state = EditorState::new("let x = 1;");
+        state.set_cursor(CursorPosition::new(0, 10));
+        assert!(state.sync_content("// note\nlet x = 1;"));
+        assert!(!state.sync_content("// note\nlet x = 1;"));
+        assert_eq!(state.cursor_position(), CursorPosition::new(1, 10));
+        assert!(state.undo());
+        assert_eq!(state.content(), "let x = 1;");
+
+        state.reset_content("other file");
+        assert!(!state.can_undo() && !state.can_redo());
+        assert_eq!(state.cursor_position(), CursorPosition::zero());
+        assert!(!state.is_modified);
+    }
+
+    #[test]
+    fn test_external_change_with_multiple_selections() {
+        let mut state = EditorState::new("one two\nthree four");
+        state.set_cursor_with_selection(CursorPosition::new(0, 3), CursorPosition::new(0, 0));
+        state.add_cursor(CursorPosition::new(1, 5));
+        let selections = |state: &EditorState| -> Vec<_> {
+            state
+                .cursors
+                .all()
+                .iter()
+                .map(|cursor| (cursor.anchor, cursor.head))
+                .collect()
+        };
+
+        // Every selection follows its text
+        assert!(state.sync_content("// note\none two\nthree four"));
+        assert_eq!(
+            selections(&state),
+            [
+                (CursorPosition::new(1, 0), CursorPosition::new(1, 3)),
+                (CursorPosition::new(2, 5), CursorPosition::new(2, 5)),
+            ]
+        );
+
+        // Cursors in removed text end up together inside the document
+        assert!(state.sync_content("// note\n"));
+        assert_eq!(
+            selections(&state),
+            [(CursorPosition::new(1, 0), CursorPosition::new(1, 0))]
+        );
+        assert!(state.undo());
+        assert_eq!(state.content(), "// note\none two\nthree four");
+    }
+
+    #[test]
+    fn test_reflow_paragraph() {
+        let mut state = EditorState::new("fn f() {}\n\n# one two three\n# four\n\nlast");
+        state.config.max_line_width = 12;
+        state.config.rulers = vec![100, 12];
+        assert_eq!(state.config.ruler_columns(), [12, 100]);
+        state.set_cursor(CursorPosition::new(3, 2));
+        state.reflow_paragraph();
+        assert_eq!(
+            state.content(),
+            "fn f() {}\n\n# one two\n# three four\n\nlast"
+        );
+        assert_eq!(state.cursor_position(), CursorPosition::new(3, 12));
+        assert!(state.undo());
+        assert_eq!(state.line_count(), 6);
+    }
+
+    #[cfg(feature = "folding")]
+    #[test]
+    fn test_edits_move_folds() {
+        use crate::components::editor::folding::FoldKind;
+
+        let mut state = EditorState::new("# A\ntext\n# B\nmore");
+        let id = state.folds.add_region(2, 3, FoldKind::Heading(1));
+        state.insert("intro\n");
+        let lines = |state: &EditorState| {
+            let region = state.folds.get_region(id).expect("region survives");
+            (region.start_line, region.end_line)
+        };
+        assert_eq!(lines(&state), (3, 4));
+        assert!(state.undo());
+        assert_eq!(lines(&state), (2, 3));
+    }
+
+    #[cfg(feature = "find-replace")]
+    #[test]
+    fn test_edits_move_find_matches() {
+        use crate::components::editor::find_replace::FindResult;
+
+        let mut state = EditorState::new("ab cd ab");
+        state.find.query = "ab".to_string();
+        let content = state.content();
+        state.find.search(&content);
+        state.insert("xx ");
+        assert_eq!(
+            state.find.matches,
+            [FindResult::new(3, 5), FindResult::new(9, 11)]
+        );
+        assert!(state.undo());
+        assert_eq!(
+            state.find.matches,
+            [FindResult::new(0, 2), FindResult::new(6, 8)]
+        );
+    }
+
+    #[test]
+    fn test_whitespace_setting_serde() {
+        let mut config = EditorConfig::default();
+        for mode in [WhitespaceMode::Boundary, WhitespaceMode::All] {
+            config.show_whitespace = mode;
+            let json = serde_json::to_string(&config).unwrap();
+            let parsed: EditorConfig = serde_json::from_str(&json).unwrap();
+            assert_eq!(parsed.show_whitespace, mode);
+        }
+
+        // Configurations saved when the setting was a `bool`
+        for (show, mode) in [(true, WhitespaceMode::All), (false, WhitespaceMode::None)] {
+            let mut json = serde_json::to_value(&config).unwrap();
+            json["show_whitespace"] = show.into();
+            let parsed: EditorConfig = serde_json::from_value(json).unwrap();
+            assert_eq!(parsed.show_whitespace, mode);
+        }
+    }
+
+    #[test]
+    fn test_take_change() {
+        let mut state = EditorState::new("ab");
+        state.set_cursor(CursorPosition::new(0, 1));
+        state.insert("x");
+        state.insert("y");
+        let event = state.take_change(ChangeOrigin::Typing).unwrap();
+        assert_eq!(event.changes.len(), 1);
+        assert_eq!((event.changes[0].offset, event.changes[0].deleted), (1, 0));
+        assert_eq!(event.changes[0].inserted, "xy");
+        assert_eq!(
+            event.cursors_before.primary().head,
+            CursorPosition::new(0, 1)
+        );
+        assert_eq!(
+            event.cursors_after.primary().head,
+            CursorPosition::new(0, 3)
+        );
+        assert_eq!(event.version, state.version);
+        assert!(state.take_change(ChangeOrigin::Typing).is_none());
+
+        state.undo();
+        let event = state.take_change(ChangeOrigin::Undo).unwrap();
+        assert_eq!(event.changes[0].deleted, 2);
+    }
+
+    #[test]
+    fn test_line_endings() {
+        let mut state = EditorState::new("one\r\ntwo\r\n");
+        assert_eq!(state.line_ending, LineEnding::CrLf);
+        assert_eq!(state.content(), "one\ntwo\n");
+        assert_eq!(state.get_line(0).as_deref(), Some("one"));
+        state.set_cursor(CursorPosition::new(2, 0));
+        state.insert("three");
+        assert_eq!(state.document_text(), "one\r\ntwo\r\nthree");
+
+        // The text that was just reported is already in sync
+        assert!(!state.sync_content("one\r\ntwo\r\nthree"));
+        assert!(state.set_line_ending(LineEnding::Lf));
+        assert_eq!(state.document_text(), "one\ntwo\nthree");
+    }
 }
@@ -7,94 +7,7 @@ use syntect::highlighting::ThemeSet;
 #[cfg(feature = "syntax-highlighting")]
 use syntect::parsing::SyntaxSet;
 
-/// Supported languages for syntax highlighting.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum Language {
-    /// Rust
-    Rust,
-    /// JavaScript
-    JavaScript,
-    /// TypeScript
-    TypeScript,
-    /// Python
-    Python,
-    /// HTML
-    Html,
-    /// CSS
-    Css,
-    /// JSON
-    Json,
-    /// YAML
-    Yaml,
-    /// TOML
-    Toml,
-    /// Markdown
-    Markdown,
-    /// SQL
-    Sql,
-    /// Shell/Bash
-    Shell,
-    /// Go
-    Go,
-    /// C
-    C,
-    /// C++
-    Cpp,
-    /// Java
-    Java,
-    /// Plain text (no highlighting)
-    PlainText,
-}
-
-impl Language {
-    /// Detect language from file extension.
-    #[must_use]
-    pub fn from_extension(ext: &str) -> Self {
-        match ext.to_lowercase().as_str() {
-            "rs" => Self::Rust,
-            "js" | "mjs" | "cjs" => Self::JavaScript,
-            "ts" | "mts" | "cts" | "tsx" => Self::TypeScript,
-            "py" | "pyi" => Self::Python,
-            "html" | "htm" => Self::Html,
-            "css" | "scss" | "sass" | "less" => Self::Css,
-            "json" => Self::Json,
-            "yaml" | "yml" => Self::Yaml,
-            "toml" => Self::Toml,
-            "md" | "markdown" => Self::Markdown,
-            "sql" => Self::Sql,
-            "sh" | "bash" | "zsh" | "fish" => Self::Shell,
-            "go" => Self::Go,
-            "c" | "h" => Self::C,
-            "cpp" | "cxx" | "cc" | "hpp" | "hxx" => Self::Cpp,
-            "java" => Self::Java,
-            _ => Self::PlainText,
-        }
-    }
-
-    /// Get the syntect syntax name.
-    #[must_use]
-    pub fn syntax_name(&self) -> &'static str {
-        match self {
-            Self::Rust => "Rust",
-            Self::JavaScript => "JavaScript",
-            Self::TypeScript => "TypeScript",
-            Self::Python => "Python",
-            Self::Html => "HTML",
-            Self::Css => "CSS",
-            Self::Json => "JSON",
-            Self::Yaml => "YAML",
-            Self::Toml => "TOML",
-            Self::Markdown => "Markdown",
-            Self::Sql => "SQL",
-            Self::Shell => "Bourne Again Shell (bash)",
-            Self::Go => "Go",
-            Self::C => "C",
-            Self::Cpp => "C++",
-            Self::Java => "Java",
-            Self::PlainText => "Plain Text",
-        }
-    }
-}
+use super::language::Language;
 
 /// Configuration for syntax highlighting.
 #[derive(Debug, Clone)]
@@ -251,14 +164,6 @@ impl Default for Highlighter {
 mod tests {
     use super::*;
 
-    #[test]
-    fn test_language_from_extension() {
-        assert_eq!(Language::from_extension("rs"), Language::Rust);
-        assert_eq!(Language::from_extension("js"), Language::JavaScript);
-        assert_eq!(Language::from_extension("py"), Language::Python);
-        assert_eq!(Language::from_extension("unknown"), Language::PlainText);
-    }
-
     #[test]
     fn test_highlighted_span_style() {
         let span = HighlightedSpan {
@@ -0,0 +1,652 @@
+//! Change sets and transactions
+//!
+//! A [`ChangeSet`] describes an edit to the whole document as a sequence of
+//! retain, delete and insert operations. Change sets can be applied,
+//! inverted, composed and used to map positions from the old document into
+//! the new one, so every piece of state that refers to document offsets
+//! (cursors, find results, fold regions) can follow the same edit.
+
+use std::ops::Range;
+
+use serde::{Deserialize, Serialize};
+
+use super::{buffer::TextBuffer, cursor::CursorSet};
+
+/// A single operation in a [`ChangeSet`].
+///
+/// Lengths are in bytes.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Operation {
+    /// Keep the next `n` bytes unchanged
+    Retain(usize),
+    /// Remove the next `n` bytes
+    Delete(usize),
+    /// Insert text at the current position
+    Insert(String),
+}
+
+/// Which side of an insertion a mapped position sticks to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Assoc {
+    /// Stay before text inserted exactly at the position
+    Before,
+    /// Move past text inserted exactly at the position
+    #[default]
+    After,
+}
+
+/// A description of an edit to a document.
+///
+/// The operations cover the whole document: the retained and deleted
+/// lengths add up to [`ChangeSet::len_before`], and the retained and
+/// inserted lengths add up to [`ChangeSet::len_after`].
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChangeSet {
+    ops: Vec<Operation>,
+    len_before: usize,
+    len_after: usize,
+}
+
+impl ChangeSet {
+    /// Create an empty change set for a document of `len` bytes.
+    ///
+    /// Add operations with [`retain`](Self::retain),
+    /// [`delete`](Self::delete) and [`insert`](Self::insert); any part of the
+    /// document not covered by them is retained.
+    #[must_use]
+    pub fn new(len: usize) -> Self {
+        Self {
+            ops: Vec::new(),
+            len_before: len,
+            len_after: len,
+        }
+    }
+
+    /// Create a change set that replaces a single byte range.
+    #[must_use]
+    pub fn replace(len: usize, range: Range<usize>, text: &str) -> Self {
+        Self::from_edits(len, [(range, text)])
+    }
+
+    /// Create a change set from a list of `(range, replacement)` edits.
+    ///
+    /// Ranges are byte ranges in the original document. They must be sorted
+    /// and must not overlap.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the ranges are unsorted, overlapping or out of bounds.
+    #[must_use]
+    pub fn from_edits<S: AsRef<str>>(
+        len: usize,
+        edits: impl IntoIterator<Item = (Range<usize>, S)>,
+    ) -> Self {
+        let mut changes = Self::new(len);
+        let mut pos = 0;
+        for (range, text) in edits {
+            assert!(
+                range.start >= pos && range.start <= range.end && range.end <= len,
+                "edits must be sorted, non-overlapping and in bounds"
+            );
+            changes.retain(range.start - pos);
+            changes.delete(range.len());
+            changes.insert(text.as_ref());
+            pos = range.end;
+        }
+        changes
+    }
+
+    /// Create the minimal single-range change set that turns `old` into `new`.
+    #[must_use]
+    pub fn diff(old: &str, new: &str) -> Self {
+        let prefix = old
+            .char_indices()
+            .zip(new.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(old.len().min(new.len()), |((i, _), _)| i);
+
+        let max_suffix = old.len().min(new.len()) - prefix;
+        let suffix = old[prefix..]
+            .chars()
+            .rev()
+            .zip(new[prefix..].chars().rev())
+            .take_while(|(a, b)| a == b)
+            .map(|(a, _)| a.len_utf8())
+            .scan(0, |total, len| {
+                *total += len;
+                Some(*total)
+            })
+            .take_while(|&total| total <= max_suffix)
+            .last()
+            .unwrap_or(0);
+
+        Self::replace(
+            old.len(),
+            prefix..old.len() - suffix,
+            &new[prefix..new.len() - suffix],
+        )
+    }
+
+    /// Keep the next `n` bytes.
+    pub fn retain(&mut self, n: usize) -> &mut Self {
+        if n == 0 {
+            return self;
+        }
+        if let Some(Operation::Retain(last)) = self.ops.last_mut() {
+            *last += n;
+        } else {
+            self.ops.push(Operation::Retain(n));
+        }
+        self
+    }
+
+    /// Delete the next `n` bytes.
+    pub fn delete(&mut self, n: usize) -> &mut Self {
+        if n == 0 {
+            return self;
+        }
+        self.len_after -= n;
+        if let Some(Operation::Delete(last)) = self.ops.last_mut() {
+            *last += n;
+        } else {
+            self.ops.push(Operation::Delete(n));
+        }
+        self
+    }
+
+    /// Insert text at the current position.
+    pub fn insert(&mut self, text: &str) -> &mut Self {
+        if text.is_empty() {
+            return self;
+        }
+        self.len_after += text.len();
+        // Keep inserts before deletes at the same position so that equal
+        // edits always produce equal operation lists.
+        let at = match self.ops.last() {
+            Some(Operation::Delete(_)) => self.ops.len() - 1,
+            _ => self.ops.len(),
+        };
+        match at.checked_sub(1).map(|i| &mut self.ops[i]) {
+            Some(Operation::Insert(prev)) => prev.push_str(text),
+            _ => self.ops.insert(at, Operation::Insert(text.to_string())),
+        }
+        self
+    }
+
+    /// Get the operations, including the implicit trailing retain.
+    #[must_use]
+    pub fn operations(&self) -> Vec<Operation> {
+        let mut ops = self.ops.clone();
+        let covered: usize = ops
+            .iter()
+            .map(|op| match op {
+                Operation::Retain(n) | Operation::Delete(n) => *n,
+                Operation::Insert(_) => 0,
+            })
+            .sum();
+        if covered < self.len_before {
+            ops.push(Operation::Retain(self.len_before - covered));
+        }
+        ops
+    }
+
+    /// Get the document length this change set applies to.
+    #[must_use]
+    pub const fn len_before(&self) -> usize {
+        self.len_before
+    }
+
+    /// Get the document length after applying this change set.
+    #[must_use]
+    pub const fn len_after(&self) -> usize {
+        self.len_after
+    }
+
+    /// Check if this change set leaves the document unchanged.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.ops.iter().all(|op| matches!(op, Operation::Retain(_)))
+    }
+
+    /// Get the changes as replaced ranges, in document order.
+    ///
+    /// Offsets refer to the document before the change, so applying the
+    /// ranges from last to first reproduces it.
+    #[must_use]
+    pub fn text_changes(&self) -> Vec<TextChange> {
+        let mut changes = Vec::new();
+        let mut current: Option<TextChange> = None;
+        let mut offset = 0;
+        for op in &self.ops {
+            let change = |offset| TextChange {
+                offset,
+                deleted: 0,
+                inserted: String::new(),
+            };
+            match op {
+                Operation::Retain(n) => {
+                    changes.extend(current.take());
+                    offset += n;
+                }
+                Operation::Delete(n) => {
+                    current.get_or_insert_with(|| change(offset)).deleted += n;
+                    offset += n;
+                }
+                Operation::Insert(text) => {
+                    current
+                        .get_or_insert_with(|| change(offset))
+                        .inserted
+                        .push_str(text);
+                }
+            }
+        }
+        changes.extend(current);
+        changes
+    }
+
+    /// Apply the changes to a buffer.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the buffer length does not match [`Self::len_before`].
+    pub fn apply(&self, buffer: &mut TextBuffer) {
+        assert_eq!(
+            buffer.len(),
+            self.len_before,
+            "change set does not match the document length"
+        );
+        let mut pos = 0;
+        for op in &self.ops {
+            match op {
+                Operation::Retain(n) => pos += n,
+                Operation::Delete(n) => buffer.remove(pos..pos + n),
+                Operation::Insert(text) => {
+                    buffer.insert(pos, text);
+                    pos += text.len();
+                }
+            }
+        }
+    }
+
+    /// Apply the changes to a string, returning the new text.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the text length does not match [`Self::len_before`].
+    #[must_use]
+    pub fn apply_to_str(&self, text: &str) -> String {
+        assert_eq!(
+            text.len(),
+            self.len_before,
+            "change set does not match the document length"
+        );
+        let mut result = String::with_capacity(self.len_after);
+        let mut pos = 0;
+        for op in &self.ops {
+            match op {
+                Operation::Retain(n) => {
+                    result.push_str(&text[pos..pos + n]);
+                    pos += n;
+                }
+                Operation::Delete(n) => pos += n,
+                Operation::Insert(inserted) => result.push_str(inserted),
+            }
+        }
+        result.push_str(&text[pos..]);
+        result
+    }
+
+    /// Create the change set that undoes this one.
+    ///
+    /// `original` is the document before this change set was applied.
+    #[must_use]
+    pub fn invert(&self, original: &TextBuffer) -> Self {
+        let mut inverted = Self::new(self.len_after);
+        let mut pos = 0;
+        for op in &self.ops {
+            match op {
+                Operation::Retain(n) => {
+                    inverted.retain(*n);
+                    pos += n;
+                }
+                Operation::Delete(n) => {
+                    let removed = original.slice(pos..pos + n).unwrap_or_default();
+                    inverted.insert(&removed);
+                    pos += n;
+                }
+                Operation::Insert(text) => {
+                    inverted.delete(text.len());
+                }
+            }
+        }
+        inverted
+    }
+
+    /// Combine this change set with one that applies to its result.
+    ///
+    /// Applying the composed set has the same effect as applying `self`
+    /// followed by `next`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `next` does not apply to the output of `self`.
+    #[must_use]
+    pub fn compose(&self, next: &Self) -> Self {
+        assert_eq!(
+            self.len_after, next.len_before,
+            "change sets cannot be composed"
+        );
+        let mut composed = Self::new(self.len_before);
+        let mut first = self.operations().into_iter();
+        let mut second = next.operations().into_iter();
+        let mut a = first.next();
+        let mut b = second.next();
+
+        loop {
+            match (a.take(), b.take()) {
+                (None, None) => break,
+                (Some(Operation::Delete(n)), op_b) => {
+                    composed.delete(n);
+                    a = first.next();
+                    b = op_b;
+                }
+                (op_a, Some(Operation::Insert(text))) => {
+                    composed.insert(&text);
+                    a = op_a;
+                    b = second.next();
+                }
+                (Some(Operation::Retain(i)), Some(Operation::Retain(j))) => {
+                    composed.retain(i.min(j));
+                    (a, b) = split_remainder(i, j, &mut first, &mut second, Operation::Retain);
+                }
+                (Some(Operation::Retain(i)), Some(Operation::Delete(j))) => {
+                    composed.delete(i.min(j));
+                    (a, b) = split_remainder(i, j, &mut first, &mut second, Operation::Delete);
+                }
+                (Some(Operation::Insert(text)), Some(Operation::Retain(j))) => {
+                    let n = text.len().min(j);
+                    composed.insert(&text[..n]);
+                    a = if n < text.len() {
+                        Some(Operation::Insert(text[n..].to_string()))
+                    } else {
+                        first.next()
+                    };
+                    b = if n < j {
+                        Some(Operation::Retain(j - n))
+                    } else {
+                        second.next()
+                    };
+                }
+                (Some(Operation::Insert(text)), Some(Operation::Delete(j))) => {
+                    let n = text.len().min(j);
+                    a = if n < text.len() {
+                        Some(Operation::Insert(text[n..].to_string()))
+                    } else {
+                        first.next()
+                    };
+                    b = if n < j {
+                        Some(Operation::Delete(j - n))
+                    } else {
+                        second.next()
+                    };
+                }
+                (None, Some(_)) | (Some(_), None) => {
+                    unreachable!("change set lengths were checked above")
+                }
+            }
+        }
+        composed
+    }
+
+    /// Map a byte offset in the old document to the new document.
+    ///
+    /// Positions inside a deleted range collapse to its start. `assoc`
+    /// decides whether a position exactly at an insertion ends up before or
+    /// after the inserted text.
+    #[must_use]
+    pub fn map_position(&self, pos: usize, assoc: Assoc) -> usize {
+        let mut old = 0;
+        let mut new = 0;
+        for op in &self.ops {
+            match op {
+                Operation::Retain(n) => {
+                    if pos < old + n {
+                        return new + (pos - old);
+                    }
+                    old += n;
+                    new += n;
+                }
+                Operation::Delete(n) => {
+                    if pos < old + n {
+                        return new;
+                    }
+                    old += n;
+                }
+                Operation::Insert(text) => {
+                    if pos == old && assoc == Assoc::Before {
+                        return new;
+                    }
+                    new += text.len();
+                }
+            }
+        }
+        new + pos.saturating_sub(old)
+    }
+
+    /// Check if any change falls inside or overlaps a byte range of the old
+    /// document.
+    ///
+    /// Insertions exactly at the range boundaries do not count.
+    #[must_use]
+    pub fn touches(&self, range: Range<usize>) -> bool {
+        let mut old = 0;
+        for op in &self.ops {
+            match op {
+                Operation::Retain(n) => old += n,
+                Operation::Delete(n) => {
+                    if old < range.end && old + n > range.start {
+                        return true;
+                    }
+                    old += n;
+                }
+                Operation::Insert(_) => {
+                    if old > range.start && old < range.end {
+                        return true;
+                    }
+                }
+            }
+            if old >= range.end {
+                break;
+            }
+        }
+        false
+    }
+}
+
+/// Continue a compose step after consuming `min(i, j)` from two length ops.
+fn split_remainder(
+    i: usize,
+    j: usize,
+    first: &mut impl Iterator<Item = Operation>,
+    second: &mut impl Iterator<Item = Operation>,
+    second_kind: fn(usize) -> Operation,
+) -> (Option<Operation>, Option<Operation>) {
+    match i.cmp(&j) {
+        std::cmp::Ordering::Less => (first.next(), Some(second_kind(j - i))),
+        std::cmp::Ordering::Greater => (Some(Operation::Retain(i - j)), second.next()),
+        std::cmp::Ordering::Equal => (first.next(), second.next()),
+    }
+}
+
+/// A change set bundled with the selection that should result from it.
+///
+/// When no selection is given, [`EditorState::apply`](super::EditorState::apply)
+/// maps the existing cursors through the changes.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Transaction {
+    changes: ChangeSet,
+    selection: Option<CursorSet>,
+}
+
+impl Transaction {
+    /// Create a transaction from a change set.
+    #[must_use]
+    pub fn new(changes: ChangeSet) -> Self {
+        Self {
+            changes,
+            selection: None,
+        }
+    }
+
+    /// Set the selection to use after the changes are applied.
+    ///
+    /// Positions refer to the document after the change.
+    #[must_use]
+    pub fn with_selection(mut self, selection: CursorSet) -> Self {
+        self.selection = Some(selection);
+        self
+    }
+
+    /// Get the change set.
+    #[must_use]
+    pub fn changes(&self) -> &ChangeSet {
+        &self.changes
+    }
+
+    /// Get the resulting selection, if one was set.
+    #[must_use]
+    pub fn selection(&self) -> Option<&CursorSet> {
+        self.selection.as_ref()
+    }
+}
+
+/// One replaced range of a [`ChangeSet`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TextChange {
+    /// Byte offset of the range in the document before the change
+    pub offset: usize,
+    /// Number of bytes removed
+    pub deleted: usize,
+    /// Text inserted in their place
+    pub inserted: String,
+}
+
+/// What caused a [`ChangeEvent`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChangeOrigin {
+    /// Keys typed into the editor
+    Typing,
+    /// Pasting from the clipboard
+    Paste,
+    /// Cutting to the clipboard
+    Cut,
+    /// Undo
+    Undo,
+    /// Redo
+    Redo,
+    /// A command, from a key binding, a Vim or Emacs key, or an
+    /// [`EditorHandle`](super::EditorHandle)
+    Command,
+    /// The parent changing the editor's `value`
+    External,
+}
+
+/// A change to the document, as reported by the editor's `on_transaction`
+/// callback.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChangeEvent {
+    /// The replaced ranges; see [`ChangeSet::text_changes`]
+    pub changes: Vec<TextChange>,
+    /// Cursors before the change
+    pub cursors_before: CursorSet,
+    /// Cursors after the change
+    pub cursors_after: CursorSet,
+    /// What caused the change
+    pub origin: ChangeOrigin,
+    /// The new [`EditorState::version`](super::EditorState::version)
+    pub version: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_apply_and_invert() {
+        let original = TextBuffer::from("hello world");
+        let changes = ChangeSet::from_edits(11, [(0..5, "goodbye"), (6..11, "rope")]);
+        assert_eq!(changes.len_after(), 12);
+
+        let mut buffer = original.clone();
+        changes.apply(&mut buffer);
+        assert_eq!(buffer, "goodbye rope");
+        assert_eq!(changes.apply_to_str("hello world"), "goodbye rope");
+
+        changes.invert(&original).apply(&mut buffer);
+        assert_eq!(buffer, original);
+    }
+
+    #[test]
+    fn test_compose_matches_sequential_apply() {
+        let text = "the quick brown fox";
+        let first = ChangeSet::from_edits(19, [(4..9, "slow"), (19..19, "!")]);
+        let middle = first.apply_to_str(text);
+        let second = ChangeSet::from_edits(middle.len(), [(0..3, "a"), (9..14, "red")]);
+        let expected = second.apply_to_str(&middle);
+
+        let composed = first.compose(&second);
+        assert_eq!(composed.apply_to_str(text), expected);
+        assert_eq!(composed.len_after(), expected.len());
+    }
+
+    #[test]
+    fn test_text_changes() {
+        let changes = ChangeSet::from_edits(11, [(0..5, "goodbye"), (6..6, "big "), (10..11, "")]);
+        assert_eq!(
+            changes.text_changes(),
+            [
+                TextChange {
+                    offset: 0,
+                    deleted: 5,
+                    inserted: "goodbye".to_string(),
+                },
+                TextChange {
+                    offset: 6,
+                    deleted: 0,
+                    inserted: "big ".to_string(),
+                },
+                TextChange {
+                    offset: 10,
+                    deleted: 1,
+                    inserted: String::new(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_map_position() {
+        // "abcdef" -> "aXYdef" by replacing "bc" with "XY" and inserting at 5
+        let changes = ChangeSet::from_edits(6, [(1..3, "XY"), (5..5, "__")]);
+
+        assert_eq!(changes.map_position(0, Assoc::After), 0);
+        assert_eq!(changes.map_position(2, Assoc::After), 3);
+        assert_eq!(changes.map_position(1, Assoc::Before), 1);
+        assert_eq!(changes.map_position(4, Assoc::After), 4);
+        assert_eq!(changes.map_position(5, Assoc::Before), 5);
+        assert_eq!(changes.map_position(5, Assoc::After), 7);
+        assert_eq!(changes.map_position(6, Assoc::After), 8);
+    }
+
+    #[test]
+    fn test_diff_and_touches() {
+        let changes = ChangeSet::diff("let x = 1;", "let y = 1;");
+        assert_eq!(changes, ChangeSet::replace(10, 4..5, "y"));
+        assert!(changes.touches(0..6));
+        assert!(!changes.touches(6..10));
+
+        // Repeated characters must not overlap the prefix and suffix
+        assert_eq!(ChangeSet::diff("aa", "aaa").apply_to_str("aa"), "aaa");
+        assert!(ChangeSet::diff("same", "same").is_empty());
+    }
+}
@@ -0,0 +1,290 @@
+//! Text transforms
+//!
+//! Case conversions and encodings for selected text, applied at every
+//! cursor by [`EditorState::transform_selections`](super::EditorState::transform_selections).
+
+use std::fmt::Write;
+
+/// A transformation of selected text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextTransform {
+    /// `UPPER CASE`
+    UpperCase,
+    /// `lower case`
+    LowerCase,
+    /// `Title Case`
+    TitleCase,
+    /// `snake_case`
+    SnakeCase,
+    /// `camelCase`
+    CamelCase,
+    /// `kebab-case`
+    KebabCase,
+    /// `PascalCase`
+    PascalCase,
+    /// Standard Base64 with padding
+    Base64Encode,
+    /// Decode standard Base64; padding is optional
+    Base64Decode,
+    /// Percent-encode everything but unreserved URL characters
+    UrlEncode,
+    /// Decode `%XX` escapes
+    UrlDecode,
+}
+
+impl TextTransform {
+    /// Every transform.
+    pub const ALL: [Self; 11] = [
+        Self::UpperCase,
+        Self::LowerCase,
+        Self::TitleCase,
+        Self::SnakeCase,
+        Self::CamelCase,
+        Self::KebabCase,
+        Self::PascalCase,
+        Self::Base64Encode,
+        Self::Base64Decode,
+        Self::UrlEncode,
+        Self::UrlDecode,
+    ];
+
+    /// Get the name of the built-in command for this transform.
+    #[must_use]
+    pub fn command_name(self) -> &'static str {
+        match self {
+            Self::UpperCase => "upper_case",
+            Self::LowerCase => "lower_case",
+            Self::TitleCase => "title_case",
+            Self::SnakeCase => "snake_case",
+            Self::CamelCase => "camel_case",
+            Self::KebabCase => "kebab_case",
+            Self::PascalCase => "pascal_case",
+            Self::Base64Encode => "base64_encode",
+            Self::Base64Decode => "base64_decode",
+            Self::UrlEncode => "url_encode",
+            Self::UrlDecode => "url_decode",
+        }
+    }
+
+    /// Transform `text`.
+    ///
+    /// The identifier cases (snake, camel, kebab and Pascal) convert each
+    /// line separately. Returns `None` if the text cannot be decoded.
+    #[must_use]
+    pub fn apply(self, text: &str) -> Option<String> {
+        let per_line = |convert: fn(&[String]) -> String| {
+            text.split('\n')
+                .map(|line| convert(&words(line)))
+                .collect::<Vec<_>>()
+                .join("\n")
+        };
+        Some(match self {
+            Self::UpperCase => text.to_uppercase(),
+            Self::LowerCase => text.to_lowercase(),
+            Self::TitleCase => title_case(text),
+            Self::SnakeCase => per_line(|words| words.join("_").to_lowercase()),
+            Self::KebabCase => per_line(|words| words.join("-").to_lowercase()),
+            Self::CamelCase => per_line(|words| {
+                let mut words = words.iter();
+                let first = words.next().map(|word| word.to_lowercase());
+                first
+                    .into_iter()
+                    .chain(words.map(|word| capitalize(word)))
+                    .collect()
+            }),
+            Self::PascalCase => {
+                per_line(|words| words.iter().map(|word| capitalize(word)).collect())
+            }
+            Self::Base64Encode => base64_encode(text.as_bytes()),
+            Self::Base64Decode => String::from_utf8(base64_decode(text.trim())?).ok()?,
+            Self::UrlEncode => url_encode(text),
+            Self::UrlDecode => String::from_utf8(url_decode(text)?).ok()?,
+        })
+    }
+}
+
+/// Split an identifier or phrase into words.
+///
+/// Words end at anything that is not a letter or digit, and before an
+/// uppercase letter that follows a lowercase letter or digit, or that
+/// starts a new word after an acronym (`HTTPServer` is `HTTP Server`).
+fn words(text: &str) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut words = Vec::new();
+    let mut word = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+        if !c.is_alphanumeric() {
+            if !word.is_empty() {
+                words.push(std::mem::take(&mut word));
+            }
+            continue;
+        }
+        let prev = i.checked_sub(1).map(|i| chars[i]);
+        let next = chars.get(i + 1);
+        let boundary = c.is_uppercase()
+            && prev.is_some_and(|prev| {
+                prev.is_lowercase()
+                    || prev.is_numeric()
+                    || (prev.is_uppercase() && next.is_some_and(|next| next.is_lowercase()))
+            });
+        if boundary && !word.is_empty() {
+            words.push(std::mem::take(&mut word));
+        }
+        word.push(c);
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+/// Uppercase the first letter of a word and lowercase the rest.
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    chars.next().map_or_else(String::new, |first| {
+        first
+            .to_uppercase()
+            .chain(chars.flat_map(char::to_lowercase))
+            .collect()
+    })
+}
+
+/// Capitalize every word, keeping the text between words.
+fn title_case(text: &str) -> String {
+    let mut title = String::with_capacity(text.len());
+    let mut in_word = false;
+    for c in text.chars() {
+        if in_word {
+            title.extend(c.to_lowercase());
+        } else {
+            title.extend(c.to_uppercase());
+        }
+        in_word = c.is_alphanumeric() || c == '\'';
+    }
+    title
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn base64_encode(bytes: &[u8]) -> String {
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, &byte)| n | u32::from(byte) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                let index = (n >> (18 - 6 * i)) & 0x3f;
+                encoded.push(char::from(BASE64_ALPHABET[index as usize]));
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+    let text = text.trim_end_matches('=');
+    let mut bytes = Vec::with_capacity(text.len() * 3 / 4);
+    let mut n = 0u32;
+    let mut bits = 0;
+    for c in text.bytes() {
+        let value = BASE64_ALPHABET.iter().position(|&a| a == c)?;
+        n = n << 6 | u32::try_from(value).ok()?;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push(u8::try_from(n >> bits).ok()?);
+            n &= (1 << bits) - 1;
+        }
+    }
+    // A single leftover character cannot hold a whole byte
+    (bits < 6).then_some(bytes)
+}
+
+fn url_encode(text: &str) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.' | b'~') {
+            encoded.push(char::from(byte));
+        } else {
+            let _ = write!(encoded, "%{byte:02X}");
+        }
+    }
+    encoded
+}
+
+fn url_decode(text: &str) -> Option<Vec<u8>> {
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut rest = text.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    Some(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_case_conversions() {
+        let apply = |transform: TextTransform, text| transform.apply(text).unwrap();
+        assert_eq!(
+            apply(TextTransform::SnakeCase, "parseHTTPResponse"),
+            "parse_http_response"
+        );
+        assert_eq!(
+            apply(TextTransform::CamelCase, "user-id field"),
+            "userIdField"
+        );
+        assert_eq!(
+            apply(TextTransform::KebabCase, "MyComponent2Name"),
+            "my-component2-name"
+        );
+        assert_eq!(
+            apply(TextTransform::PascalCase, "snake_case\nkebab-case"),
+            "SnakeCase\nKebabCase"
+        );
+        assert_eq!(
+            apply(TextTransform::TitleCase, "it's a TITLE"),
+            "It's A Title"
+        );
+    }
+
+    #[test]
+    fn test_encodings() {
+        for text in ["", "f", "fo", "foo", "héllo wörld"] {
+            let encoded = TextTransform::Base64Encode.apply(text).unwrap();
+            assert_eq!(
+                TextTransform::Base64Decode.apply(&encoded).as_deref(),
+                Some(text)
+            );
+        }
+        assert_eq!(
+            TextTransform::Base64Encode.apply("foob").as_deref(),
+            Some("Zm9vYg==")
+        );
+        assert_eq!(TextTransform::Base64Decode.apply("Zm9v!"), None);
+
+        assert_eq!(
+            TextTransform::UrlEncode.apply("a b/é").as_deref(),
+            Some("a%20b%2F%C3%A9")
+        );
+        assert_eq!(
+            TextTransform::UrlDecode.apply("a%20b%2F%C3%A9").as_deref(),
+            Some("a b/é")
+        );
+        assert_eq!(TextTransform::UrlDecode.apply("100%"), None);
+    }
+}
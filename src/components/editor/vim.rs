@@ -0,0 +1,2092 @@
+//! Vim emulation
+//!
+//! An opt-in modal editing layer. [`Vim`] keeps the mode, the keys of an
+//! unfinished command, the registers and the last change, and turns key
+//! presses into edits through the [`EditorState`] API, so undo and change
+//! tracking behave the same as for ordinary editing.
+//!
+//! Insert mode leaves typing to the editor: [`Vim::handle_key`] returns
+//! [`VimResponse::Ignored`] for every key except Escape.
+
+use std::{collections::HashMap, fmt, ops::Range};
+
+use super::{
+    brackets::{self, BRACKET_PAIRS},
+    buffer::TextBuffer,
+    cursor::{Cursor, CursorPosition, CursorSet},
+    find_replace::FindState,
+    keymap::KeyChord,
+    selection::is_word_char,
+    state::EditorState,
+    transaction::{ChangeSet, Transaction},
+};
+
+/// A Vim mode.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum VimMode {
+    /// Commands and motions
+    #[default]
+    Normal,
+    /// Typing text
+    Insert,
+    /// Charwise selection
+    Visual,
+    /// Linewise selection
+    VisualLine,
+    /// Rectangular selection
+    VisualBlock,
+    /// Typing a `:` command
+    CommandLine,
+}
+
+impl VimMode {
+    /// Get the name shown in the status bar.
+    #[must_use]
+    pub const fn label(self) -> &'static str {
+        match self {
+            Self::Normal => "NORMAL",
+            Self::Insert => "INSERT",
+            Self::Visual => "VISUAL",
+            Self::VisualLine => "VISUAL LINE",
+            Self::VisualBlock => "VISUAL BLOCK",
+            Self::CommandLine => "COMMAND",
+        }
+    }
+
+    /// Check if this is one of the visual modes.
+    #[must_use]
+    pub const fn is_visual(self) -> bool {
+        matches!(self, Self::Visual | Self::VisualLine | Self::VisualBlock)
+    }
+}
+
+/// A key press as Vim sees it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VimKey {
+    /// A printable character, with Shift already applied
+    Char(char),
+    /// Ctrl and a character, e.g. `<C-r>`
+    Ctrl(char),
+    /// Escape (or `<C-[>`)
+    Escape,
+    /// Enter
+    Enter,
+    /// Backspace
+    Backspace,
+    /// Left arrow
+    Left,
+    /// Right arrow
+    Right,
+    /// Up arrow
+    Up,
+    /// Down arrow
+    Down,
+    /// Any other key; Vim leaves it to the editor
+    Other,
+}
+
+impl VimKey {
+    /// Get the Vim key for a chord.
+    #[must_use]
+    pub fn from_chord(chord: &KeyChord) -> Self {
+        let mut chars = chord.key.chars();
+        let single = match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(c),
+            _ => None,
+        };
+        match (chord.key.as_str(), single) {
+            _ if chord.meta || chord.alt => Self::Other,
+            ("Escape", _) | (_, Some('[')) if chord.ctrl || chord.key == "Escape" => Self::Escape,
+            ("Enter", _) => Self::Enter,
+            ("Backspace", _) => Self::Backspace,
+            ("ArrowLeft", _) => Self::Left,
+            ("ArrowRight", _) => Self::Right,
+            ("ArrowUp", _) => Self::Up,
+            ("ArrowDown", _) => Self::Down,
+            (_, Some(c)) if chord.ctrl => Self::Ctrl(c),
+            (_, Some(c)) if chord.shift => Self::Char(c.to_uppercase().next().unwrap_or(c)),
+            (_, Some(c)) => Self::Char(c),
+            _ => Self::Other,
+        }
+    }
+
+    /// Parse keys in Vim notation, such as `"dw"` or `"ihello<Esc>"`.
+    ///
+    /// Recognizes `<Esc>`, `<CR>`, `<BS>`, `<Left>`, `<Right>`, `<Up>`,
+    /// `<Down>`, `<lt>` and `<C-x>`; any other `<` is a literal character.
+    #[must_use]
+    pub fn parse_keys(text: &str) -> Vec<Self> {
+        let mut keys = Vec::new();
+        let mut rest = text;
+        while let Some(c) = rest.chars().next() {
+            let special = (c == '<')
+                .then(|| rest.find('>'))
+                .flatten()
+                .and_then(|end| Some((Self::parse_special(&rest[1..end])?, end + 1)));
+            if let Some((key, len)) = special {
+                keys.push(key);
+                rest = &rest[len..];
+            } else {
+                keys.push(Self::Char(c));
+                rest = &rest[c.len_utf8()..];
+            }
+        }
+        keys
+    }
+
+    /// Parse the name between `<` and `>`.
+    fn parse_special(name: &str) -> Option<Self> {
+        Some(match name.to_ascii_lowercase().as_str() {
+            "esc" => Self::Escape,
+            "cr" | "enter" => Self::Enter,
+            "bs" => Self::Backspace,
+            "left" => Self::Left,
+            "right" => Self::Right,
+            "up" => Self::Up,
+            "down" => Self::Down,
+            "lt" => Self::Char('<'),
+            other => {
+                let key = other.strip_prefix("c-")?;
+                let mut chars = key.chars();
+                match (chars.next(), chars.next()) {
+                    (Some('['), None) => Self::Escape,
+                    (Some(c), None) => Self::Ctrl(c),
+                    _ => return None,
+                }
+            }
+        })
+    }
+}
+
+impl fmt::Display for VimKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Char('<') => f.write_str("<lt>"),
+            Self::Char(c) => write!(f, "{c}"),
+            Self::Ctrl(c) => write!(f, "<C-{c}>"),
+            Self::Escape => f.write_str("<Esc>"),
+            Self::Enter => f.write_str("<CR>"),
+            Self::Backspace => f.write_str("<BS>"),
+            Self::Left => f.write_str("<Left>"),
+            Self::Right => f.write_str("<Right>"),
+            Self::Up => f.write_str("<Up>"),
+            Self::Down => f.write_str("<Down>"),
+            Self::Other => Ok(()),
+        }
+    }
+}
+
+/// What the editor should do after [`Vim::handle_key`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VimResponse {
+    /// Vim used the key
+    Handled,
+    /// Vim does not use the key; the editor should handle it as usual
+    Ignored,
+    /// Vim used the key and the document should be saved (`:w`)
+    Save,
+}
+
+/// How the text in a register was cut or copied.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RegisterKind {
+    /// Part of a line or lines; pasted at the cursor
+    #[default]
+    Char,
+    /// Whole lines; pasted above or below the current line
+    Line,
+    /// A rectangular block; pasted as a block
+    Block,
+}
+
+/// The contents of a register.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Register {
+    /// The text; linewise text ends with a line break
+    pub text: String,
+    /// How the text was cut or copied
+    pub kind: RegisterKind,
+}
+
+/// The Vim state of one editor.
+#[derive(Debug, Clone, Default)]
+pub struct Vim {
+    /// The current mode
+    mode: VimMode,
+    /// Keys of an unfinished command
+    pending: Vec<VimKey>,
+    /// The command line being typed, without the `:`
+    command_line: String,
+    /// A message for the status bar, cleared by the next key
+    message: Option<String>,
+    /// Named and unnamed registers
+    registers: HashMap<char, Register>,
+    /// The last `f`, `F`, `t` or `T`
+    last_find: Option<Find>,
+    /// The last pattern used by `:s`
+    last_pattern: Option<String>,
+    /// The last change, for `.`
+    last_change: Option<Change>,
+    /// The change that started the current insert
+    recording: Option<Change>,
+    /// Byte offset where the current insert started
+    insert_start: usize,
+    /// How many times the current insert is repeated
+    insert_count: usize,
+    /// The visual selection, with inclusive ends
+    visual: Cursor,
+    /// The lines of the last visual selection, for `'<,'>`
+    last_visual: Option<(usize, usize)>,
+}
+
+/// A change that `.` repeats.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Change {
+    /// The normal-mode keys of the command
+    keys: Vec<VimKey>,
+    /// Text typed in the insert mode the command started
+    inserted: Option<String>,
+}
+
+/// The result of parsing keys typed so far.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Parse<T> {
+    /// More keys are needed
+    Incomplete,
+    /// The keys are not a command
+    Invalid,
+    /// A complete command
+    Done(T),
+}
+
+impl<T> Parse<T> {
+    fn map<U>(self, f: impl FnOnce(T) -> U) -> Parse<U> {
+        match self {
+            Self::Incomplete => Parse::Incomplete,
+            Self::Invalid => Parse::Invalid,
+            Self::Done(value) => Parse::Done(f(value)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Delete,
+    Change,
+    Yank,
+}
+
+/// A character search on the current line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Find {
+    target: char,
+    forward: bool,
+    till: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    WordStart { big: bool },
+    WordBack { big: bool },
+    WordEnd { big: bool },
+    LineStart,
+    FirstNonBlank,
+    LineEnd,
+    FirstLine,
+    LastLine,
+    Find(Find),
+    RepeatFind { reverse: bool },
+    MatchPair,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TextObject {
+    Word {
+        around: bool,
+        big: bool,
+    },
+    Pair {
+        open: char,
+        close: char,
+        around: bool,
+    },
+    Quote {
+        quote: char,
+        around: bool,
+    },
+}
+
+/// What an operator applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Target {
+    Motion(Motion),
+    Object(TextObject),
+    /// Whole lines (`dd`, `cc`, `yy`)
+    Lines,
+    /// The visual selection
+    Selection,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InsertAt {
+    Cursor,
+    After,
+    LineStart,
+    LineEnd,
+    LineBelow,
+    LineAbove,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Move(Motion),
+    Operate(Operator, Target),
+    SelectObject(TextObject),
+    Insert(InsertAt),
+    BlockInsert { append: bool },
+    Visual(VimMode),
+    SwapEnds,
+    Put { before: bool },
+    DeleteChar { before: bool },
+    Replace(char),
+    Join,
+    Undo,
+    Redo,
+    Repeat,
+    CommandLine,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Command {
+    register: Option<char>,
+    count: Option<usize>,
+    action: Action,
+}
+
+/// How a motion's range is bounded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Span {
+    Exclusive,
+    Inclusive,
+    Linewise,
+}
+
+/// The text an operator works on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Region {
+    /// A byte range
+    Chars(Range<usize>),
+    /// A range of whole lines (inclusive)
+    Lines(usize, usize),
+}
+
+impl Vim {
+    /// Create a Vim state in normal mode.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Get the current mode.
+    #[must_use]
+    pub fn mode(&self) -> VimMode {
+        self.mode
+    }
+
+    /// Get a register by name; `'"'` is the unnamed register.
+    #[must_use]
+    pub fn register(&self, name: char) -> Option<&Register> {
+        self.registers.get(&name.to_ascii_lowercase())
+    }
+
+    /// Get the text for the status bar: the mode and any pending keys, the
+    /// command line being typed, or a message.
+    #[must_use]
+    pub fn status(&self) -> String {
+        if self.mode == VimMode::CommandLine {
+            return format!(":{}", self.command_line);
+        }
+        if let Some(message) = &self.message {
+            return message.clone();
+        }
+        let mut status = format!("-- {} --", self.mode.label());
+        if !self.pending.is_empty() {
+            status.push(' ');
+            status.extend(self.pending.iter().map(ToString::to_string));
+        }
+        status
+    }
+
+    /// Handle a key press.
+    pub fn handle_key(&mut self, key: VimKey, state: &mut EditorState) -> VimResponse {
+        self.message = None;
+        match self.mode {
+            VimMode::Insert if key == VimKey::Escape => {
+                self.finish_insert(state);
+                VimResponse::Handled
+            }
+            VimMode::Insert => VimResponse::Ignored,
+            VimMode::CommandLine => self.command_line_key(key, state),
+            _ => self.normal_key(key, state),
+        }
+    }
+
+    /// Handle a key in normal or visual mode.
+    fn normal_key(&mut self, key: VimKey, state: &mut EditorState) -> VimResponse {
+        match key {
+            VimKey::Escape => {
+                self.pending.clear();
+                if self.mode.is_visual() {
+                    self.leave_visual(state);
+                } else {
+                    clamp_normal(state);
+                }
+                return VimResponse::Handled;
+            }
+            VimKey::Ctrl(c) if !matches!(c, 'v' | 'r') => return VimResponse::Ignored,
+            VimKey::Other => return VimResponse::Ignored,
+            _ => {}
+        }
+
+        self.pending.push(key);
+        match parse_command(&self.pending, self.mode) {
+            Parse::Incomplete => {}
+            Parse::Invalid => self.pending.clear(),
+            Parse::Done(command) => {
+                let keys = std::mem::take(&mut self.pending);
+                self.execute(command, keys, state);
+            }
+        }
+        VimResponse::Handled
+    }
+
+    /// Run a parsed normal or visual mode command.
+    fn execute(&mut self, command: Command, keys: Vec<VimKey>, state: &mut EditorState) {
+        let count = command.count.unwrap_or(1);
+        let register = command.register;
+        let visual = self.mode.is_visual();
+        self.record(command.action, keys, state);
+
+        match command.action {
+            Action::Move(motion) => self.move_cursor(state, motion, command.count),
+            Action::Operate(op, Target::Selection) => self.operate_visual(state, op, register),
+            Action::Operate(op, target) => {
+                if let Some(region) = self.target_region(state, op, target, command.count) {
+                    self.operate(state, op, register, region);
+                }
+            }
+            Action::SelectObject(object) => {
+                let head = self.visual.head;
+                if let Some((start, end)) = text_object(&state.buffer, head, object) {
+                    let end = position_of(
+                        &state.buffer,
+                        state
+                            .buffer
+                            .prev_char_boundary(offset_of(&state.buffer, end)),
+                    );
+                    self.visual = Cursor::with_selection(end, start);
+                }
+            }
+            Action::Insert(at) => self.insert_at(state, at, count),
+            Action::BlockInsert { append } => {
+                if let Some(block) = state.active_block() {
+                    let columns = block.columns();
+                    let column = if append { columns.end } else { columns.start };
+                    let block = block.collapsed_at(column);
+                    state.select_block(block.anchor, block.head);
+                }
+                self.recording = None;
+                self.begin_insert(state);
+            }
+            Action::Visual(mode) => self.toggle_visual(state, mode),
+            Action::SwapEnds => {
+                let Cursor { head, anchor, .. } = self.visual;
+                self.visual = Cursor::with_selection(anchor, head);
+            }
+            Action::Put { before } => self.put(state, register, before, count),
+            Action::DeleteChar { before } => {
+                let head = state.cursor_position();
+                let len = line_len(&state.buffer, head.line);
+                let (start, end) = if before {
+                    (head.column.saturating_sub(count), head.column)
+                } else {
+                    (head.column, (head.column + count).min(len))
+                };
+                if start < end {
+                    let range = offset_of(&state.buffer, CursorPosition::new(head.line, start))
+                        ..offset_of(&state.buffer, CursorPosition::new(head.line, end));
+                    self.operate(state, Operator::Delete, register, Region::Chars(range));
+                }
+            }
+            Action::Replace(c) => replace_chars(state, c, count),
+            Action::Join => join_lines(state, count.max(2)),
+            Action::Undo => {
+                for _ in 0..count {
+                    if !state.undo() {
+                        break;
+                    }
+                }
+            }
+            Action::Redo => {
+                for _ in 0..count {
+                    if !state.redo() {
+                        break;
+                    }
+                }
+            }
+            Action::Repeat => self.repeat(state, count),
+            Action::CommandLine => {
+                self.command_line.clear();
+                if visual {
+                    self.leave_visual(state);
+                    self.command_line.push_str("'<,'>");
+                }
+                self.mode = VimMode::CommandLine;
+            }
+        }
+
+        if self.mode == VimMode::Normal {
+            clamp_normal(state);
+        } else if self.mode.is_visual() {
+            self.show_visual(state);
+        }
+    }
+
+    /// Start a new undo step for a command that changes the document, and
+    /// remember it for `.`.
+    ///
+    /// A command that enters insert mode is only complete once insert mode
+    /// ends, so it is kept in `recording` until then.
+    fn record(&mut self, action: Action, keys: Vec<VimKey>, state: &mut EditorState) {
+        let changes = match action {
+            Action::Operate(op, _) => op != Operator::Yank,
+            Action::Insert(_)
+            | Action::BlockInsert { .. }
+            | Action::Put { .. }
+            | Action::DeleteChar { .. }
+            | Action::Replace(_)
+            | Action::Join => true,
+            _ => false,
+        };
+        if !changes {
+            return;
+        }
+        state.history.checkpoint();
+        if self.mode.is_visual() {
+            return;
+        }
+        let change = Change {
+            keys,
+            inserted: None,
+        };
+        if matches!(
+            action,
+            Action::Insert(_) | Action::Operate(Operator::Change, _)
+        ) {
+            self.recording = Some(change);
+        } else {
+            self.last_change = Some(change);
+        }
+    }
+
+    /// Move the cursor, or the head of the visual selection.
+    fn move_cursor(&mut self, state: &mut EditorState, motion: Motion, count: Option<usize>) {
+        let cursor = if self.mode.is_visual() {
+            self.visual
+        } else {
+            *state.cursors.primary()
+        };
+        let Some((target, _)) = self.motion_target(state, &cursor, motion, count) else {
+            return;
+        };
+        let moved = moved_cursor(&cursor, target, motion);
+        if self.mode.is_visual() {
+            self.visual.head = moved.head;
+            self.visual.preferred_column = moved.preferred_column;
+        } else {
+            state.cursors = CursorSet::new(moved);
+        }
+    }
+
+    /// Repeat the last change `count` times.
+    fn repeat(&mut self, state: &mut EditorState, count: usize) {
+        let Some(change) = self.last_change.clone() else {
+            return;
+        };
+        for _ in 0..count {
+            self.pending.clear();
+            for &key in &change.keys {
+                self.normal_key(key, state);
+            }
+            if self.mode == VimMode::Insert {
+                if let Some(text) = &change.inserted {
+                    state.insert(text);
+                }
+                self.finish_insert(state);
+            }
+        }
+    }
+
+    /// Handle a key while typing a `:` command.
+    fn command_line_key(&mut self, key: VimKey, state: &mut EditorState) -> VimResponse {
+        match key {
+            VimKey::Enter => return self.run_command_line(state),
+            VimKey::Escape => self.leave_command_line(state),
+            VimKey::Backspace if self.command_line.pop().is_none() => {
+                self.leave_command_line(state);
+            }
+            VimKey::Char(c) => self.command_line.push(c),
+            _ => {}
+        }
+        VimResponse::Handled
+    }
+
+    fn leave_command_line(&mut self, state: &mut EditorState) {
+        self.command_line.clear();
+        self.mode = VimMode::Normal;
+        clamp_normal(state);
+    }
+
+    /// Run the command line: `:w`, `:{line}` or `:[range]s/pattern/replacement/[flags]`.
+    fn run_command_line(&mut self, state: &mut EditorState) -> VimResponse {
+        let line = std::mem::take(&mut self.command_line);
+        self.leave_command_line(state);
+
+        let current = state.cursor_position().line;
+        let last = state.line_count() - 1;
+        let (range, command) = parse_range(line.trim(), current, last, self.last_visual);
+        match command.trim() {
+            "" => {
+                if let Some((_, line)) = range {
+                    place(state, first_non_blank(&state.buffer, line));
+                    clamp_normal(state);
+                }
+            }
+            "w" | "write" | "wq" | "x" | "up" | "update" => return VimResponse::Save,
+            command
+                if command.starts_with('s') && !command[1..].starts_with(char::is_alphanumeric) =>
+            {
+                self.substitute(state, range.unwrap_or((current, current)), &command[1..]);
+            }
+            command => self.message = Some(format!("Not an editor command: {command}")),
+        }
+        VimResponse::Handled
+    }
+
+    /// Run `:s` on a range of lines, using [`FindState`] for the matching.
+    fn substitute(&mut self, state: &mut EditorState, (first, last): (usize, usize), args: &str) {
+        let Some(delimiter) = args.chars().next() else {
+            self.message = Some("Missing pattern".to_string());
+            return;
+        };
+        let parts = split_unescaped(&args[delimiter.len_utf8()..], delimiter);
+        let Some(pattern) = parts
+            .first()
+            .filter(|pattern| !pattern.is_empty())
+            .or(self.last_pattern.as_ref())
+            .cloned()
+        else {
+            self.message = Some("No previous pattern".to_string());
+            return;
+        };
+        let flags = parts.get(2).map_or("", String::as_str);
+
+        let mut find = FindState::new();
+        find.query.clone_from(&pattern);
+        find.replacement = parts.get(1).cloned().unwrap_or_default();
+        find.options.use_regex = true;
+        find.options.case_sensitive = !flags.contains('i');
+        find.search(&state.content());
+        self.last_pattern = Some(pattern.clone());
+
+        let buffer = &state.buffer;
+        let start = buffer.line_start(first).unwrap_or(0);
+        let end = buffer
+            .line_range(last)
+            .map_or(buffer.len(), |range| range.end);
+        let mut last_line = None;
+        let mut edits = Vec::new();
+        for m in &find.matches {
+            if m.start < start || m.end > end {
+                continue;
+            }
+            let line = buffer.line_of_offset(m.start);
+            if !flags.contains('g') && last_line == Some(line) {
+                continue;
+            }
+            edits.push((m.start..m.end, find.replacement.as_str()));
+            last_line = Some(line);
+        }
+
+        let Some(line) = last_line else {
+            self.message = Some(format!("Pattern not found: {pattern}"));
+            return;
+        };
+        state.history.checkpoint();
+        let changes = ChangeSet::from_edits(buffer.len(), edits);
+        if state.apply(&Transaction::new(changes)) {
+            place(state, first_non_blank(&state.buffer, line));
+            clamp_normal(state);
+        }
+    }
+
+    /// Find where a motion goes from a cursor.
+    ///
+    /// Returns `None` if the motion fails, e.g. `f` without a match.
+    fn motion_target(
+        &mut self,
+        state: &EditorState,
+        cursor: &Cursor,
+        motion: Motion,
+        count: Option<usize>,
+    ) -> Option<(CursorPosition, Span)> {
+        let buffer = &state.buffer;
+        let head = cursor.head;
+        let n = count.unwrap_or(1);
+        let last = buffer.line_count() - 1;
+        let target = match motion {
+            Motion::Left => {
+                let column = head.column.checked_sub(1)?;
+                (
+                    CursorPosition::new(head.line, column.saturating_sub(n - 1)),
+                    Span::Exclusive,
+                )
+            }
+            Motion::Right => {
+                let column = (head.column + n).min(line_len(buffer, head.line));
+                if column == head.column {
+                    return None;
+                }
+                (CursorPosition::new(head.line, column), Span::Exclusive)
+            }
+            Motion::Up | Motion::Down => {
+                let line = if motion == Motion::Up {
+                    head.line.checked_sub(1)?.saturating_sub(n - 1)
+                } else if head.line < last {
+                    (head.line + n).min(last)
+                } else {
+                    return None;
+                };
+                let column = cursor
+                    .preferred_column
+                    .unwrap_or(head.column)
+                    .min(line_len(buffer, line));
+                (CursorPosition::new(line, column), Span::Linewise)
+            }
+            Motion::WordStart { big } => {
+                let mut walker = Walker::new(buffer, head);
+                (0..n).for_each(|_| word_forward(&mut walker, big));
+                (walker.position(), Span::Exclusive)
+            }
+            Motion::WordBack { big } => {
+                let mut walker = Walker::new(buffer, head);
+                (0..n).for_each(|_| word_backward(&mut walker, big));
+                (walker.position(), Span::Exclusive)
+            }
+            Motion::WordEnd { big } => {
+                let mut walker = Walker::new(buffer, head);
+                (0..n).for_each(|_| word_end(&mut walker, big));
+                (walker.position(), Span::Inclusive)
+            }
+            Motion::LineStart => (CursorPosition::new(head.line, 0), Span::Exclusive),
+            Motion::FirstNonBlank => (first_non_blank(buffer, head.line), Span::Exclusive),
+            Motion::LineEnd => {
+                let line = (head.line + n - 1).min(last);
+                let column = line_len(buffer, line).saturating_sub(1);
+                (CursorPosition::new(line, column), Span::Inclusive)
+            }
+            Motion::FirstLine | Motion::LastLine => {
+                let default = if motion == Motion::FirstLine { 0 } else { last };
+                let line = count.map_or(default, |n| n.saturating_sub(1).min(last));
+                (first_non_blank(buffer, line), Span::Linewise)
+            }
+            Motion::Find(find) => {
+                self.last_find = Some(find);
+                find_char(buffer, head, find, n, false)?
+            }
+            Motion::RepeatFind { reverse } => {
+                let mut find = self.last_find?;
+                find.forward ^= reverse;
+                find_char(buffer, head, find, n, true)?
+            }
+            Motion::MatchPair => (match_pair(state, head)?, Span::Inclusive),
+        };
+        Some(target)
+    }
+
+    /// Get the region an operator applies to in normal mode.
+    fn target_region(
+        &mut self,
+        state: &EditorState,
+        op: Operator,
+        target: Target,
+        count: Option<usize>,
+    ) -> Option<Region> {
+        let buffer = &state.buffer;
+        let cursor = *state.cursors.primary();
+        let head = cursor.head;
+        let n = count.unwrap_or(1);
+
+        let (target, span) = match target {
+            Target::Lines => {
+                let last = (head.line + n - 1).min(buffer.line_count() - 1);
+                return Some(Region::Lines(head.line, last));
+            }
+            Target::Object(object) => {
+                let (start, end) = text_object(buffer, head, object)?;
+                return Some(Region::Chars(
+                    offset_of(buffer, start)..offset_of(buffer, end),
+                ));
+            }
+            Target::Selection => return None,
+            // `cw` on a word changes to the end of the word, like `ce`
+            Target::Motion(Motion::WordStart { big })
+                if op == Operator::Change && !Walker::new(buffer, head).char().is_whitespace() =>
+            {
+                let mut walker = Walker::new(buffer, head);
+                for i in 0..n {
+                    if i == 0 {
+                        let class = char_class(walker.char(), big);
+                        while walker
+                            .peek_next()
+                            .is_some_and(|c| char_class(c, big) == class)
+                        {
+                            walker.next();
+                        }
+                    } else {
+                        word_end(&mut walker, big);
+                    }
+                }
+                (walker.position(), Span::Inclusive)
+            }
+            Target::Motion(motion) => {
+                let (mut target, span) = self.motion_target(state, &cursor, motion, count)?;
+                // `dw` on the last word of a line stops at the line end
+                if matches!(motion, Motion::WordStart { .. }) && target.line > head.line {
+                    let end =
+                        CursorPosition::new(target.line - 1, line_len(buffer, target.line - 1));
+                    if end > head {
+                        target = end;
+                    }
+                }
+                (target, span)
+            }
+        };
+
+        let (start, end) = (head.min(target), head.max(target));
+        Some(match span {
+            Span::Linewise => Region::Lines(start.line, end.line),
+            Span::Exclusive => Region::Chars(offset_of(buffer, start)..offset_of(buffer, end)),
+            Span::Inclusive => {
+                let column = (end.column + 1).min(line_len(buffer, end.line));
+                Region::Chars(
+                    offset_of(buffer, start)
+                        ..offset_of(buffer, CursorPosition::new(end.line, column)),
+                )
+            }
+        })
+    }
+
+    /// Apply an operator to a region.
+    fn operate(
+        &mut self,
+        state: &mut EditorState,
+        op: Operator,
+        register: Option<char>,
+        region: Region,
+    ) {
+        let head = state.cursor_position();
+        match region {
+            Region::Chars(range) => {
+                let text = state.buffer.slice(range.clone()).unwrap_or_default();
+                self.store(register, text, RegisterKind::Char, op == Operator::Yank);
+                let start = position_of(&state.buffer, range.start);
+                if op != Operator::Yank {
+                    replace(state, range, "");
+                }
+                place(state, start);
+                if op == Operator::Change {
+                    self.begin_insert(state);
+                }
+            }
+            Region::Lines(first, last) => {
+                let buffer = &state.buffer;
+                let start = buffer.line_start(first).unwrap_or(0);
+                let next = buffer.line_start(last + 1);
+                let mut text = buffer
+                    .slice(start..next.unwrap_or(buffer.len()))
+                    .unwrap_or_default();
+                if next.is_none() {
+                    text.push('\n');
+                }
+                self.store(register, text, RegisterKind::Line, op == Operator::Yank);
+
+                match op {
+                    Operator::Yank => {
+                        if first < head.line {
+                            place(state, CursorPosition::new(first, head.column));
+                        }
+                    }
+                    Operator::Delete => {
+                        let range = match next {
+                            Some(next) => start..next,
+                            // Deleting the last lines also removes the line
+                            // break before them
+                            None => start.saturating_sub(1)..buffer.len(),
+                        };
+                        replace(state, range, "");
+                        let line = first.min(state.line_count() - 1);
+                        place(state, first_non_blank(&state.buffer, line));
+                    }
+                    Operator::Change => {
+                        let indent = if state.config.auto_indent {
+                            indent_of(buffer, first)
+                        } else {
+                            String::new()
+                        };
+                        let end = buffer.line_range(last).map_or(buffer.len(), |r| r.end);
+                        replace(state, start..end, &indent);
+                        place(state, CursorPosition::new(first, indent.chars().count()));
+                        self.begin_insert(state);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Apply an operator to the visual selection and leave visual mode.
+    fn operate_visual(&mut self, state: &mut EditorState, op: Operator, register: Option<char>) {
+        let mode = self.mode;
+        self.leave_visual(state);
+        let (start, end) = (
+            self.visual.head.min(self.visual.anchor),
+            self.visual.head.max(self.visual.anchor),
+        );
+        match mode {
+            VimMode::VisualLine => {
+                self.operate(state, op, register, Region::Lines(start.line, end.line));
+            }
+            VimMode::VisualBlock => {
+                self.show_visual_block(state);
+                if let Some(text) = state.block_text() {
+                    self.store(register, text, RegisterKind::Block, op == Operator::Yank);
+                }
+                let top_left = CursorPosition::new(
+                    start.line,
+                    self.visual.head.column.min(self.visual.anchor.column),
+                );
+                match op {
+                    Operator::Yank => place(state, top_left),
+                    Operator::Delete => {
+                        state.delete_backward();
+                        place(state, top_left);
+                    }
+                    Operator::Change => {
+                        state.delete_backward();
+                        self.recording = None;
+                        self.begin_insert(state);
+                    }
+                }
+            }
+            _ => {
+                let buffer = &state.buffer;
+                let end = buffer.next_char_boundary(offset_of(buffer, end));
+                self.operate(
+                    state,
+                    op,
+                    register,
+                    Region::Chars(offset_of(buffer, start)..end),
+                );
+            }
+        }
+        if op == Operator::Change {
+            self.recording = None;
+        }
+    }
+
+    /// Store cut or copied text in a register and the unnamed register.
+    ///
+    /// An uppercase register name appends to the lowercase register, and
+    /// `_` discards the text.
+    fn store(&mut self, name: Option<char>, text: String, kind: RegisterKind, yank: bool) {
+        let mut register = Register { text, kind };
+        match name {
+            Some('_') => return,
+            Some(c) if c.is_ascii_uppercase() => {
+                let entry = self.registers.entry(c.to_ascii_lowercase()).or_default();
+                entry.text.push_str(&register.text);
+                if kind == RegisterKind::Line {
+                    entry.kind = kind;
+                }
+                register = entry.clone();
+            }
+            Some(c) if c != '"' => {
+                self.registers.insert(c, register.clone());
+            }
+            _ if yank => {
+                self.registers.insert('0', register.clone());
+            }
+            _ => {}
+        }
+        self.registers.insert('"', register);
+    }
+
+    /// Paste a register after (`p`) or before (`P`) the cursor.
+    fn put(&mut self, state: &mut EditorState, register: Option<char>, before: bool, count: usize) {
+        let name = register.unwrap_or('"').to_ascii_lowercase();
+        let Some(register) = self.registers.get(&name).cloned() else {
+            self.message = Some(format!("Nothing in register {name}"));
+            return;
+        };
+        let head = state.cursor_position();
+        let buffer = &state.buffer;
+        match register.kind {
+            RegisterKind::Line => {
+                let text = register.text.repeat(count);
+                let (offset, text, line) = if before {
+                    (buffer.line_start(head.line).unwrap_or(0), text, head.line)
+                } else if let Some(next) = buffer.line_start(head.line + 1) {
+                    (next, text, head.line + 1)
+                } else {
+                    let text = format!("\n{}", text.strip_suffix('\n').unwrap_or(&text));
+                    (buffer.len(), text, head.line + 1)
+                };
+                replace(state, offset..offset, &text);
+                place(state, first_non_blank(&state.buffer, line));
+            }
+            RegisterKind::Char => {
+                let text = register.text.repeat(count);
+                let column = if before {
+                    head.column
+                } else {
+                    (head.column + 1).min(line_len(buffer, head.line))
+                };
+                let offset = offset_of(buffer, CursorPosition::new(head.line, column));
+                replace(state, offset..offset, &text);
+                let end = state.buffer.prev_char_boundary(offset + text.len());
+                place(state, position_of(&state.buffer, end));
+            }
+            RegisterKind::Block => {
+                let column = head.column + usize::from(!before && line_len(buffer, head.line) > 0);
+                let at = CursorPosition::new(head.line, column);
+                state.cursors = CursorSet::new(Cursor::new(at));
+                state.paste_block(&register.text);
+                place(state, at);
+            }
+        }
+    }
+
+    /// Enter insert mode from `i`, `a`, `I`, `A`, `o` or `O`.
+    fn insert_at(&mut self, state: &mut EditorState, at: InsertAt, count: usize) {
+        let head = state.cursor_position();
+        let buffer = &state.buffer;
+        let len = line_len(buffer, head.line);
+        let indent = if state.config.auto_indent {
+            indent_of(buffer, head.line)
+        } else {
+            String::new()
+        };
+        let indent_len = indent.chars().count();
+        match at {
+            InsertAt::Cursor => {}
+            InsertAt::After => place(
+                state,
+                CursorPosition::new(head.line, (head.column + 1).min(len)),
+            ),
+            InsertAt::LineStart => place(state, first_non_blank(buffer, head.line)),
+            InsertAt::LineEnd => place(state, CursorPosition::new(head.line, len)),
+            InsertAt::LineBelow => {
+                let end = buffer.line_range(head.line).map_or(buffer.len(), |r| r.end);
+                replace(state, end..end, &format!("\n{indent}"));
+                place(state, CursorPosition::new(head.line + 1, indent_len));
+            }
+            InsertAt::LineAbove => {
+                let start = buffer.line_start(head.line).unwrap_or(0);
+                replace(state, start..start, &format!("{indent}\n"));
+                place(state, CursorPosition::new(head.line, indent_len));
+            }
+        }
+        self.insert_count = if matches!(at, InsertAt::LineBelow | InsertAt::LineAbove) {
+            1
+        } else {
+            count
+        };
+        self.begin_insert(state);
+    }
+
+    fn begin_insert(&mut self, state: &EditorState) {
+        self.mode = VimMode::Insert;
+        self.insert_start = offset_of(&state.buffer, state.cursor_position());
+    }
+
+    /// Leave insert mode, repeating the typed text for a count and
+    /// recording it for `.`.
+    fn finish_insert(&mut self, state: &mut EditorState) {
+        let end = offset_of(&state.buffer, state.cursor_position());
+        let inserted = if end >= self.insert_start {
+            state
+                .buffer
+                .slice(self.insert_start..end)
+                .unwrap_or_default()
+        } else {
+            String::new()
+        };
+        if self.insert_count > 1 && !inserted.is_empty() {
+            state.insert(&inserted.repeat(self.insert_count - 1));
+        }
+        self.insert_count = 1;
+        if let Some(mut change) = self.recording.take() {
+            change.inserted = Some(inserted);
+            self.last_change = Some(change);
+        }
+
+        self.mode = VimMode::Normal;
+        let head = state.cursor_position();
+        place(
+            state,
+            CursorPosition::new(head.line, head.column.saturating_sub(1)),
+        );
+        clamp_normal(state);
+    }
+
+    /// Enter a visual mode, switch between them, or leave it if already in
+    /// that mode.
+    fn toggle_visual(&mut self, state: &mut EditorState, mode: VimMode) {
+        if self.mode == mode {
+            self.leave_visual(state);
+            return;
+        }
+        if !self.mode.is_visual() {
+            self.visual = Cursor::new(state.cursor_position());
+        }
+        self.mode = mode;
+    }
+
+    fn leave_visual(&mut self, state: &mut EditorState) {
+        let Cursor { head, anchor, .. } = self.visual;
+        self.last_visual = Some((head.line.min(anchor.line), head.line.max(anchor.line)));
+        self.mode = VimMode::Normal;
+        place(state, head);
+        state.block_selection = None;
+    }
+
+    /// Show the visual selection in the editor state.
+    ///
+    /// Visual selections include the character under the cursor, while
+    /// editor selections end before it.
+    fn show_visual(&self, state: &mut EditorState) {
+        let Cursor { head, anchor, .. } = self.visual;
+        let buffer = &state.buffer;
+        let (start, end) = match self.mode {
+            VimMode::Visual => {
+                let start = offset_of(buffer, head.min(anchor));
+                let end = buffer.next_char_boundary(offset_of(buffer, head.max(anchor)));
+                (position_of(buffer, start), position_of(buffer, end))
+            }
+            VimMode::VisualLine => {
+                let last = head.line.max(anchor.line);
+                let end = buffer.line_start(last + 1).unwrap_or(buffer.len());
+                (
+                    CursorPosition::new(head.line.min(anchor.line), 0),
+                    position_of(buffer, end),
+                )
+            }
+            VimMode::VisualBlock => {
+                self.show_visual_block(state);
+                return;
+            }
+            _ => return,
+        };
+        let cursor = if head >= anchor {
+            Cursor::with_selection(end, start)
+        } else {
+            Cursor::with_selection(start, end)
+        };
+        state.cursors = CursorSet::new(cursor);
+    }
+
+    fn show_visual_block(&self, state: &mut EditorState) {
+        let Cursor {
+            mut head,
+            mut anchor,
+            ..
+        } = self.visual;
+        if head.column >= anchor.column {
+            head.column += 1;
+        } else {
+            anchor.column += 1;
+        }
+        state.select_block(anchor, head);
+    }
+}
+
+/// Parse the keys of a normal or visual mode command.
+fn parse_command(keys: &[VimKey], mode: VimMode) -> Parse<Command> {
+    let mut i = 0;
+    let mut register = None;
+    if keys.first() == Some(&VimKey::Char('"')) {
+        match keys.get(1) {
+            None => return Parse::Incomplete,
+            Some(&VimKey::Char(c)) if c.is_ascii_alphanumeric() || c == '"' || c == '_' => {
+                register = Some(c);
+            }
+            Some(_) => return Parse::Invalid,
+        }
+        i = 2;
+    }
+    let mut count = parse_count(keys, &mut i);
+    let Some(&key) = keys.get(i) else {
+        return Parse::Incomplete;
+    };
+    let rest = &keys[i + 1..];
+    let visual = mode.is_visual();
+
+    let action = match key {
+        VimKey::Char(c @ ('d' | 'c' | 'y')) => {
+            let op = match c {
+                'd' => Operator::Delete,
+                'c' => Operator::Change,
+                _ => Operator::Yank,
+            };
+            if visual {
+                Parse::Done(Action::Operate(op, Target::Selection))
+            } else {
+                let mut j = 0;
+                if let Some(inner) = parse_count(rest, &mut j) {
+                    count = Some(count.unwrap_or(1).saturating_mul(inner));
+                }
+                let rest = &rest[j..];
+                match rest.first() {
+                    None => Parse::Incomplete,
+                    Some(&VimKey::Char(k)) if k == c => {
+                        if rest.len() == 1 {
+                            Parse::Done(Target::Lines)
+                        } else {
+                            Parse::Invalid
+                        }
+                    }
+                    Some(&VimKey::Char(k @ ('i' | 'a'))) => {
+                        parse_object(k == 'a', &rest[1..]).map(Target::Object)
+                    }
+                    Some(_) => parse_motion(rest).map(Target::Motion),
+                }
+                .map(|target| Action::Operate(op, target))
+            }
+        }
+        VimKey::Char(k @ ('i' | 'a')) if visual => {
+            parse_object(k == 'a', rest).map(Action::SelectObject)
+        }
+        VimKey::Char('f' | 'F' | 't' | 'T' | 'g') => parse_motion(&keys[i..]).map(Action::Move),
+        VimKey::Char('r') if !visual => match rest {
+            [] => Parse::Incomplete,
+            [VimKey::Char(c)] => Parse::Done(Action::Replace(*c)),
+            _ => Parse::Invalid,
+        },
+        _ if !rest.is_empty() => Parse::Invalid,
+        _ => match single_key_action(key, mode) {
+            Some(action) => Parse::Done(action),
+            None => parse_motion(&keys[i..]).map(Action::Move),
+        },
+    };
+    action.map(|action| Command {
+        register,
+        count,
+        action,
+    })
+}
+
+/// Get the action for a command that is a single key.
+fn single_key_action(key: VimKey, mode: VimMode) -> Option<Action> {
+    let visual = mode.is_visual();
+    let block = mode == VimMode::VisualBlock;
+    Some(match key {
+        VimKey::Char('i') => Action::Insert(InsertAt::Cursor),
+        VimKey::Char('a') => Action::Insert(InsertAt::After),
+        VimKey::Char('I') if block => Action::BlockInsert { append: false },
+        VimKey::Char('A') if block => Action::BlockInsert { append: true },
+        VimKey::Char('I') => Action::Insert(InsertAt::LineStart),
+        VimKey::Char('A') => Action::Insert(InsertAt::LineEnd),
+        VimKey::Char('o') if visual => Action::SwapEnds,
+        VimKey::Char('o') => Action::Insert(InsertAt::LineBelow),
+        VimKey::Char('O') => Action::Insert(InsertAt::LineAbove),
+        VimKey::Char('v') => Action::Visual(VimMode::Visual),
+        VimKey::Char('V') => Action::Visual(VimMode::VisualLine),
+        VimKey::Ctrl('v') => Action::Visual(VimMode::VisualBlock),
+        VimKey::Char('x' | 'X') if visual => Action::Operate(Operator::Delete, Target::Selection),
+        VimKey::Char('s' | 'S') if visual => Action::Operate(Operator::Change, Target::Selection),
+        VimKey::Char('p') => Action::Put { before: false },
+        VimKey::Char('P') => Action::Put { before: true },
+        VimKey::Char('x') => Action::DeleteChar { before: false },
+        VimKey::Char('X') => Action::DeleteChar { before: true },
+        VimKey::Char('s') => Action::Operate(Operator::Change, Target::Motion(Motion::Right)),
+        VimKey::Char('S') => Action::Operate(Operator::Change, Target::Lines),
+        VimKey::Char('C') => Action::Operate(Operator::Change, Target::Motion(Motion::LineEnd)),
+        VimKey::Char('D') => Action::Operate(Operator::Delete, Target::Motion(Motion::LineEnd)),
+        VimKey::Char('Y') => Action::Operate(Operator::Yank, Target::Lines),
+        VimKey::Char('J') => Action::Join,
+        VimKey::Char('u') if !visual => Action::Undo,
+        VimKey::Ctrl('r') => Action::Redo,
+        VimKey::Char('.') if !visual => Action::Repeat,
+        VimKey::Char(':') => Action::CommandLine,
+        _ => return None,
+    })
+}
+
+/// Parse a count; a leading `0` is the line start motion, not a count.
+fn parse_count(keys: &[VimKey], i: &mut usize) -> Option<usize> {
+    let mut count: Option<usize> = None;
+    while let Some(&VimKey::Char(c)) = keys.get(*i) {
+        let Some(digit) = c.to_digit(10).and_then(|d| usize::try_from(d).ok()) else {
+            break;
+        };
+        if digit == 0 && count.is_none() {
+            break;
+        }
+        count = Some(count.unwrap_or(0).saturating_mul(10).saturating_add(digit));
+        *i += 1;
+    }
+    count
+}
+
+/// Parse a motion that must use every key.
+fn parse_motion(keys: &[VimKey]) -> Parse<Motion> {
+    let Some(&first) = keys.first() else {
+        return Parse::Incomplete;
+    };
+    let (motion, used) = match first {
+        VimKey::Char('h') | VimKey::Left | VimKey::Backspace => (Motion::Left, 1),
+        VimKey::Char('l' | ' ') | VimKey::Right => (Motion::Right, 1),
+        VimKey::Char('j') | VimKey::Down | VimKey::Enter => (Motion::Down, 1),
+        VimKey::Char('k') | VimKey::Up => (Motion::Up, 1),
+        VimKey::Char(c @ ('w' | 'W')) => (Motion::WordStart { big: c == 'W' }, 1),
+        VimKey::Char(c @ ('b' | 'B')) => (Motion::WordBack { big: c == 'B' }, 1),
+        VimKey::Char(c @ ('e' | 'E')) => (Motion::WordEnd { big: c == 'E' }, 1),
+        VimKey::Char('0') => (Motion::LineStart, 1),
+        VimKey::Char('^') => (Motion::FirstNonBlank, 1),
+        VimKey::Char('$') => (Motion::LineEnd, 1),
+        VimKey::Char('G') => (Motion::LastLine, 1),
+        VimKey::Char('%') => (Motion::MatchPair, 1),
+        VimKey::Char(';') => (Motion::RepeatFind { reverse: false }, 1),
+        VimKey::Char(',') => (Motion::RepeatFind { reverse: true }, 1),
+        VimKey::Char('g') => match keys.get(1) {
+            None => return Parse::Incomplete,
+            Some(VimKey::Char('g')) => (Motion::FirstLine, 2),
+            Some(_) => return Parse::Invalid,
+        },
+        VimKey::Char(c @ ('f' | 'F' | 't' | 'T')) => match keys.get(1) {
+            None => return Parse::Incomplete,
+            Some(&VimKey::Char(target)) => {
+                let find = Find {
+                    target,
+                    forward: c.is_lowercase(),
+                    till: matches!(c, 't' | 'T'),
+                };
+                (Motion::Find(find), 2)
+            }
+            Some(_) => return Parse::Invalid,
+        },
+        _ => return Parse::Invalid,
+    };
+    if keys.len() == used {
+        Parse::Done(motion)
+    } else {
+        Parse::Invalid
+    }
+}
+
+/// Parse the key after `i` or `a`.
+fn parse_object(around: bool, keys: &[VimKey]) -> Parse<TextObject> {
+    let c = match keys {
+        [] => return Parse::Incomplete,
+        [VimKey::Char(c)] => *c,
+        _ => return Parse::Invalid,
+    };
+    Parse::Done(match c {
+        'w' | 'W' => TextObject::Word {
+            around,
+            big: c == 'W',
+        },
+        '(' | ')' | 'b' => TextObject::Pair {
+            open: '(',
+            close: ')',
+            around,
+        },
+        '[' | ']' => TextObject::Pair {
+            open: '[',
+            close: ']',
+            around,
+        },
+        '{' | '}' | 'B' => TextObject::Pair {
+            open: '{',
+            close: '}',
+            around,
+        },
+        '<' | '>' => TextObject::Pair {
+            open: '<',
+            close: '>',
+            around,
+        },
+        '"' | '\'' | '`' => TextObject::Quote { quote: c, around },
+        _ => return Parse::Invalid,
+    })
+}
+
+/// Parse the line range at the start of a command line.
+///
+/// Supports `%`, `'<,'>`, `.`, `$` and line numbers, alone or as `a,b`.
+fn parse_range(
+    command: &str,
+    current: usize,
+    last: usize,
+    visual: Option<(usize, usize)>,
+) -> (Option<(usize, usize)>, &str) {
+    if let Some(rest) = command.strip_prefix('%') {
+        return (Some((0, last)), rest);
+    }
+    if let Some(rest) = command.strip_prefix("'<,'>") {
+        return (Some(visual.unwrap_or((current, current))), rest);
+    }
+    let address = |text: &str| -> Option<(usize, usize)> {
+        if text.starts_with('.') {
+            return Some((current, 1));
+        }
+        if text.starts_with('$') {
+            return Some((last, 1));
+        }
+        let digits = text.chars().take_while(char::is_ascii_digit).count();
+        let line: usize = text[..digits].parse().ok()?;
+        Some((line.saturating_sub(1).min(last), digits))
+    };
+    let Some((first, used)) = address(command) else {
+        return (None, command);
+    };
+    let rest = &command[used..];
+    if let Some((second, used)) = rest.strip_prefix(',').and_then(address) {
+        return (
+            Some((first.min(second), first.max(second))),
+            &rest[1 + used..],
+        );
+    }
+    (Some((first, first)), rest)
+}
+
+/// Split `text` at unescaped `delimiter`s, removing the escapes before
+/// delimiters.
+fn split_unescaped(text: &str, delimiter: char) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        let part = parts.last_mut().expect("parts is never empty");
+        if c == '\\' && chars.peek() == Some(&delimiter) {
+            part.push(delimiter);
+            chars.next();
+        } else if c == '\\' {
+            part.push(c);
+            if let Some(next) = chars.next() {
+                part.push(next);
+            }
+        } else if c == delimiter {
+            parts.push(String::new());
+        } else {
+            part.push(c);
+        }
+    }
+    parts
+}
+
+/// Walks through the document one char at a time, with a `'\n'` at the
+/// end of every line.
+struct Walker<'a> {
+    buffer: &'a TextBuffer,
+    line: usize,
+    column: usize,
+    chars: Vec<char>,
+}
+
+impl<'a> Walker<'a> {
+    fn new(buffer: &'a TextBuffer, position: CursorPosition) -> Self {
+        let line = position.line.min(buffer.line_count() - 1);
+        let chars = line_chars(buffer, line);
+        Self {
+            buffer,
+            line,
+            column: position.column.min(chars.len()),
+            chars,
+        }
+    }
+
+    fn position(&self) -> CursorPosition {
+        CursorPosition::new(self.line, self.column)
+    }
+
+    fn char(&self) -> char {
+        self.chars.get(self.column).copied().unwrap_or('\n')
+    }
+
+    fn is_empty_line(&self) -> bool {
+        self.chars.is_empty()
+    }
+
+    fn peek_next(&self) -> Option<char> {
+        self.chars.get(self.column + 1).copied()
+    }
+
+    fn peek_prev(&self) -> Option<char> {
+        self.column
+            .checked_sub(1)
+            .and_then(|column| self.chars.get(column))
+            .copied()
+    }
+
+    /// Step forward; returns `false` at the end of the document.
+    fn next(&mut self) -> bool {
+        if self.column < self.chars.len() {
+            self.column += 1;
+        } else if self.line + 1 < self.buffer.line_count() {
+            self.line += 1;
+            self.chars = line_chars(self.buffer, self.line);
+            self.column = 0;
+        } else {
+            return false;
+        }
+        true
+    }
+
+    /// Step back; returns `false` at the start of the document.
+    fn prev(&mut self) -> bool {
+        if self.column > 0 {
+            self.column -= 1;
+        } else if self.line > 0 {
+            self.line -= 1;
+            self.chars = line_chars(self.buffer, self.line);
+            self.column = self.chars.len();
+        } else {
+            return false;
+        }
+        true
+    }
+}
+
+/// The kind of character a word motion treats as one run; `big` words
+/// only break at whitespace.
+fn char_class(c: char, big: bool) -> u8 {
+    if c.is_whitespace() {
+        0
+    } else if big || is_word_char(c) {
+        1
+    } else {
+        2
+    }
+}
+
+/// Move to the start of the next word (`w`). Empty lines count as words.
+fn word_forward(walker: &mut Walker<'_>, big: bool) {
+    let c = walker.char();
+    if !c.is_whitespace() {
+        let class = char_class(c, big);
+        while walker.next() && char_class(walker.char(), big) == class {}
+    }
+    while walker.char().is_whitespace() {
+        let newline = walker.char() == '\n';
+        if !walker.next() || (newline && walker.is_empty_line()) {
+            return;
+        }
+    }
+}
+
+/// Move to the start of the previous word (`b`).
+fn word_backward(walker: &mut Walker<'_>, big: bool) {
+    if !walker.prev() {
+        return;
+    }
+    while walker.char().is_whitespace() {
+        if walker.is_empty_line() || !walker.prev() {
+            return;
+        }
+    }
+    let class = char_class(walker.char(), big);
+    while walker
+        .peek_prev()
+        .is_some_and(|c| char_class(c, big) == class)
+    {
+        walker.prev();
+    }
+}
+
+/// Move to the end of the next word (`e`).
+fn word_end(walker: &mut Walker<'_>, big: bool) {
+    if !walker.next() {
+        return;
+    }
+    while walker.char().is_whitespace() {
+        if !walker.next() {
+            return;
+        }
+    }
+    let class = char_class(walker.char(), big);
+    while walker
+        .peek_next()
+        .is_some_and(|c| char_class(c, big) == class)
+    {
+        walker.next();
+    }
+}
+
+/// Find the `n`th occurrence of a character on the cursor's line.
+///
+/// `repeat` is set for `;` and `,`, which skip a match right next to the
+/// cursor after `t` or `T`.
+fn find_char(
+    buffer: &TextBuffer,
+    head: CursorPosition,
+    find: Find,
+    n: usize,
+    repeat: bool,
+) -> Option<(CursorPosition, Span)> {
+    let chars = line_chars(buffer, head.line);
+    let mut column = head.column;
+    for i in 0..n {
+        let skip = usize::from(find.till && (i > 0 || repeat));
+        column = if find.forward {
+            let from = column + 1 + skip;
+            let found = from + chars.get(from..)?.iter().position(|&c| c == find.target)?;
+            if find.till { found - 1 } else { found }
+        } else {
+            let to = column.checked_sub(skip)?;
+            let found = chars[..to.min(chars.len())]
+                .iter()
+                .rposition(|&c| c == find.target)?;
+            if find.till { found + 1 } else { found }
+        };
+    }
+    let span = if find.forward {
+        Span::Inclusive
+    } else {
+        Span::Exclusive
+    };
+    Some((CursorPosition::new(head.line, column), span))
+}
+
+/// Find the bracket matching the first bracket at or after the cursor on
+/// its line (`%`).
+fn match_pair(state: &EditorState, head: CursorPosition) -> Option<CursorPosition> {
+    let buffer = &state.buffer;
+    let chars = line_chars(buffer, head.line);
+    let column = head.column
+        + chars.get(head.column..)?.iter().position(|c| {
+            BRACKET_PAIRS
+                .iter()
+                .any(|&(open, close)| *c == open || *c == close)
+        })?;
+    let offset = offset_of(buffer, CursorPosition::new(head.line, column));
+    let partner = brackets::find_partner(buffer, state.language_kind(), offset)?;
+    Some(position_of(buffer, partner))
+}
+
+/// Find the unmatched `close` after the walker.
+fn find_close(walker: &mut Walker<'_>, open: char, close: char) -> Option<CursorPosition> {
+    let mut depth = 0usize;
+    while walker.next() {
+        match walker.char() {
+            c if c == open => depth += 1,
+            c if c == close => {
+                if depth == 0 {
+                    return Some(walker.position());
+                }
+                depth -= 1;
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Find the unmatched `open` before the walker.
+fn find_open(walker: &mut Walker<'_>, open: char, close: char) -> Option<CursorPosition> {
+    let mut depth = 0usize;
+    while walker.prev() {
+        match walker.char() {
+            c if c == close => depth += 1,
+            c if c == open => {
+                if depth == 0 {
+                    return Some(walker.position());
+                }
+                depth -= 1;
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Get the start and (exclusive) end of a text object around a position.
+fn text_object(
+    buffer: &TextBuffer,
+    head: CursorPosition,
+    object: TextObject,
+) -> Option<(CursorPosition, CursorPosition)> {
+    match object {
+        TextObject::Word { around, big } => {
+            let chars = line_chars(buffer, head.line);
+            let column = head.column.min(chars.len().checked_sub(1)?);
+            let class = char_class(chars[column], big);
+            let same = |c: char| char_class(c, big) == class;
+            let mut start = column;
+            while start > 0 && same(chars[start - 1]) {
+                start -= 1;
+            }
+            let mut end = column + 1;
+            while end < chars.len() && same(chars[end]) {
+                end += 1;
+            }
+            if around {
+                // Take the whitespace after the word, or before it if there
+                // is none; on whitespace, take the word after it
+                let trailing = end;
+                if class == 0 {
+                    let next = chars.get(end).map(|&c| char_class(c, big));
+                    while end < chars.len() && Some(char_class(chars[end], big)) == next {
+                        end += 1;
+                    }
+                } else {
+                    while end < chars.len() && chars[end].is_whitespace() {
+                        end += 1;
+                    }
+                }
+                if end == trailing {
+                    while start > 0 && chars[start - 1].is_whitespace() {
+                        start -= 1;
+                    }
+                }
+            }
+            Some((
+                CursorPosition::new(head.line, start),
+                CursorPosition::new(head.line, end),
+            ))
+        }
+        TextObject::Pair {
+            open,
+            close,
+            around,
+        } => {
+            let mut walker = Walker::new(buffer, head);
+            let start = if walker.char() == open {
+                walker.position()
+            } else {
+                find_open(&mut walker, open, close)?
+            };
+            let end = find_close(&mut Walker::new(buffer, start), open, close)?;
+            if around {
+                return Some((start, CursorPosition::new(end.line, end.column + 1)));
+            }
+            let mut inner_start = CursorPosition::new(start.line, start.column + 1);
+            let mut inner_end = end;
+            // A multi-line block keeps the lines with its brackets
+            if inner_start.column == line_len(buffer, start.line) && start.line < end.line {
+                inner_start = CursorPosition::new(start.line + 1, 0);
+            }
+            if inner_start.line < end.line
+                && line_chars(buffer, end.line)[..end.column]
+                    .iter()
+                    .all(|c| c.is_whitespace())
+            {
+                inner_end = CursorPosition::new(end.line, 0);
+            }
+            Some((inner_start, inner_end))
+        }
+        TextObject::Quote { quote, around } => {
+            let chars = line_chars(buffer, head.line);
+            let quotes: Vec<usize> = chars
+                .iter()
+                .enumerate()
+                .filter(|&(i, &c)| c == quote && (i == 0 || chars[i - 1] != '\\'))
+                .map(|(i, _)| i)
+                .collect();
+            let (start, end) = quotes
+                .chunks_exact(2)
+                .map(|pair| (pair[0], pair[1]))
+                .find(|&(_, end)| head.column <= end)?;
+            let (start, end) = if around {
+                (start, end + 1)
+            } else {
+                (start + 1, end)
+            };
+            Some((
+                CursorPosition::new(head.line, start),
+                CursorPosition::new(head.line, end),
+            ))
+        }
+    }
+}
+
+/// Replace `count` characters from the cursor with `c` (`r`).
+fn replace_chars(state: &mut EditorState, c: char, count: usize) {
+    let head = state.cursor_position();
+    if head.column + count > line_len(&state.buffer, head.line) {
+        return;
+    }
+    let start = offset_of(&state.buffer, head);
+    let end = offset_of(
+        &state.buffer,
+        CursorPosition::new(head.line, head.column + count),
+    );
+    replace(state, start..end, &c.to_string().repeat(count));
+    place(
+        state,
+        CursorPosition::new(head.line, head.column + count - 1),
+    );
+}
+
+/// Join `count` lines starting at the cursor (`J`).
+///
+/// Leading whitespace on the joined lines becomes a single space, except
+/// before `)` or after an empty line.
+fn join_lines(state: &mut EditorState, count: usize) {
+    let head = state.cursor_position();
+    let buffer = &state.buffer;
+    let last = (head.line + count - 1).min(buffer.line_count() - 1);
+    if last == head.line {
+        return;
+    }
+
+    let mut edits = Vec::new();
+    for line in head.line..last {
+        let Some(end) = buffer.line_range(line).map(|range| range.end) else {
+            break;
+        };
+        let next = line_chars(buffer, line + 1);
+        let indent = next.iter().take_while(|c| c.is_whitespace()).count();
+        let next_start = offset_of(buffer, CursorPosition::new(line + 1, indent));
+        let ends_blank = buffer
+            .char_at(buffer.prev_char_boundary(end))
+            .is_none_or(char::is_whitespace)
+            || line_len(buffer, line) == 0;
+        let separator = if ends_blank || indent == next.len() || next[indent] == ')' {
+            ""
+        } else {
+            " "
+        };
+        edits.push((end..next_start, separator));
+    }
+    let column = line_len(buffer, head.line);
+    let changes = ChangeSet::from_edits(buffer.len(), edits);
+    if state.apply(&Transaction::new(changes)) {
+        place(state, CursorPosition::new(head.line, column));
+    }
+}
+
+/// Move a cursor to the target of a motion, keeping the column for
+/// vertical motions; `$` sticks to the end of the line.
+fn moved_cursor(cursor: &Cursor, target: CursorPosition, motion: Motion) -> Cursor {
+    let mut moved = Cursor::new(target);
+    match motion {
+        Motion::Up | Motion::Down => {
+            moved.set_preferred_column(cursor.preferred_column.unwrap_or(cursor.head.column));
+        }
+        Motion::LineEnd => moved.set_preferred_column(usize::MAX),
+        _ => {}
+    }
+    moved
+}
+
+/// Replace a byte range in one transaction.
+fn replace(state: &mut EditorState, range: Range<usize>, text: &str) -> bool {
+    let changes = ChangeSet::replace(state.buffer.len(), range, text);
+    state.apply(&Transaction::new(changes))
+}
+
+/// Put a single cursor at a position.
+fn place(state: &mut EditorState, position: CursorPosition) {
+    state.cursors = CursorSet::new(Cursor::new(position));
+}
+
+/// Keep a single cursor on a character, as normal mode does: the cursor
+/// cannot sit after the last character of a line.
+fn clamp_normal(state: &mut EditorState) {
+    let cursor = *state.cursors.primary();
+    let head = state.clamp_position(cursor.head);
+    let len = line_len(&state.buffer, head.line);
+    let mut clamped = Cursor::new(CursorPosition::new(
+        head.line,
+        head.column.min(len.saturating_sub(1)),
+    ));
+    clamped.preferred_column = cursor.preferred_column;
+    state.cursors = CursorSet::new(clamped);
+}
+
+fn line_len(buffer: &TextBuffer, line: usize) -> usize {
+    buffer.line_len_chars(line).unwrap_or(0)
+}
+
+fn line_chars(buffer: &TextBuffer, line: usize) -> Vec<char> {
+    buffer.line(line).unwrap_or_default().chars().collect()
+}
+
+fn indent_of(buffer: &TextBuffer, line: usize) -> String {
+    line_chars(buffer, line)
+        .into_iter()
+        .take_while(|c| *c == ' ' || *c == '\t')
+        .collect()
+}
+
+fn first_non_blank(buffer: &TextBuffer, line: usize) -> CursorPosition {
+    CursorPosition::new(line, indent_of(buffer, line).chars().count())
+}
+
+/// Convert a position to a byte offset, clamping it to the document.
+fn offset_of(buffer: &TextBuffer, position: CursorPosition) -> usize {
+    let line = position.line.min(buffer.line_count() - 1);
+    let column = position.column.min(line_len(buffer, line));
+    buffer
+        .line_col_to_offset(line, column)
+        .unwrap_or(buffer.len())
+}
+
+fn position_of(buffer: &TextBuffer, offset: usize) -> CursorPosition {
+    buffer
+        .offset_to_line_col(offset.min(buffer.len()))
+        .map(|(line, column)| CursorPosition::new(line, column))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feed keys, typing into the state when insert mode ignores them.
+    fn feed(vim: &mut Vim, state: &mut EditorState, keys: &str) -> Vec<VimResponse> {
+        VimKey::parse_keys(keys)
+            .into_iter()
+            .map(|key| {
+                let response = vim.handle_key(key, state);
+                match (response, key) {
+                    (VimResponse::Ignored, VimKey::Char(c)) => state.insert(&c.to_string()),
+                    (VimResponse::Ignored, VimKey::Enter) => state.insert("\n"),
+                    _ => {}
+                }
+                response
+            })
+            .collect()
+    }
+
+    fn head(state: &EditorState) -> (usize, usize) {
+        let head = state.cursor_position();
+        (head.line, head.column)
+    }
+
+    #[test]
+    fn test_motions_and_counts() {
+        let mut vim = Vim::new();
+        let mut state = EditorState::new("fn main(a, b) {\n    call(x);\n}\n\nlast word");
+
+        feed(&mut vim, &mut state, "w");
+        assert_eq!(head(&state), (0, 3));
+        feed(&mut vim, &mut state, "2e");
+        assert_eq!(head(&state), (0, 7));
+        feed(&mut vim, &mut state, "$");
+        assert_eq!(head(&state), (0, 14));
+        feed(&mut vim, &mut state, "0fb");
+        assert_eq!(head(&state), (0, 11));
+        feed(&mut vim, &mut state, "0t(");
+        assert_eq!(head(&state), (0, 6));
+        feed(&mut vim, &mut state, "$%");
+        assert_eq!(head(&state), (2, 0));
+        feed(&mut vim, &mut state, "%");
+        assert_eq!(head(&state), (0, 14));
+        feed(&mut vim, &mut state, "G");
+        assert_eq!(head(&state), (4, 0));
+        feed(&mut vim, &mut state, "2gg");
+        assert_eq!(head(&state), (1, 4));
+        feed(&mut vim, &mut state, "5w");
+        assert_eq!(head(&state), (3, 0));
+        feed(&mut vim, &mut state, "b");
+        assert_eq!(head(&state), (2, 0));
+    }
+
+    #[test]
+    fn test_operators_text_objects_and_registers() {
+        let mut vim = Vim::new();
+        let mut state = EditorState::new("one two three\ncall(a, b) \"quoted text\"");
+
+        feed(&mut vim, &mut state, "dw");
+        assert_eq!(state.content(), "two three\ncall(a, b) \"quoted text\"");
+        feed(&mut vim, &mut state, "cwfour<Esc>");
+        assert_eq!(state.content(), "four three\ncall(a, b) \"quoted text\"");
+        assert_eq!(vim.mode(), VimMode::Normal);
+
+        feed(&mut vim, &mut state, "jfaci(x<Esc>");
+        assert_eq!(state.content(), "four three\ncall(x) \"quoted text\"");
+        feed(&mut vim, &mut state, "$di\"");
+        assert_eq!(state.content(), "four three\ncall(x) \"\"");
+        assert_eq!(vim.register('"').unwrap().text, "quoted text");
+
+        feed(&mut vim, &mut state, "gg\"ayiwjP");
+        assert_eq!(vim.register('a').unwrap().text, "four");
+        assert_eq!(state.content(), "four three\nfourcall(x) \"\"");
+
+        feed(&mut vim, &mut state, "ddkp");
+        assert_eq!(state.content(), "four three\nfourcall(x) \"\"");
+        feed(&mut vim, &mut state, "\"byyG\"bp");
+        assert_eq!(
+            state.content(),
+            "four three\nfourcall(x) \"\"\nfourcall(x) \"\""
+        );
+        assert_eq!(vim.register('b').unwrap().kind, RegisterKind::Line);
+        assert_eq!(head(&state), (2, 0));
+    }
+
+    #[test]
+    fn test_dot_repeat_and_undo() {
+        let mut vim = Vim::new();
+        let mut state = EditorState::new("a b c d\nx y z");
+
+        feed(&mut vim, &mut state, "dw..");
+        assert_eq!(state.content(), "d\nx y z");
+        feed(&mut vim, &mut state, "jciwnew<Esc>w.");
+        assert_eq!(state.content(), "d\nnew new z");
+        feed(&mut vim, &mut state, "3ix<Esc>");
+        assert_eq!(state.content(), "d\nnew nexxxw z");
+
+        feed(&mut vim, &mut state, "u");
+        assert_eq!(state.content(), "d\nnew new z");
+        feed(&mut vim, &mut state, "uu");
+        assert_eq!(state.content(), "d\nx y z");
+        feed(&mut vim, &mut state, "<C-r>");
+        assert_eq!(state.content(), "d\nnew y z");
+    }
+
+    #[test]
+    fn test_visual_modes_and_command_line() {
+        let mut vim = Vim::new();
+        let mut state = EditorState::new("foo bar\nbaz foo\nqux\nfoo foo");
+
+        feed(&mut vim, &mut state, "vey");
+        assert_eq!(vim.register('0').unwrap().text, "foo");
+        feed(&mut vim, &mut state, "jVjd");
+        assert_eq!(state.content(), "foo bar\nfoo foo");
+        assert_eq!(vim.mode(), VimMode::Normal);
+
+        feed(&mut vim, &mut state, "gg<C-v>jld");
+        assert_eq!(state.content(), "o bar\no foo");
+        assert_eq!(vim.register('"').unwrap().kind, RegisterKind::Block);
+
+        feed(&mut vim, &mut state, ":%s/o/0/g<CR>");
+        assert_eq!(state.content(), "0 bar\n0 f00");
+        feed(&mut vim, &mut state, "gg:s/bar/baz<CR>");
+        assert_eq!(state.content(), "0 baz\n0 f00");
+        feed(&mut vim, &mut state, ":s/nope/x<CR>");
+        assert_eq!(vim.status(), "Pattern not found: nope");
+
+        let responses = feed(&mut vim, &mut state, ":w<CR>");
+        assert_eq!(responses.last(), Some(&VimResponse::Save));
+        feed(&mut vim, &mut state, ":2");
+        assert_eq!(vim.status(), ":2");
+        feed(&mut vim, &mut state, "<CR>");
+        assert_eq!(vim.status(), "-- NORMAL --");
+        assert_eq!(head(&state), (1, 0));
+    }
+}
@@ -36,6 +36,29 @@ pub fn is_browser() -> bool {
     }
 }
 
+/// Check if the browser is running on macOS or iOS.
+///
+/// Used to pick Cmd rather than Ctrl as the main shortcut modifier.
+/// Always `false` outside the browser.
+#[cfg(feature = "editor")]
+#[must_use]
+pub fn is_mac() -> bool {
+    #[cfg(target_arch = "wasm32")]
+    {
+        web_sys::window()
+            .and_then(|w| w.navigator().user_agent().ok())
+            .is_some_and(|agent| {
+                ["Mac", "iPhone", "iPad"]
+                    .iter()
+                    .any(|name| agent.contains(name))
+            })
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        false
+    }
+}
+
 /// Safely execute code only in browser context.
 ///
 /// This is useful for operations that should only run on the client,
@@ -10,7 +10,11 @@ mod text;
 
 // Internal re-exports (crate-visible)
 // Note: These are available for use via dom:: prefix
+#[cfg(feature = "editor")]
+pub(crate) use dom::is_mac;
 #[allow(unused_imports)]
 pub(crate) use dom::{get_document, is_browser, on_browser};
 // Public re-exports (for users who need these utilities)
-pub use text::{count_lines, line_range, offset_to_position, position_to_offset, text_stats};
+pub use text::{
+    LineEnding, count_lines, line_range, offset_to_position, position_to_offset, text_stats,
+};
@@ -1,10 +1,19 @@
 //! Text processing utilities
 //!
 //! Provides efficient text analysis and manipulation functions.
+//!
+//! The line and position helpers scan the text from the start on every
+//! call. The editor keeps its text in a rope instead, so `EditorState` and
+//! `TextBuffer` look lines up in O(log n) and are the fast path for
+//! repeated lookups on a large document.
+
+use std::{borrow::Cow, fmt};
 
 /// Count the number of lines in a string.
 ///
 /// Returns at least 1 for an empty string (representing a single empty line).
+/// Only `\n` ends a line, as in the other helpers here; use
+/// [`LineEnding::normalize`] first for text with other line endings.
 ///
 /// # Examples
 ///
@@ -24,6 +33,94 @@ pub fn count_lines(text: &str) -> usize {
     }
 }
 
+/// The character sequence that ends a line.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum LineEnding {
+    /// `\n` (Unix, macOS)
+    #[default]
+    Lf,
+    /// `\r\n` (Windows)
+    CrLf,
+    /// `\r` (classic Mac OS)
+    Cr,
+}
+
+impl LineEnding {
+    /// Get the line ending's characters.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Lf => "\n",
+            Self::CrLf => "\r\n",
+            Self::Cr => "\r",
+        }
+    }
+
+    /// Find the line ending used most in `text`.
+    ///
+    /// Returns `None` if the text has no line breaks. Ties go to LF, then
+    /// CRLF.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use longcipher_leptos_components::helpers::LineEnding;
+    ///
+    /// assert_eq!(LineEnding::detect("a\r\nb\r\nc\n"), Some(LineEnding::CrLf));
+    /// assert_eq!(LineEnding::detect("abc"), None);
+    /// ```
+    #[must_use]
+    pub fn detect(text: &str) -> Option<Self> {
+        let (mut lf, mut crlf, mut cr) = (0, 0, 0);
+        let mut bytes = text.bytes().peekable();
+        while let Some(b) = bytes.next() {
+            match b {
+                b'\n' => lf += 1,
+                b'\r' if bytes.next_if_eq(&b'\n').is_some() => crlf += 1,
+                b'\r' => cr += 1,
+                _ => {}
+            }
+        }
+        match lf.max(crlf).max(cr) {
+            0 => None,
+            max if lf == max => Some(Self::Lf),
+            max if crlf == max => Some(Self::CrLf),
+            _ => Some(Self::Cr),
+        }
+    }
+
+    /// Convert every line ending in `text` to `\n`.
+    #[must_use]
+    pub fn normalize(text: &str) -> Cow<'_, str> {
+        if text.contains('\r') {
+            Cow::Owned(text.replace("\r\n", "\n").replace('\r', "\n"))
+        } else {
+            Cow::Borrowed(text)
+        }
+    }
+
+    /// Convert the `\n` line endings of normalized text to this line
+    /// ending.
+    #[must_use]
+    pub fn apply(self, text: &str) -> Cow<'_, str> {
+        match self {
+            Self::Lf => Cow::Borrowed(text),
+            _ if !text.contains('\n') => Cow::Borrowed(text),
+            _ => Cow::Owned(text.replace('\n', self.as_str())),
+        }
+    }
+}
+
+impl fmt::Display for LineEnding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Lf => "LF",
+            Self::CrLf => "CRLF",
+            Self::Cr => "CR",
+        })
+    }
+}
+
 /// Calculate basic text statistics.
 ///
 /// Returns a tuple of (words, characters, `characters_no_spaces`, lines).
@@ -61,7 +158,8 @@ pub fn text_stats(text: &str) -> (usize, usize, usize, usize) {
 
 /// Get the line and column position from a character offset.
 ///
-/// Both line and column are 0-indexed.
+/// Both line and column are 0-indexed. Offsets and columns count chars
+/// (Unicode scalar values), not bytes.
 #[must_use]
 #[allow(clippy::explicit_counter_loop)]
 pub fn offset_to_position(text: &str, offset: usize) -> (usize, usize) {
@@ -122,7 +220,7 @@ pub fn position_to_offset(text: &str, line: usize, col: usize) -> Option<usize>
     None
 }
 
-/// Get the start and end offsets of a specific line (0-indexed).
+/// Get the start and end char offsets of a specific line (0-indexed).
 ///
 /// Returns `(start, end)` where `end` is exclusive.
 #[must_use]
@@ -163,6 +261,25 @@ mod tests {
         assert_eq!(count_lines("hello\nworld"), 2);
         assert_eq!(count_lines("a\nb\nc"), 3);
         assert_eq!(count_lines("\n\n\n"), 4);
+
+        // Other line endings agree with the other helpers once normalized
+        let text = LineEnding::normalize("a\r\nb\rc");
+        assert_eq!(count_lines(&text), 3);
+        assert_eq!(line_range(&text, 2), Some((4, 5)));
+        assert_eq!(offset_to_position(&text, 4), (2, 0));
+        assert_eq!(position_to_offset(&text, 2, 0), Some(4));
+    }
+
+    #[test]
+    fn test_line_ending_round_trip() {
+        let text = "one\r\ntwo\r\nthree";
+        let ending = LineEnding::detect(text).unwrap();
+        assert_eq!(ending, LineEnding::CrLf);
+        let normalized = LineEnding::normalize(text);
+        assert_eq!(normalized, "one\ntwo\nthree");
+        assert_eq!(ending.apply(&normalized), text);
+        assert_eq!(LineEnding::normalize("a\rb\r\nc"), "a\nb\nc");
+        assert_eq!(LineEnding::detect("a\rb\rc\n"), Some(LineEnding::Cr));
     }
 
     #[test]